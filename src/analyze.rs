@@ -0,0 +1,393 @@
+use std::{fmt, time::Duration};
+use anyhow::Result;
+use regex::Regex;
+
+use super::{Args, MigrationFile, gather_validated_migrations, is_pending, query_actual_version};
+use super::{connections, read_sql_file};
+use super::statements::split_statements;
+
+/// the table level lock modes of postgres, from weakest to strongest
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Lock {
+	RowExclusive,
+	ShareUpdateExclusive,
+	Share,
+	ShareRowExclusive,
+	Exclusive,
+	AccessExclusive,
+}
+
+impl Lock {
+	fn blocks(&self) -> &'static str {
+		match self {
+			Lock::RowExclusive | Lock::ShareUpdateExclusive => "blocks only other ddl",
+			Lock::Share | Lock::ShareRowExclusive | Lock::Exclusive => "blocks writes",
+			Lock::AccessExclusive => "blocks reads and writes",
+		}
+	}
+}
+
+impl fmt::Display for Lock {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Lock::RowExclusive => "ROW EXCLUSIVE",
+			Lock::ShareUpdateExclusive => "SHARE UPDATE EXCLUSIVE",
+			Lock::Share => "SHARE",
+			Lock::ShareRowExclusive => "SHARE ROW EXCLUSIVE",
+			Lock::Exclusive => "EXCLUSIVE",
+			Lock::AccessExclusive => "ACCESS EXCLUSIVE",
+		})
+	}
+}
+
+/// work a statement does proportional to the size of the table, while holding its lock
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Effect {
+	Scan,
+	IndexBuild,
+	Rewrite,
+}
+
+impl Effect {
+	/// a rough estimate, assuming the table is read or written at `bytes_per_second`
+	/// and that sorting for an index build costs about as much as reading the table again
+	fn estimate(&self, stats: &RelationStats, bytes_per_second: u64) -> Duration {
+		let bytes = match self {
+			Effect::Scan => stats.table_bytes,
+			Effect::IndexBuild => stats.table_bytes * 2,
+			// the new table is written along with all of its indexes, after the old one is read
+			Effect::Rewrite => stats.table_bytes + stats.bytes,
+		};
+		Duration::from_secs_f64(bytes.max(0) as f64 / bytes_per_second.max(1) as f64)
+	}
+}
+
+impl fmt::Display for Effect {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Effect::Scan => "scans the whole table",
+			Effect::IndexBuild => "builds an index",
+			Effect::Rewrite => "rewrites the whole table",
+		})
+	}
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct StatementAnalysis {
+	lock: Lock,
+	effect: Option<Effect>,
+	relations: Vec<String>,
+}
+
+const RELATION: &str = r#"((?:"[^"]+"|[\w$]+)(?:\.(?:"[^"]+"|[\w$]+))?)"#;
+
+/// `"Fruit"` stays case sensitive, `Fruit` is folded like postgres folds it
+fn normalize_relation(relation: &str) -> String {
+	relation.split('.')
+		.map(|part| match part.strip_prefix('"').and_then(|part| part.strip_suffix('"')) {
+			Some(quoted) => quoted.to_string(),
+			None => part.to_lowercase(),
+		})
+		.collect::<Vec<_>>()
+		.join(".")
+}
+
+fn capture_relation(pattern: &str, sql: &str) -> Option<(String, String)> {
+	let captures = Regex::new(&format!("(?is){pattern}")).unwrap().captures(sql)?;
+	let relation = normalize_relation(&captures[1]);
+	let rest = captures.get(2).map_or("", |rest| rest.as_str()).to_string();
+	Some((relation, rest))
+}
+
+fn matches(pattern: &str, sql: &str) -> bool {
+	Regex::new(&format!("(?is){pattern}")).unwrap().is_match(sql)
+}
+
+/// the strongest lock and most expensive effect of the subcommands of an `alter table`
+fn analyze_alter_table(actions: &str) -> (Lock, Option<Effect>) {
+	let volatile_default = r"\bdefault\s+[^,]*\b(random|gen_random_uuid|uuid_generate_v[14]|clock_timestamp|timeofday|nextval)\s*\(";
+	let generated = r"\b(big|small)?serial\b|\bgenerated\s+(always|by\s+default)\s+as\s+(identity|\(.*\)\s*stored)";
+	let add_constraint = r"\badd\s+(constraint\s+\S+\s+)?";
+
+	let mut found = vec![];
+	if matches(&format!(r"{add_constraint}foreign\s+key"), actions) {
+		found.push((Lock::ShareRowExclusive, (!matches(r"\bnot\s+valid\b", actions)).then_some(Effect::Scan)));
+	}
+	if matches(&format!(r"{add_constraint}check\b"), actions) {
+		found.push((Lock::AccessExclusive, (!matches(r"\bnot\s+valid\b", actions)).then_some(Effect::Scan)));
+	}
+	if matches(&format!(r"{add_constraint}(primary\s+key|unique|exclude)\b"), actions) {
+		found.push((Lock::AccessExclusive, (!matches(r"\busing\s+index\b", actions)).then_some(Effect::IndexBuild)));
+	}
+	let adds_column = Regex::new(r#"(?i)\badd\s+(?:column\s+(?:if\s+not\s+exists\s+)?)?(\w+|"[^"]+")"#).unwrap()
+		.captures_iter(actions)
+		.any(|captures| !["constraint", "primary", "unique", "check", "foreign", "exclude"].contains(&captures[1].to_lowercase().as_str()));
+	if adds_column {
+		let rewrites = matches(volatile_default, actions) || matches(generated, actions);
+		found.push((Lock::AccessExclusive, rewrites.then_some(Effect::Rewrite)));
+	}
+	if matches(r"\bvalidate\s+constraint\b", actions) {
+		found.push((Lock::ShareUpdateExclusive, Some(Effect::Scan)));
+	}
+	if matches(r"\balter\s+(column\s+)?\S+\s+(set\s+data\s+)?type\b", actions) || matches(r"\bset\s+(tablespace|logged|unlogged)\b", actions) {
+		found.push((Lock::AccessExclusive, Some(Effect::Rewrite)));
+	}
+	if matches(r"\bset\s+not\s+null\b", actions) {
+		found.push((Lock::AccessExclusive, Some(Effect::Scan)));
+	}
+	if matches(r"\battach\s+partition\b", actions) {
+		found.push((Lock::ShareUpdateExclusive, Some(Effect::Scan)));
+	}
+	if matches(r"\bdetach\s+partition\b.*\bconcurrently\b", actions) || matches(r"\bset\s*\(", actions) {
+		found.push((Lock::ShareUpdateExclusive, None));
+	}
+	if matches(r"\b(enable|disable)\s+trigger\b", actions) {
+		found.push((Lock::ShareRowExclusive, None));
+	}
+
+	// everything else alter table can do, like dropping columns or renaming, takes an ACCESS EXCLUSIVE lock
+	found.into_iter()
+		.reduce(|(lock, effect), (other_lock, other_effect)| (lock.max(other_lock), effect.max(other_effect)))
+		.unwrap_or((Lock::AccessExclusive, None))
+}
+
+/// the lock a statement takes on existing relations, and what it does to them,
+/// or none if it doesn't lock any existing relation in a way that matters
+fn analyze_statement(sql: &str) -> Option<StatementAnalysis> {
+	let analysis = |lock, effect, relations| Some(StatementAnalysis{ lock, effect, relations });
+
+	if let Some((relation, _)) = capture_relation(&format!(r"^create\s+(?:unique\s+)?index\s+concurrently\s+.*?\bon\s+(?:only\s+)?{RELATION}"), sql) {
+		return analysis(Lock::ShareUpdateExclusive, Some(Effect::IndexBuild), vec![relation]);
+	}
+	if let Some((relation, _)) = capture_relation(&format!(r"^create\s+(?:unique\s+)?index\s+.*?\bon\s+(?:only\s+)?{RELATION}"), sql) {
+		return analysis(Lock::Share, Some(Effect::IndexBuild), vec![relation]);
+	}
+	if let Some((relation, _)) = capture_relation(&format!(r"^reindex\s+(?:\(.*?\)\s*)?(?:index|table)\s+concurrently\s+{RELATION}"), sql) {
+		return analysis(Lock::ShareUpdateExclusive, Some(Effect::IndexBuild), vec![relation]);
+	}
+	if let Some((relation, _)) = capture_relation(&format!(r"^reindex\s+(?:\(.*?\)\s*)?(?:index|table)\s+{RELATION}"), sql) {
+		return analysis(Lock::Share, Some(Effect::IndexBuild), vec![relation]);
+	}
+	if let Some((relation, _)) = capture_relation(&format!(r"^drop\s+index\s+concurrently\s+(?:if\s+exists\s+)?{RELATION}"), sql) {
+		return analysis(Lock::ShareUpdateExclusive, None, vec![relation]);
+	}
+	if let Some((relation, _)) = capture_relation(&format!(r"^drop\s+index\s+(?:if\s+exists\s+)?{RELATION}"), sql) {
+		return analysis(Lock::AccessExclusive, None, vec![relation]);
+	}
+	if let Some((relation, actions)) = capture_relation(&format!(r"^alter\s+table\s+(?:if\s+exists\s+)?(?:only\s+)?{RELATION}\s+(.*)$"), sql) {
+		let (lock, effect) = analyze_alter_table(&actions);
+		return analysis(lock, effect, vec![relation]);
+	}
+	if let Some(captures) = Regex::new(r"(?is)^(?:drop\s+table\s+(?:if\s+exists\s+)?|truncate\s+(?:table\s+)?(?:only\s+)?)(.*?)(?:\s+(?:cascade|restrict|restart\s+identity|continue\s+identity))*\s*$").unwrap().captures(sql) {
+		let relations = captures[1].split(',').map(|relation| normalize_relation(relation.trim())).collect();
+		return analysis(Lock::AccessExclusive, None, relations);
+	}
+	if let Some((relation, _)) = capture_relation(&format!(r"^create\s+(?:or\s+replace\s+)?(?:constraint\s+)?trigger\s+.*?\bon\s+{RELATION}"), sql) {
+		return analysis(Lock::ShareRowExclusive, None, vec![relation]);
+	}
+	if let Some((relation, _)) = capture_relation(&format!(r"^refresh\s+materialized\s+view\s+concurrently\s+{RELATION}"), sql) {
+		return analysis(Lock::Exclusive, Some(Effect::Scan), vec![relation]);
+	}
+	if let Some((relation, _)) = capture_relation(&format!(r"^refresh\s+materialized\s+view\s+{RELATION}"), sql) {
+		return analysis(Lock::AccessExclusive, Some(Effect::Rewrite), vec![relation]);
+	}
+	if let Some((relation, _)) = capture_relation(&format!(r"^(?:vacuum\s+(?:\(.*?\bfull\b.*?\)|full\b.*?)\s*|cluster\s+(?:verbose\s+)?){RELATION}"), sql) {
+		return analysis(Lock::AccessExclusive, Some(Effect::Rewrite), vec![relation]);
+	}
+	if let Some((relation, _)) = capture_relation(&format!(r"^(?:update\s+(?:only\s+)?|delete\s+from\s+(?:only\s+)?){RELATION}"), sql) {
+		return analysis(Lock::RowExclusive, Some(Effect::Scan), vec![relation]);
+	}
+	None
+}
+
+#[test]
+fn test_analyze_statement() {
+	let analyze = |sql: &str| analyze_statement(sql).map(|analysis| (analysis.lock, analysis.effect, analysis.relations.join(",")));
+
+	assert_eq!(analyze("create table fruit (id int)"), None);
+	assert_eq!(analyze("insert into fruit values (1)"), None);
+	assert_eq!(analyze("create index fruit_name on fruit (name)"), Some((Lock::Share, Some(Effect::IndexBuild), "fruit".into())));
+	assert_eq!(
+		analyze("CREATE UNIQUE INDEX CONCURRENTLY fruit_name ON public.\"Fruit\" (name)"),
+		Some((Lock::ShareUpdateExclusive, Some(Effect::IndexBuild), "public.Fruit".into())),
+	);
+	assert_eq!(analyze("drop index fruit_name"), Some((Lock::AccessExclusive, None, "fruit_name".into())));
+	assert_eq!(analyze("drop table if exists fruit, Vegetable cascade"), Some((Lock::AccessExclusive, None, "fruit,vegetable".into())));
+	assert_eq!(analyze("alter table fruit drop column color"), Some((Lock::AccessExclusive, None, "fruit".into())));
+	assert_eq!(analyze("alter table fruit add column color text default 'red'"), Some((Lock::AccessExclusive, None, "fruit".into())));
+	assert_eq!(
+		analyze("alter table fruit add column id uuid not null default gen_random_uuid()"),
+		Some((Lock::AccessExclusive, Some(Effect::Rewrite), "fruit".into())),
+	);
+	assert_eq!(analyze("alter table only fruit alter column name type text"), Some((Lock::AccessExclusive, Some(Effect::Rewrite), "fruit".into())));
+	assert_eq!(analyze("alter table fruit alter column name set not null"), Some((Lock::AccessExclusive, Some(Effect::Scan), "fruit".into())));
+	assert_eq!(
+		analyze("alter table fruit add constraint fruit_tree foreign key (tree_id) references tree (id) not valid"),
+		Some((Lock::ShareRowExclusive, None, "fruit".into())),
+	);
+	assert_eq!(
+		analyze("alter table fruit add constraint fruit_tree foreign key (tree_id) references tree (id)"),
+		Some((Lock::ShareRowExclusive, Some(Effect::Scan), "fruit".into())),
+	);
+	assert_eq!(analyze("alter table fruit validate constraint fruit_tree"), Some((Lock::ShareUpdateExclusive, Some(Effect::Scan), "fruit".into())));
+	assert_eq!(analyze("alter table fruit add primary key (id)"), Some((Lock::AccessExclusive, Some(Effect::IndexBuild), "fruit".into())));
+	assert_eq!(
+		analyze("alter table if exists only public.\"Fruit\" validate constraint fruit_tree, alter column name type text"),
+		Some((Lock::AccessExclusive, Some(Effect::Rewrite), "public.Fruit".into())),
+	);
+	assert_eq!(
+		analyze("alter table fruit enable trigger fruit_audit, set (fillfactor = 70)"),
+		Some((Lock::ShareRowExclusive, None, "fruit".into())),
+	);
+	// the limits of recognizing statements by their shape: keywords inside string literals are taken as actions
+	assert_eq!(
+		analyze("alter table fruit add column note text default 'set not null'"),
+		Some((Lock::AccessExclusive, Some(Effect::Scan), "fruit".into())),
+	);
+	assert_eq!(analyze("update fruit set color = 'red'"), Some((Lock::RowExclusive, Some(Effect::Scan), "fruit".into())));
+	assert_eq!(analyze("refresh materialized view concurrently fruit_stats"), Some((Lock::Exclusive, Some(Effect::Scan), "fruit_stats".into())));
+}
+
+/// tables with at least this many rows, or with at least this much activity recorded in their statistics, are reported as hot
+const HOT_TABLE_ROWS: i64 = 1_000_000;
+const HOT_TABLE_ACTIVITY: i64 = 1_000_000;
+
+struct RelationStats {
+	rows: i64,
+	/// the table including its indexes
+	bytes: i64,
+	/// the table without its indexes
+	table_bytes: i64,
+	activity: i64,
+}
+
+const RELATION_STATS_QUERY: &str = "
+	select
+		greatest(pg_class.reltuples, 0)::bigint as rows,
+		pg_total_relation_size(pg_class.oid) as bytes,
+		pg_table_size(pg_class.oid) as table_bytes,
+		coalesce(seq_scan + coalesce(idx_scan, 0) + n_tup_ins + n_tup_upd + n_tup_del, 0)::bigint as activity
+	from pg_catalog.pg_class left join pg_catalog.pg_stat_all_tables on pg_stat_all_tables.relid = pg_class.oid
+	where pg_class.oid = to_regclass($1)
+";
+
+fn query_relation_stats(client: &mut postgres::Client, relation: &str) -> Result<Option<RelationStats>> {
+	let quoted = relation.split('.').map(|part| format!("\"{}\"", part.replace('"', "\"\""))).collect::<Vec<_>>().join(".");
+	Ok(client.query_opt(RELATION_STATS_QUERY, &[&quoted])?.map(|row| RelationStats{
+		rows: row.get("rows"),
+		bytes: row.get("bytes"),
+		table_bytes: row.get("table_bytes"),
+		activity: row.get("activity"),
+	}))
+}
+
+fn format_bytes(bytes: i64) -> String {
+	let units = ["bytes", "kB", "MB", "GB", "TB"];
+	let mut size = bytes as f64;
+	let mut unit = 0;
+	while size >= 1024.0 && unit < units.len() - 1 {
+		size /= 1024.0;
+		unit += 1;
+	}
+	match unit {
+		0 => format!("{bytes} bytes"),
+		_ => format!("{size:.1} {}", units[unit]),
+	}
+}
+
+#[test]
+fn test_format_bytes() {
+	assert_eq!(format_bytes(0), "0 bytes");
+	assert_eq!(format_bytes(1023), "1023 bytes");
+	assert_eq!(format_bytes(1536), "1.5 kB");
+	assert_eq!(format_bytes(200 * 1024 * 1024 * 1024), "200.0 GB");
+}
+
+fn format_duration(duration: Duration) -> String {
+	let seconds = duration.as_secs();
+	match seconds {
+		0 => "<1s".to_string(),
+		1..=59 => format!("{seconds}s"),
+		60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+		_ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+	}
+}
+
+#[test]
+fn test_format_duration() {
+	assert_eq!(format_duration(Duration::from_millis(400)), "<1s");
+	assert_eq!(format_duration(Duration::from_secs(59)), "59s");
+	assert_eq!(format_duration(Duration::from_secs(200)), "3m 20s");
+	assert_eq!(format_duration(Duration::from_secs(7500)), "2h 5m");
+}
+
+#[test]
+fn test_effect_estimate() {
+	let gigabyte = 1024 * 1024 * 1024;
+	let stats = RelationStats{ rows: 0, bytes: 300 * gigabyte, table_bytes: 200 * gigabyte, activity: 0 };
+	let bytes_per_second = 100 * 1024 * 1024;
+	assert_eq!(Effect::Scan.estimate(&stats, bytes_per_second), Duration::from_secs(2048));
+	assert_eq!(Effect::IndexBuild.estimate(&stats, bytes_per_second), Duration::from_secs(4096));
+	assert_eq!(Effect::Rewrite.estimate(&stats, bytes_per_second), Duration::from_secs(5120));
+}
+
+fn first_line(sql: &str) -> String {
+	let line = sql.lines().next().unwrap_or_default();
+	match line.chars().count() > 80 || sql.contains('\n') {
+		true => format!("{}...", line.chars().take(77).collect::<String>()),
+		false => line.to_string(),
+	}
+}
+
+/// reports the locks pending migrations will take, how the affected tables look in the target database,
+/// and roughly how long each migration will block writes to them
+pub(crate) fn command_analyze(args: &Args, megabytes_per_second: u64, maintenance_window_seconds: u64) -> Result<()> {
+	let bytes_per_second = megabytes_per_second * 1024 * 1024;
+	let (migration_files, _) = gather_validated_migrations(args)?;
+	let mut client = connections::connect(args.read_config())?;
+	let actual_version = query_actual_version(&mut client)?;
+
+	for MigrationFile{file_path, display_file_path, current_version, is_onboard, ..} in &migration_files {
+		if !is_pending(current_version, &actual_version) || *is_onboard {
+			continue;
+		}
+
+		println!("{display_file_path}");
+		let statements = split_statements(&read_sql_file(file_path)?)?;
+		let mut analyzed_any = false;
+		let mut blocking_writes = Duration::ZERO;
+		for statement in statements {
+			let Some(analysis) = analyze_statement(&statement.sql) else { continue };
+			analyzed_any = true;
+
+			println!("  line {}: {}", statement.line, first_line(&statement.sql));
+			match analysis.effect {
+				Some(effect) => println!("    {} lock ({}), {effect}", analysis.lock, analysis.lock.blocks()),
+				None => println!("    {} lock ({})", analysis.lock, analysis.lock.blocks()),
+			}
+			for relation in &analysis.relations {
+				match query_relation_stats(&mut client, relation)? {
+					None => println!("    {relation}: doesn't exist in the database yet"),
+					Some(stats) => {
+						let hot = if stats.rows >= HOT_TABLE_ROWS || stats.activity >= HOT_TABLE_ACTIVITY { ", hot" } else { "" };
+						let estimate = analysis.effect.map(|effect| effect.estimate(&stats, bytes_per_second));
+						let estimated = estimate.map(|estimate| format!(", estimated {}", format_duration(estimate))).unwrap_or_default();
+						println!("    {relation}: ~{} rows, {}{hot}{estimated}", stats.rows, format_bytes(stats.bytes));
+
+						if analysis.lock >= Lock::Share {
+							blocking_writes += estimate.unwrap_or_default();
+						}
+					},
+				}
+			}
+		}
+		if !analyzed_any {
+			println!("  doesn't lock any existing tables");
+		}
+		else if blocking_writes >= Duration::from_secs(maintenance_window_seconds) {
+			println!("  blocks writes for an estimated {}, consider a maintenance window", format_duration(blocking_writes));
+		}
+	}
+
+	Ok(())
+}
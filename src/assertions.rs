@@ -0,0 +1,132 @@
+//! Queries in the assertions directory that check the data is still consistent after migrating.
+
+use std::path::Path;
+use anyhow::{anyhow, Result};
+use postgres::{Client, SimpleQueryMessage, SimpleQueryRow};
+
+use super::{Args, connections, list_sql_files, read_sql_file};
+
+/// how many of the rows an assertion returned are printed
+const SHOWN_ROWS: usize = 5;
+
+/// an assertion passes when its query returns no rows, or a single `true`
+fn passes(rows: &[Vec<(String, Option<String>)>]) -> bool {
+	match rows {
+		[] => true,
+		[row] => matches!(&row[..], [(_, Some(value))] if value == "t"),
+		_ => false,
+	}
+}
+
+#[test]
+fn test_passes() {
+	let row = |columns: &[(&str, Option<&str>)]| columns.iter().map(|(name, value)| (name.to_string(), value.map(str::to_string))).collect::<Vec<_>>();
+	assert!(passes(&[]));
+	assert!(passes(&[row(&[("consistent", Some("t"))])]));
+	assert!(!passes(&[row(&[("consistent", Some("f"))])]));
+	assert!(!passes(&[row(&[("consistent", None)])]));
+	assert!(!passes(&[row(&[("id", Some("7"))])]));
+	assert!(!passes(&[row(&[("id", Some("t")), ("name", Some("t"))])]));
+	assert!(!passes(&[row(&[("ok", Some("t"))]), row(&[("ok", Some("t"))])]));
+}
+
+fn format_row(row: &[(String, Option<String>)]) -> String {
+	row.iter()
+		.map(|(name, value)| format!("{name}={}", value.as_deref().unwrap_or("null")))
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+fn columns(row: &SimpleQueryRow) -> Vec<(String, Option<String>)> {
+	row.columns().iter().enumerate()
+		.map(|(index, column)| (column.name().to_string(), row.get(index).map(str::to_string)))
+		.collect()
+}
+
+/// runs `sql` in a read only transaction that's rolled back, so an assertion can never change anything.
+/// returns a description of why it failed, if it did
+fn run_assertion(client: &mut Client, sql: &str) -> Result<Option<String>> {
+	let mut transaction = client.transaction()?;
+	transaction.batch_execute("set transaction read only")?;
+	let rows: Vec<_> = transaction.simple_query(sql)?.iter()
+		.filter_map(|message| match message {
+			SimpleQueryMessage::Row(row) => Some(columns(row)),
+			_ => None,
+		})
+		.collect();
+	if passes(&rows) {
+		return Ok(None);
+	}
+
+	let mut failure = format!("returned {} rows", rows.len());
+	for row in rows.iter().take(SHOWN_ROWS) {
+		failure.push_str(&format!("\n          {}", format_row(row)));
+	}
+	if rows.len() > SHOWN_ROWS {
+		failure.push_str("\n          ...");
+	}
+	Ok(Some(failure))
+}
+
+/// runs every assertion, printing whether each passed, and fails if any didn't.
+/// with `required`, a missing or empty assertions directory is an error too
+pub(crate) fn run_assertions(args: &Args, required: bool) -> Result<()> {
+	let sql_files = match Path::new(&args.assertions_directory).exists() {
+		true => list_sql_files(&args.assertions_directory)?,
+		false => vec![],
+	};
+	if sql_files.is_empty() {
+		return match required {
+			true => Err(anyhow!("there are no assertions in {}", args.assertions_directory)),
+			false => Ok(()),
+		};
+	}
+
+	let failed_count = connections::with_client(&args.pg_url, |client| {
+		let mut failed_count = 0;
+		for sql_file in &sql_files {
+			match run_assertion(client, &read_sql_file(sql_file)?) {
+				Ok(None) => println!("ok      {}", sql_file.display()),
+				Ok(Some(failure)) => {
+					println!("failed  {}: {failure}", sql_file.display());
+					failed_count += 1;
+				},
+				Err(error) => {
+					println!("failed  {}: unable to run it, {}", sql_file.display(), error.root_cause());
+					failed_count += 1;
+				},
+			}
+		}
+		Ok(failed_count)
+	})?;
+
+	match failed_count {
+		0 => Ok(()),
+		_ => Err(anyhow!("{failed_count} of {} assertions failed", sql_files.len())),
+	}
+}
+
+#[test]
+fn test_run_assertions_without_assertions() {
+	let args = super::test_args(&["--assertions-directory", "assertions/that/dont/exist", "assert"]);
+	assert!(run_assertions(&args, false).is_ok());
+	assert_eq!(run_assertions(&args, true).unwrap_err().to_string(), "there are no assertions in assertions/that/dont/exist");
+}
+
+#[test]
+#[ignore]
+fn test_run_assertion() -> Result<()> {
+	let mut client = std::env::var("PG_URL")?.parse::<postgres::Config>()?.connect(postgres::NoTls)?;
+
+	assert_eq!(run_assertion(&mut client, "select 1 as id where false")?, None);
+	assert_eq!(run_assertion(&mut client, "select null::text as name")?, Some("returned 1 rows\n          name=null".to_string()));
+	let failure = run_assertion(&mut client, "select generate_series(1, 7) as id")?.unwrap();
+	assert!(failure.starts_with("returned 7 rows\n          id=1\n"));
+	assert!(failure.ends_with("id=5\n          ..."));
+
+	// assertions can't change anything, and broken ones are errors rather than failures
+	assert!(run_assertion(&mut client, "create table assertion_side_effect ()").is_err());
+	assert!(run_assertion(&mut client, "select from table_that_doesnt_exist").is_err());
+	assert!(client.query_opt("select to_regclass('assertion_side_effect')::text as name", &[])?.unwrap().get::<_, Option<String>>("name").is_none());
+	Ok(())
+}
@@ -0,0 +1,101 @@
+use std::{process::Command, time::{Duration, SystemTime}};
+use anyhow::Result;
+use postgres::Config;
+
+use super::connections;
+
+pub(crate) const AUDIT_TABLE: &str = "_migrator_audit";
+
+pub(crate) const AUDIT_TABLE_SQL: &str = "
+	create table if not exists _migrator_audit (
+		id bigserial primary key,
+		started_at timestamptz not null,
+		duration_ms bigint not null,
+		invocation text not null,
+		os_user text,
+		database_user text not null default current_user,
+		host text,
+		git_sha text,
+		succeeded bool not null,
+		error text
+	);
+";
+
+/// options whose values can contain a password or a token
+const REDACTED_OPTIONS: [&str; 6] = ["--pg-url", "--scratch-url", "--maintenance-url", "--read-url", "--proxy", "--remote-header"];
+
+/// the command line this process was invoked with, with connection strings and credentials redacted
+fn redacted_invocation(raw_args: impl Iterator<Item = String>) -> String {
+	let mut redact_next = false;
+	raw_args
+		.map(|arg| {
+			if redact_next {
+				redact_next = false;
+				return "<redacted>".to_string();
+			}
+			let option = arg.split('=').next().unwrap_or_default();
+			match (REDACTED_OPTIONS.contains(&option), arg.contains('=')) {
+				(true, true) => format!("{option}=<redacted>"),
+				(true, false) => { redact_next = true; arg },
+				(false, _) => arg,
+			}
+		})
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+#[test]
+fn test_redacted_invocation() {
+	let invocation = |args: &[&str]| redacted_invocation(args.iter().map(|arg| arg.to_string()));
+	assert_eq!(invocation(&["migrate", "--dry-run"]), "migrate --dry-run");
+	assert_eq!(
+		invocation(&["--pg-url", "postgres://user:password@db/app", "migrate"]),
+		"--pg-url <redacted> migrate",
+	);
+	assert_eq!(
+		invocation(&["--pg-url=postgres://user:password@db/app", "generate", "add fruit"]),
+		"--pg-url=<redacted> generate add fruit",
+	);
+	assert_eq!(
+		invocation(&["--scratch-url", "postgres://a:b@scratch", "--read-url=postgres://a:b@replica", "--remote-header", "Authorization: Bearer x", "check"]),
+		"--scratch-url <redacted> --read-url=<redacted> --remote-header <redacted> check",
+	);
+	// a trailing option without its value has nothing to redact
+	assert_eq!(invocation(&["migrate", "--pg-url"]), "migrate --pg-url");
+	assert_eq!(invocation(&["generate", "--pg-url-ish=x"]), "generate --pg-url-ish=x");
+}
+
+fn command_output(program: &str, program_args: &[&str]) -> Option<String> {
+	let output = Command::new(program).args(program_args).output().ok()?;
+	match output.status.success() {
+		true => Some(String::from_utf8_lossy(&output.stdout).trim().to_string()).filter(|output| !output.is_empty()),
+		false => None,
+	}
+}
+
+#[test]
+fn test_command_output() {
+	assert_eq!(command_output("echo", &["  main  "]), Some("main".to_string()));
+	assert_eq!(command_output("echo", &[""]), None);
+	assert_eq!(command_output("false", &[]), None);
+	assert_eq!(command_output("postgres_migrator_program_that_doesnt_exist", &[]), None);
+}
+
+/// records an invocation of postgres_migrator in the `_migrator_audit` table of the target database
+pub(crate) fn record_invocation(pg_url: &Config, started_at: SystemTime, duration: Duration, outcome: &Result<()>) -> Result<()> {
+	let invocation = redacted_invocation(std::env::args().skip(1));
+	let os_user = std::env::var("USER").or_else(|_| std::env::var("USERNAME")).ok();
+	let host = command_output("hostname", &[]);
+	let git_sha = command_output("git", &["rev-parse", "HEAD"]);
+	let error = outcome.as_ref().err().map(|err| format!("{err:#}"));
+	let duration_ms = duration.as_millis() as i64;
+
+	let mut client = connections::connect(pg_url)?;
+	client.batch_execute(AUDIT_TABLE_SQL)?;
+	client.execute(
+		"insert into _migrator_audit (started_at, duration_ms, invocation, os_user, host, git_sha, succeeded, error)
+		values ($1, $2, $3, $4, $5, $6, $7, $8)",
+		&[&started_at, &duration_ms, &invocation, &os_user, &host, &git_sha, &outcome.is_ok(), &error],
+	)?;
+	Ok(())
+}
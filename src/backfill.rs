@@ -0,0 +1,159 @@
+//! Runs large data migrations in small batches, so they never hold locks or a transaction for long.
+
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BackfillAction {
+	/// the `set` clause of the update, without the `set` keyword, for example `color = 'red'`
+	Update { set: String },
+	Delete,
+}
+
+/// what to backfill, the sql fragments are interpolated as they are
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BackfillSpec {
+	pub table: String,
+	/// a unique, ordered column that batches are paginated by, usually the primary key
+	pub key: String,
+	pub action: BackfillAction,
+	/// only rows matching this condition are changed
+	pub predicate: Option<String>,
+	pub batch_size: i64,
+	/// pause between batches to leave room for other traffic and replication
+	pub sleep: Duration,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BackfillProgress {
+	pub batches: u64,
+	pub changed_rows: u64,
+	/// the key of the last row the latest batch went through, as text
+	pub last_key: String,
+}
+
+impl BackfillSpec {
+	fn batch_sql(&self, key_type: &str) -> String {
+		let BackfillSpec{table, key, action, predicate, batch_size, ..} = self;
+		let predicate = predicate.as_deref().unwrap_or("true");
+		let change = match action {
+			BackfillAction::Update{set} => format!("update {table} set {set} from batch where {table}.{key} = batch.batch_key returning 1"),
+			BackfillAction::Delete => format!("delete from {table} using batch where {table}.{key} = batch.batch_key returning 1"),
+		};
+		// the key is renamed in the batch, so `set` can refer to the key column of the table without qualifying it
+		format!("
+			with batch as (
+				select {key} as batch_key from {table}
+				where ($1::text is null or {key} > $1::text::{key_type}) and ({predicate})
+				order by {key} limit {batch_size}
+				for update
+			), changed as (
+				{change}
+			)
+			select
+				(select max(batch_key)::text from batch) as last_key,
+				(select count(*) from batch) as batch_rows,
+				(select count(*) from changed) as changed_rows
+		")
+	}
+}
+
+#[test]
+fn test_batch_sql() {
+	let spec = BackfillSpec {
+		table: "fruit".into(), key: "id".into(),
+		action: BackfillAction::Update{ set: "color = 'red'".into() },
+		predicate: Some("color is null".into()),
+		batch_size: 500, sleep: Duration::ZERO,
+	};
+	let batch_sql = spec.batch_sql("bigint");
+	assert!(batch_sql.contains("where ($1::text is null or id > $1::text::bigint) and (color is null)"));
+	assert!(batch_sql.contains("order by id limit 500"));
+	assert!(batch_sql.contains("update fruit set color = 'red' from batch where fruit.id = batch.batch_key returning 1"));
+
+	let delete_sql = BackfillSpec{ action: BackfillAction::Delete, predicate: None, ..spec }.batch_sql("uuid");
+	assert!(delete_sql.contains("and (true)"));
+	assert!(delete_sql.contains("delete from fruit using batch where fruit.id = batch.batch_key returning 1"));
+}
+
+const KEY_TYPE_QUERY: &str = "
+	select format_type(atttypid, atttypmod) as key_type
+	from pg_catalog.pg_attribute
+	where attrelid = $1::text::regclass and attname = $2 and not attisdropped
+";
+
+/// runs `spec` one batch at a time, each batch in its own transaction, calling `on_progress` after every batch
+pub fn backfill(client: &mut postgres::Client, spec: &BackfillSpec, mut on_progress: impl FnMut(&BackfillProgress)) -> Result<BackfillProgress> {
+	if spec.batch_size < 1 {
+		return Err(anyhow!("batch size has to be at least 1"));
+	}
+	let key_type: String = client.query_opt(KEY_TYPE_QUERY, &[&spec.table, &spec.key])?
+		.ok_or_else(|| anyhow!("{} has no column {}", spec.table, spec.key))?
+		.get("key_type");
+	let batch_sql = spec.batch_sql(&key_type);
+
+	let mut progress = BackfillProgress{ batches: 0, changed_rows: 0, last_key: String::new() };
+	let mut last_key: Option<String> = None;
+	loop {
+		let row = client.query_one(batch_sql.as_str(), &[&last_key])?;
+		let Some(batch_last_key) = row.get::<_, Option<String>>("last_key") else { break };
+		let batch_rows: i64 = row.get("batch_rows");
+		let changed_rows: i64 = row.get("changed_rows");
+
+		progress.batches += 1;
+		progress.changed_rows += changed_rows as u64;
+		progress.last_key = batch_last_key.clone();
+		on_progress(&progress);
+
+		if batch_rows < spec.batch_size {
+			break;
+		}
+		last_key = Some(batch_last_key);
+		std::thread::sleep(spec.sleep);
+	}
+	Ok(progress)
+}
+
+#[test]
+#[ignore]
+fn test_backfill() -> Result<()> {
+	let mut client = std::env::var("PG_URL")?.parse::<postgres::Config>()?.connect(postgres::NoTls)?;
+	client.batch_execute("
+		create temporary table backfilled_fruit (id bigint primary key, color text);
+		insert into backfilled_fruit select id, case when id % 5 = 0 then 'green' end from generate_series(1, 25) as id;
+	")?;
+	let spec = BackfillSpec {
+		table: "backfilled_fruit".into(), key: "id".into(),
+		action: BackfillAction::Update{ set: "color = 'red'".into() },
+		predicate: Some("color is null".into()),
+		batch_size: 10, sleep: Duration::ZERO,
+	};
+
+	let mut reported = vec![];
+	let progress = backfill(&mut client, &spec, |progress| reported.push(progress.changed_rows))?;
+	assert_eq!(reported, vec![10, 20]);
+	assert_eq!(progress, BackfillProgress{ batches: 2, changed_rows: 20, last_key: "24".into() });
+	// there's nothing left to change
+	assert_eq!(backfill(&mut client, &spec, |_| {})?.batches, 0);
+
+	assert_eq!(backfill(&mut client, &BackfillSpec{ batch_size: 0, ..spec.clone() }, |_| {}).unwrap_err().to_string(), "batch size has to be at least 1");
+	assert_eq!(
+		backfill(&mut client, &BackfillSpec{ key: "uuid".into(), ..spec.clone() }, |_| {}).unwrap_err().to_string(),
+		"backfilled_fruit has no column uuid",
+	);
+	assert!(backfill(&mut client, &BackfillSpec{ table: "fruit_that_doesnt_exist".into(), ..spec.clone() }, |_| {}).is_err());
+
+	// batches before the one that fails stay committed
+	let failing = BackfillSpec {
+		action: BackfillAction::Update{ set: "color = (1 / (id - 15))::text".into() },
+		predicate: None,
+		..spec
+	};
+	let mut batches = 0;
+	assert!(backfill(&mut client, &failing, |progress| batches = progress.batches).is_err());
+	assert_eq!(batches, 1);
+	let changed: i64 = client.query_one("select count(*) from backfilled_fruit where color not in ('red', 'green')", &[])?.get(0);
+	assert_eq!(changed, 10);
+	Ok(())
+}
+
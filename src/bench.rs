@@ -0,0 +1,77 @@
+//! Times how long each schema file and each migration takes to apply to a temporary database.
+
+use std::{path::PathBuf, time::{Duration, Instant}};
+use anyhow::{anyhow, Result};
+
+use super::{Args, TempDb, connections, gather_validated_migrations, list_sql_files, read_sql_to_apply, statements};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Timing {
+	backend: &'static str,
+	file_path: PathBuf,
+	duration: Duration,
+}
+
+fn apply_timed(args: &Args, backend: &'static str, sql_files: Vec<PathBuf>) -> Result<Vec<Timing>> {
+	let dbname = args.pg_url.get_dbname().ok_or_else(|| anyhow!("provided pg_url has no dbname"))?;
+	let temp = TempDb::new(args, dbname, &format!("bench_{backend}"))?;
+	connections::with_client(&temp.config, |client| {
+		let mut timings = vec![];
+		for sql_file in sql_files {
+			let query = read_sql_to_apply(&sql_file, backend == "migrations")?;
+			let started = Instant::now();
+			statements::batch_execute(client, &query, &sql_file)?;
+			timings.push(Timing{ backend, file_path: sql_file, duration: started.elapsed() });
+		}
+		Ok(timings)
+	})
+}
+
+/// the `top` slowest timings, slowest first
+fn slowest(mut timings: Vec<Timing>, top: usize) -> Vec<Timing> {
+	timings.sort_by(|a, b| b.duration.cmp(&a.duration).then_with(|| a.file_path.cmp(&b.file_path)));
+	timings.truncate(top);
+	timings
+}
+
+#[test]
+fn test_slowest() {
+	let timing = |file_path: &str, millis: u64| Timing{ backend: "schema", file_path: file_path.into(), duration: Duration::from_millis(millis) };
+	assert_eq!(
+		slowest(vec![timing("a.sql", 5), timing("b.sql", 300), timing("c.sql", 40), timing("d.sql", 40)], 3),
+		vec![timing("b.sql", 300), timing("c.sql", 40), timing("d.sql", 40)],
+	);
+	assert_eq!(slowest(vec![timing("a.sql", 5)], 3), vec![timing("a.sql", 5)]);
+}
+
+fn total(timings: &[Timing]) -> Duration {
+	timings.iter().map(|timing| timing.duration).sum()
+}
+
+/// applies the schema directory and the migrations to their own temporary databases, timing every file,
+/// and prints the `top` slowest of them
+pub(crate) fn command_bench(args: &Args, top: usize) -> Result<()> {
+	let schema_timings = apply_timed(args, "schema", list_sql_files(&args.schema_directory)?)?;
+	let migration_files = gather_validated_migrations(args)?.0.into_iter().map(|migration_file| migration_file.file_path).collect();
+	let migration_timings = apply_timed(args, "migrations", migration_files)?;
+
+	println!(
+		"applied {} schema files in {:.2?} and {} migrations in {:.2?}",
+		schema_timings.len(), total(&schema_timings), migration_timings.len(), total(&migration_timings),
+	);
+	let all_timings: Vec<Timing> = schema_timings.into_iter().chain(migration_timings).collect();
+	let overall = total(&all_timings).as_secs_f64();
+	let slowest = slowest(all_timings, top);
+	if slowest.is_empty() {
+		return Ok(());
+	}
+	println!("slowest:");
+	for Timing{backend, file_path, duration} in slowest {
+		let share = match overall > 0.0 {
+			true => duration.as_secs_f64() / overall * 100.0,
+			false => 0.0,
+		};
+		println!("  {:>10.2?} {share:>5.1}%  {backend:<10}  {}", duration, file_path.display());
+	}
+	Ok(())
+}
@@ -0,0 +1,150 @@
+//! Packages the schema and migrations directories as an OCI artifact in a container registry, with [oras](https://oras.land).
+
+use std::{fs, path::Path, process::Command};
+use anyhow::{anyhow, Context, Result};
+
+use super::{Args, gather_validated_migrations};
+use super::remote::{RemoteDirectory, run};
+
+const ARTIFACT_TYPE: &str = "application/vnd.postgres-migrator.bundle.v1";
+const LAYER_MEDIA_TYPE: &str = "application/vnd.postgres-migrator.bundle.v1.tar+gzip";
+const ARCHIVE_FILE_NAME: &str = "bundle.tar.gz";
+/// the directories of the archive, whatever the schema and migrations directories are called locally
+const SCHEMA_DIRECTORY: &str = "schema";
+const MIGRATIONS_DIRECTORY: &str = "migrations";
+
+#[derive(clap::Subcommand, Debug)]
+pub(crate) enum BundleCommand {
+	/// packages the schema and migrations directories and pushes them to the registry
+	Push {
+		/// where the bundle is pushed, tagged with the app version, such as registry.example.com/app/schema:1.4.2
+		reference: String,
+	},
+	/// pulls a bundle and unpacks its schema and migrations directories
+	Pull {
+		/// the bundle, such as registry.example.com/app/schema:1.4.2
+		reference: String,
+		/// where the schema and migrations directories are unpacked
+		#[clap(long, default_value = "bundle")]
+		output_directory: String,
+	},
+}
+
+/// the tag of a reference like `registry:5000/app/schema:1.4.2`, the part after a colon in its last path segment
+fn reference_tag(reference: &str) -> Option<&str> {
+	let last_segment = reference.split('@').next()?.rsplit('/').next()?;
+	let (_, tag) = last_segment.split_once(':')?;
+	(!tag.is_empty()).then_some(tag)
+}
+
+#[test]
+fn test_reference_tag() {
+	assert_eq!(reference_tag("registry.example.com/app/schema:1.4.2"), Some("1.4.2"));
+	assert_eq!(reference_tag("localhost:5000/schema:v3"), Some("v3"));
+	assert_eq!(reference_tag("localhost:5000/schema"), None);
+	assert_eq!(reference_tag("registry.example.com/app/schema@sha256:abc"), None);
+	assert_eq!(reference_tag("registry.example.com/app/schema:"), None);
+}
+
+fn oras() -> Command {
+	Command::new("oras")
+}
+
+/// pulls the bundle `reference` into a temporary directory, deleted when the returned directory is dropped
+pub(crate) fn pull(reference: &str) -> Result<RemoteDirectory> {
+	let bundle = RemoteDirectory::create()?;
+	let pulled = bundle.temp_path("pulled");
+	run(oras().arg("pull").arg(reference).arg("--output").arg(&pulled), &format!("oras pull {reference}"))
+		.context("is oras installed and logged in to the registry?")?;
+	let archive = pulled.join(ARCHIVE_FILE_NAME);
+	if !archive.exists() {
+		return Err(anyhow!("{reference} isn't a postgres_migrator bundle, it has no {ARCHIVE_FILE_NAME}"));
+	}
+	run(Command::new("tar").arg("-xzf").arg(&archive).arg("-C").arg(&bundle.directory), "tar -xzf")
+		.with_context(|| format!("unable to unpack {reference}"))?;
+	Ok(bundle)
+}
+
+/// the schema and migrations directories of a pulled bundle
+pub(crate) fn bundle_directories(bundle: &RemoteDirectory) -> (String, String) {
+	let directory = |name: &str| bundle.directory.join(name).to_string_lossy().into_owned();
+	(directory(SCHEMA_DIRECTORY), directory(MIGRATIONS_DIRECTORY))
+}
+
+fn copy_directory(source: &str, destination: &Path) -> Result<()> {
+	run(Command::new("cp").arg("-R").arg(source).arg(destination), &format!("cp -R {source}"))
+}
+
+/// checks the migration chain, so a broken one is never released, and pushes both directories as one archive
+fn command_push(args: &Args, reference: &str) -> Result<()> {
+	if reference_tag(reference).is_none() {
+		return Err(anyhow!("{reference} needs a tag, such as {reference}:1.4.2, so deploys can pull the bundle of their version"));
+	}
+	gather_validated_migrations(args)?;
+
+	let staging = RemoteDirectory::create()?;
+	copy_directory(&args.schema_directory, &staging.directory.join(SCHEMA_DIRECTORY))?;
+	copy_directory(&args.migrations_directory, &staging.directory.join(MIGRATIONS_DIRECTORY))?;
+	run(
+		Command::new("tar").arg("-czf").arg(staging.temp_path(ARCHIVE_FILE_NAME)).arg("-C").arg(&staging.directory).args([SCHEMA_DIRECTORY, MIGRATIONS_DIRECTORY]),
+		"tar -czf",
+	)?;
+	// run from the archive's directory, so oras records the file by its name instead of a temporary path
+	run(
+		oras().current_dir(staging.temp_path(""))
+			.args(["push", reference, "--artifact-type", ARTIFACT_TYPE, &format!("{ARCHIVE_FILE_NAME}:{LAYER_MEDIA_TYPE}")]),
+		&format!("oras push {reference}"),
+	).context("is oras installed and logged in to the registry?")?;
+	println!("pushed {reference}");
+	Ok(())
+}
+
+fn command_pull(reference: &str, output_directory: &str) -> Result<()> {
+	let output_path = Path::new(output_directory);
+	if output_path.exists() && fs::read_dir(output_path)?.next().is_some() {
+		return Err(anyhow!("{output_directory} isn't empty"));
+	}
+	let bundle = pull(reference)?;
+	fs::create_dir_all(output_path)?;
+	for name in [SCHEMA_DIRECTORY, MIGRATIONS_DIRECTORY] {
+		copy_directory(&bundle.directory.join(name).to_string_lossy(), &output_path.join(name))?;
+	}
+	println!("unpacked {reference} into {output_directory}");
+	Ok(())
+}
+
+#[test]
+fn test_bundle_failures() -> Result<()> {
+	let root = std::env::temp_dir().join("postgres_migrator_test_bundle_failures");
+	let _ = fs::remove_dir_all(&root);
+	let migrations_directory = root.join("migrations");
+	fs::create_dir_all(&migrations_directory)?;
+	// its previous migration is missing
+	fs::write(migrations_directory.join("20220102000000.20220101000000.add_color.sql"), "alter table fruit add color text;")?;
+	let args = super::test_args(&["--migrations-directory", migrations_directory.to_str().unwrap(), "bundle", "push", "localhost:1/schema:1"]);
+
+	assert_eq!(
+		command_push(&args, "localhost:1/schema").unwrap_err().to_string(),
+		"localhost:1/schema needs a tag, such as localhost:1/schema:1.4.2, so deploys can pull the bundle of their version",
+	);
+	// the chain is checked before anything is pushed
+	assert!(command_push(&args, "localhost:1/schema:1").is_err());
+
+	assert_eq!(
+		command_pull("localhost:1/schema:1", &root.to_string_lossy()).unwrap_err().to_string(),
+		format!("{} isn't empty", root.display()),
+	);
+	let error = command_pull("localhost:1/schema:1", &root.join("bundle").to_string_lossy()).unwrap_err();
+	assert_eq!(error.to_string(), "is oras installed and logged in to the registry?");
+	assert!(!root.join("bundle").exists());
+
+	fs::remove_dir_all(&root)?;
+	Ok(())
+}
+
+pub(crate) fn command_bundle(args: &Args, command: &BundleCommand) -> Result<()> {
+	match command {
+		BundleCommand::Push{reference} => command_push(args, reference),
+		BundleCommand::Pull{reference, output_directory} => command_pull(reference, output_directory),
+	}
+}
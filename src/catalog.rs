@@ -0,0 +1,218 @@
+//! A machine-readable model of what the schema directory declares, read from the catalog of a temp database it's applied to.
+
+use anyhow::{anyhow, Result};
+use postgres::Client;
+use serde_json::{Value, json};
+
+use super::{Args, TempDb, apply_schema_files, connections, list_sql_files};
+use super::lints::{USER_NAMESPACES, not_from_extension};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+pub(crate) enum CatalogFormat {
+	Json,
+}
+
+fn constraint_kind(contype: &str) -> &'static str {
+	match contype {
+		"p" => "primary key",
+		"u" => "unique",
+		"f" => "foreign key",
+		"c" => "check",
+		"x" => "exclusion",
+		_ => "other",
+	}
+}
+
+/// moves each of `children`, which have a `schema` and a `table`, into the `field` array of the relation they belong to
+fn nest(mut relations: Vec<Value>, children: Vec<Value>, field: &str) -> Vec<Value> {
+	for relation in relations.iter_mut() {
+		relation[field] = json!([]);
+	}
+	for mut child in children {
+		let key = (child["schema"].clone(), child["table"].clone());
+		let Some(relation) = relations.iter_mut().find(|relation| (relation["schema"].clone(), relation["name"].clone()) == key) else { continue };
+		let child = child.as_object_mut().unwrap();
+		child.remove("schema");
+		child.remove("table");
+		relation[field].as_array_mut().unwrap().push(Value::Object(child.clone()));
+	}
+	relations
+}
+
+#[test]
+fn test_nest() {
+	let relations = vec![json!({"schema": "public", "name": "fruit"}), json!({"schema": "app", "name": "fruit"})];
+	let columns = vec![
+		json!({"schema": "public", "table": "fruit", "name": "id"}),
+		json!({"schema": "app", "table": "fruit", "name": "color"}),
+		json!({"schema": "public", "table": "fruit", "name": "name"}),
+		json!({"schema": "public", "table": "vegetable", "name": "id"}),
+	];
+	assert_eq!(nest(relations, columns, "columns"), vec![
+		json!({"schema": "public", "name": "fruit", "columns": [{"name": "id"}, {"name": "name"}]}),
+		json!({"schema": "app", "name": "fruit", "columns": [{"name": "color"}]}),
+	]);
+	assert_eq!(constraint_kind("f"), "foreign key");
+}
+
+fn introspect(client: &mut Client) -> Result<Value> {
+	let relations = |relkinds: &str| format!("
+		select n.nspname::text as schema, c.relname::text as name, c.relkind::text as kind, obj_description(c.oid, 'pg_class') as comment
+		from pg_class c join pg_namespace n on n.oid = c.relnamespace
+		where c.relkind in ({relkinds}) and {USER_NAMESPACES} and {}
+		order by 1, 2
+	", not_from_extension("c.oid"));
+	let tables = client.query(&relations("'r', 'p'"), &[])?.into_iter().map(|row| json!({
+		"schema": row.get::<_, String>("schema"),
+		"name": row.get::<_, String>("name"),
+		"partitioned": row.get::<_, String>("kind") == "p",
+		"comment": row.get::<_, Option<String>>("comment"),
+	})).collect();
+	let views = client.query(&relations("'v', 'm'"), &[])?.into_iter().map(|row| json!({
+		"schema": row.get::<_, String>("schema"),
+		"name": row.get::<_, String>("name"),
+		"materialized": row.get::<_, String>("kind") == "m",
+		"comment": row.get::<_, Option<String>>("comment"),
+	})).collect();
+
+	let columns: Vec<Value> = client.query(&format!("
+		select n.nspname::text as schema, c.relname::text as table, a.attname::text as name,
+			format_type(a.atttypid, a.atttypmod) as type, not a.attnotnull as nullable,
+			pg_get_expr(d.adbin, d.adrelid) as default, a.attidentity::text as identity, a.attgenerated::text as generated,
+			col_description(c.oid, a.attnum) as comment
+		from pg_attribute a
+		join pg_class c on c.oid = a.attrelid
+		join pg_namespace n on n.oid = c.relnamespace
+		left join pg_attrdef d on d.adrelid = a.attrelid and d.adnum = a.attnum
+		where c.relkind in ('r', 'p', 'v', 'm') and a.attnum > 0 and not a.attisdropped and {USER_NAMESPACES} and {}
+		order by 1, 2, a.attnum
+	", not_from_extension("c.oid")), &[])?.into_iter().map(|row| {
+		let (identity, generated): (String, String) = (row.get("identity"), row.get("generated"));
+		json!({
+			"schema": row.get::<_, String>("schema"),
+			"table": row.get::<_, String>("table"),
+			"name": row.get::<_, String>("name"),
+			"type": row.get::<_, String>("type"),
+			"nullable": row.get::<_, bool>("nullable"),
+			"default": row.get::<_, Option<String>>("default"),
+			"identity": match identity.as_str() { "a" => Some("always"), "d" => Some("by default"), _ => None },
+			"generated": generated == "s",
+			"comment": row.get::<_, Option<String>>("comment"),
+		})
+	}).collect();
+
+	let constraints: Vec<Value> = client.query(&format!("
+		select n.nspname::text as schema, t.relname::text as table, con.conname::text as name, con.contype::text as kind,
+			array(select a.attname::text from unnest(con.conkey) with ordinality k(attnum, position)
+				join pg_attribute a on a.attrelid = con.conrelid and a.attnum = k.attnum order by k.position) as columns,
+			rn.nspname::text as referenced_schema, r.relname::text as referenced_table,
+			array(select a.attname::text from unnest(con.confkey) with ordinality k(attnum, position)
+				join pg_attribute a on a.attrelid = con.confrelid and a.attnum = k.attnum order by k.position) as referenced_columns,
+			pg_get_constraintdef(con.oid) as definition
+		from pg_constraint con
+		join pg_class t on t.oid = con.conrelid
+		join pg_namespace n on n.oid = t.relnamespace
+		left join pg_class r on r.oid = con.confrelid
+		left join pg_namespace rn on rn.oid = r.relnamespace
+		where {USER_NAMESPACES} and {}
+		order by 1, 2, 3
+	", not_from_extension("t.oid")), &[])?.into_iter().map(|row| {
+		let referenced_table: Option<String> = row.get("referenced_table");
+		json!({
+			"schema": row.get::<_, String>("schema"),
+			"table": row.get::<_, String>("table"),
+			"name": row.get::<_, String>("name"),
+			"kind": constraint_kind(&row.get::<_, String>("kind")),
+			"columns": row.get::<_, Vec<String>>("columns"),
+			"references": referenced_table.map(|referenced_table| json!({
+				"schema": row.get::<_, Option<String>>("referenced_schema"),
+				"table": referenced_table,
+				"columns": row.get::<_, Vec<String>>("referenced_columns"),
+			})),
+			"definition": row.get::<_, String>("definition"),
+		})
+	}).collect();
+
+	let enums: Vec<Value> = client.query(&format!("
+		select n.nspname::text as schema, t.typname::text as name,
+			array(select e.enumlabel::text from pg_enum e where e.enumtypid = t.oid order by e.enumsortorder) as values
+		from pg_type t join pg_namespace n on n.oid = t.typnamespace
+		where t.typtype = 'e' and {USER_NAMESPACES} and {}
+		order by 1, 2
+	", not_from_extension("t.oid")), &[])?.into_iter().map(|row| json!({
+		"schema": row.get::<_, String>("schema"),
+		"name": row.get::<_, String>("name"),
+		"values": row.get::<_, Vec<String>>("values"),
+	})).collect();
+
+	let functions: Vec<Value> = client.query(&format!("
+		select n.nspname::text as schema, p.proname::text as name, p.prokind::text as kind,
+			pg_get_function_identity_arguments(p.oid) as arguments, pg_get_function_result(p.oid) as returns,
+			l.lanname::text as language, obj_description(p.oid, 'pg_proc') as comment
+		from pg_proc p
+		join pg_namespace n on n.oid = p.pronamespace
+		join pg_language l on l.oid = p.prolang
+		where p.prokind in ('f', 'p') and {USER_NAMESPACES} and {}
+		order by 1, 2, 4
+	", not_from_extension("p.oid")), &[])?.into_iter().map(|row| json!({
+		"schema": row.get::<_, String>("schema"),
+		"name": row.get::<_, String>("name"),
+		"kind": if row.get::<_, String>("kind") == "p" { "procedure" } else { "function" },
+		"arguments": row.get::<_, String>("arguments"),
+		"returns": row.get::<_, Option<String>>("returns"),
+		"language": row.get::<_, String>("language"),
+		"comment": row.get::<_, Option<String>>("comment"),
+	})).collect();
+
+	let tables = nest(nest(tables, columns.clone(), "columns"), constraints, "constraints");
+	let views = nest(views, columns, "columns");
+	Ok(json!({ "tables": tables, "views": views, "enums": enums, "functions": functions }))
+}
+
+/// the catalog of the schema directory, applied to a temp database
+pub(crate) fn schema_catalog(args: &Args) -> Result<Value> {
+	let dbname = args.pg_url.get_dbname().ok_or_else(|| anyhow!("need a dbname to read the catalog of the schema"))?;
+	let schema = TempDb::new(args, dbname, "catalog")?;
+	apply_schema_files(args, &schema.config, list_sql_files(&args.schema_directory)?)?;
+	connections::with_client(&schema.config, introspect)
+}
+
+#[test]
+fn test_schema_catalog_failures() {
+	let args = super::test_args(&["--pg-url", "postgres://localhost", "export", "catalog"]);
+	assert_eq!(schema_catalog(&args).unwrap_err().to_string(), "need a dbname to read the catalog of the schema");
+
+	assert_eq!(nest(vec![json!({"schema": "public", "name": "fruit"})], vec![], "columns"), vec![json!({"schema": "public", "name": "fruit", "columns": []})]);
+	assert_eq!(constraint_kind("t"), "other");
+}
+
+#[test]
+#[ignore]
+fn test_schema_catalog() -> Result<()> {
+	let schema_directory = std::env::temp_dir().join("postgres_migrator_test_schema_catalog");
+	let _ = std::fs::remove_dir_all(&schema_directory);
+	std::fs::create_dir_all(&schema_directory)?;
+	let pg_url = std::env::var("PG_URL")?;
+	let args = super::test_args(&["--pg-url", &pg_url, "--schema-directory", schema_directory.to_str().unwrap(), "export", "catalog"]);
+
+	std::fs::write(schema_directory.join("fruit.sql"), "create table fruit (id int primary key, name text not null);")?;
+	let catalog = schema_catalog(&args)?;
+	assert_eq!(catalog["tables"][0]["name"], "fruit");
+	assert_eq!(catalog["tables"][0]["constraints"][0]["kind"], "primary key");
+	assert_eq!(catalog["views"], json!([]));
+
+	std::fs::write(schema_directory.join("vegetable.sql"), "create table vegetable (fruit_id int references fruit (color));")?;
+	let error = format!("{:#}", schema_catalog(&args).unwrap_err());
+	assert!(error.contains("unable to apply") && error.contains("vegetable.sql"), "{error}");
+
+	std::fs::remove_dir_all(&schema_directory)?;
+	Ok(())
+}
+
+pub(crate) fn export_catalog(args: &Args, format: CatalogFormat) -> Result<()> {
+	let catalog = schema_catalog(args)?;
+	match format {
+		CatalogFormat::Json => println!("{}", serde_json::to_string_pretty(&catalog)?),
+	}
+	Ok(())
+}
@@ -0,0 +1,829 @@
+use std::{fs, path::{Path, PathBuf}};
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDateTime;
+use postgres::Config;
+use regex::Regex;
+use postgres_migrator::{
+	ARCHIVE_DIRECTORY_NAME, DOWN_MIGRATION_SUFFIX, UP_MIGRATION_SUFFIX, compute_checksum, down_migration_path, get_null_string, has_sql_extension,
+	is_archived, is_down_migration, is_up_migration, migration_description, read_sql_file,
+};
+
+use super::{
+	Args, Backend, CHECKSUMS_FILE_NAME, MigrationFile, connections,
+	command_check, confirm, create_timestamp, gather_validated_migrations, list_usable_migration_files, make_slug, parse_checksums, query_actual_version, query_applied_versions,
+	read_checksums, record_checksum, redact_password, to_connection_string, write_checksums,
+};
+use super::statements::split_statements;
+use super::structure::{NO_TRANSACTION_DIRECTIVE, needs_no_transaction, runs_without_transaction};
+
+const VERSION_FORMAT: &str = "%Y%m%d%H%M%S";
+
+/// a migration file's name, without requiring it to be part of a valid chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MigrationName {
+	file_path: PathBuf,
+	current_version: String,
+	previous_version: String,
+	description: String,
+}
+
+impl MigrationName {
+	fn parse(file_path: &Path) -> Result<MigrationName> {
+		let display_file_path = file_path.display();
+		let file_name = file_path.file_name().and_then(|file_name| file_name.to_str())
+			.ok_or_else(|| anyhow!("file name isn't valid unicode: {display_file_path}"))?;
+		let portions: Vec<_> = file_name.split('.').collect();
+		let (current_version, previous_version) = match portions.as_slice() {
+			[current_version, previous_version, _, ..] => (current_version.to_string(), previous_version.to_string()),
+			_ => return Err(anyhow!("no version strings in this path: {display_file_path}")),
+		};
+		let description = migration_description(file_path);
+		Ok(MigrationName{file_path: file_path.to_path_buf(), current_version, previous_version, description})
+	}
+
+	fn file_stem(&self) -> String {
+		match self.description.is_empty() {
+			true => format!("{}.{}", self.current_version, self.previous_version),
+			false => format!("{}.{}.{}", self.current_version, self.previous_version, self.description),
+		}
+	}
+
+	/// keeps the `.up.sql` of a paired migration
+	fn file_name(&self) -> String {
+		match is_up_migration(&self.file_path) {
+			true => format!("{}{UP_MIGRATION_SUFFIX}", self.file_stem()),
+			false => format!("{}.sql", self.file_stem()),
+		}
+	}
+
+	fn down_file_name(&self) -> String {
+		format!("{}{DOWN_MIGRATION_SUFFIX}", self.file_stem())
+	}
+}
+
+#[test]
+fn test_migration_name() {
+	let name = MigrationName::parse(Path::new("migrations/20220102000000.20220101000000.add_color.sql")).unwrap();
+	assert_eq!(name, MigrationName{
+		file_path: PathBuf::from("migrations/20220102000000.20220101000000.add_color.sql"),
+		current_version: "20220102000000".into(),
+		previous_version: "20220101000000".into(),
+		description: "add_color".into(),
+	});
+	assert_eq!(name.file_name(), "20220102000000.20220101000000.add_color.sql");
+	assert_eq!(MigrationName::parse(Path::new("20220101000000.null.sql")).unwrap().file_name(), "20220101000000.null.sql");
+	assert!(MigrationName::parse(Path::new("migrations/yo.sql")).is_err());
+
+	let paired = MigrationName::parse(Path::new("migrations/20220102000000.20220101000000.add_color.up.sql")).unwrap();
+	assert_eq!(paired.description, "add_color");
+	assert_eq!(paired.file_name(), "20220102000000.20220101000000.add_color.up.sql");
+	assert_eq!(paired.down_file_name(), "20220102000000.20220101000000.add_color.down.sql");
+}
+
+/// splits migrations into the chain that can be followed from the first migration,
+/// always continuing with the earliest version when several migrations claim the same previous version,
+/// and the remaining migrations that branch off of it, sorted by version
+fn split_main_chain(mut names: Vec<MigrationName>) -> (Vec<MigrationName>, Vec<MigrationName>) {
+	names.sort_by(|a, b| a.current_version.cmp(&b.current_version));
+
+	let mut main_chain: Vec<MigrationName> = vec![];
+	loop {
+		let next = names.iter().position(|name| match main_chain.last() {
+			None => name.previous_version == get_null_string() || name.previous_version == "onboard",
+			Some(last) => name.previous_version == last.current_version,
+		});
+		match next {
+			Some(index) => main_chain.push(names.remove(index)),
+			None => return (main_chain, names),
+		}
+	}
+}
+
+#[test]
+fn test_split_main_chain() {
+	let name = |current_version: &str, previous_version: &str| MigrationName{
+		file_path: PathBuf::new(), description: String::new(),
+		current_version: current_version.into(), previous_version: previous_version.into(),
+	};
+
+	assert_eq!(split_main_chain(vec![]), (vec![], vec![]));
+	assert_eq!(
+		split_main_chain(vec![name("2", "1"), name("1", "null"), name("3", "2")]),
+		(vec![name("1", "null"), name("2", "1"), name("3", "2")], vec![]),
+	);
+	// two branches off of 1, then more work on each branch
+	assert_eq!(
+		split_main_chain(vec![name("1", "onboard"), name("2", "1"), name("3", "1"), name("4", "2"), name("5", "3")]),
+		(vec![name("1", "onboard"), name("2", "1"), name("4", "2")], vec![name("3", "1"), name("5", "3")]),
+	);
+}
+
+/// groups of migrations that claim the same previous version, each sorted by version, which happens
+/// when two branches both generated a migration and were merged
+fn find_divergences(names: &[MigrationName]) -> Vec<Vec<&MigrationName>> {
+	let mut by_previous_version: Vec<Vec<&MigrationName>> = vec![];
+	for name in names {
+		match by_previous_version.iter_mut().find(|group| group[0].previous_version == name.previous_version) {
+			Some(group) => group.push(name),
+			None => by_previous_version.push(vec![name]),
+		}
+	}
+	let mut divergences: Vec<_> = by_previous_version.into_iter().filter(|group| group.len() > 1).collect();
+	for group in divergences.iter_mut() {
+		group.sort_by(|a, b| a.current_version.cmp(&b.current_version));
+	}
+	divergences.sort_by(|a, b| a[0].previous_version.cmp(&b[0].previous_version));
+	divergences
+}
+
+#[test]
+fn test_find_divergences() {
+	let name = |current_version: &str, previous_version: &str| MigrationName{
+		file_path: PathBuf::new(), description: String::new(),
+		current_version: current_version.into(), previous_version: previous_version.into(),
+	};
+	assert!(find_divergences(&[name("1", "null"), name("2", "1"), name("3", "2")]).is_empty());
+	let names = [name("1", "null"), name("3", "1"), name("2", "1"), name("4", "2"), name("5", "4"), name("6", "4")];
+	assert_eq!(find_divergences(&names), vec![vec![&names[2], &names[1]], vec![&names[4], &names[5]]]);
+}
+
+/// explains the divergences in `file_paths`, if there are any
+fn describe_divergences(file_paths: &[PathBuf]) -> Option<String> {
+	let names = file_paths.iter()
+		.filter(|file_path| !is_down_migration(file_path))
+		.map(|file_path| MigrationName::parse(file_path))
+		.collect::<Result<Vec<_>>>().ok()?;
+	let divergences = find_divergences(&names);
+	if divergences.is_empty() {
+		return None;
+	}
+	let mut description = String::new();
+	for group in divergences {
+		description.push_str(&format!("these migrations all follow {}:\n", group[0].previous_version));
+		for name in group {
+			description.push_str(&format!("  {}\n", name.file_path.display()));
+		}
+	}
+	description.push_str("\nthe chain has branched, usually because two branches each generated a migration and were then merged.\n");
+	description.push_str("run `postgres_migrator rebase` to give the later migrations of each branch fresh versions after the end of the chain,\n");
+	description.push_str("then check that the schema is still right with `postgres_migrator check schema migrations`");
+	Some(description)
+}
+
+/// validates the chain of `file_paths`, explaining it when the chain has branched
+pub(crate) fn validate_chain(file_paths: Vec<PathBuf>) -> Result<Vec<MigrationFile>> {
+	let divergences = describe_divergences(&file_paths);
+	MigrationFile::vec_from_paths(file_paths).map_err(|error| match divergences {
+		Some(divergences) => anyhow!("the migration chain has branched\n\n{divergences}"),
+		None => error,
+	})
+}
+
+/// validates the migration chain without any database, for CI
+pub(crate) fn command_check_chain(args: &Args) -> Result<()> {
+	let (migration_files, current_version) = gather_validated_migrations(args)?;
+	match current_version {
+		Some(current_version) => println!("the migration chain is linear, {} migrations up to {current_version}", migration_files.len()),
+		None => println!("there are no migrations in {}", args.migrations_directory),
+	}
+	Ok(())
+}
+
+/// returns `count` sequential versions, all after both `after` and now
+fn versions_after(after: Option<&str>, count: usize) -> Result<Vec<String>> {
+	let now = NaiveDateTime::parse_from_str(&create_timestamp(), VERSION_FORMAT)?;
+	let start = match after {
+		None => now,
+		Some(after) => {
+			let after = NaiveDateTime::parse_from_str(after, VERSION_FORMAT)
+				.map_err(|err| anyhow!("invalid version {after}: {err}"))?;
+			std::cmp::max(now, after + chrono::Duration::seconds(1))
+		},
+	};
+	Ok((0..count).map(|offset| (start + chrono::Duration::seconds(offset as i64)).format(VERSION_FORMAT).to_string()).collect())
+}
+
+#[test]
+fn test_versions_after() {
+	assert_eq!(versions_after(Some("99990101000000"), 2).unwrap(), vec!["99990101000001", "99990101000002"]);
+
+	let versions = versions_after(Some("20220101000000"), 3).unwrap();
+	assert_eq!(versions.len(), 3);
+	assert!(versions[0] >= create_timestamp());
+	assert!(versions.windows(2).all(|pair| pair[0] < pair[1]));
+
+	assert!(versions_after(Some("yoyo"), 1).is_err());
+}
+
+/// renames each old migration in `restamped` (along with its down migration and checksum) to its new name
+fn rename_migrations(migrations_directory: &str, restamped: &[(MigrationName, MigrationName)], dry_run: bool) -> Result<()> {
+	let mut checksums = read_checksums(migrations_directory)?;
+	for (old, new) in restamped {
+		let new_file_path = old.file_path.with_file_name(new.file_name());
+		println!("{}{} -> {}", if dry_run { "would rename " } else { "" }, old.file_path.display(), new_file_path.display());
+		if dry_run {
+			continue;
+		}
+		fs::rename(&old.file_path, &new_file_path)?;
+
+		let old_down_path = old.file_path.with_file_name(old.down_file_name());
+		if old_down_path.exists() {
+			fs::rename(&old_down_path, new_file_path.with_file_name(new.down_file_name()))?;
+		}
+
+		if let Some(checksums) = checksums.as_mut() {
+			if let Some(checksum) = checksums.remove(&old.current_version) {
+				checksums.insert(new.current_version.clone(), checksum);
+			}
+		}
+	}
+
+	if let (Some(checksums), false) = (checksums, dry_run) {
+		write_checksums(migrations_directory, &checksums)?;
+	}
+	Ok(())
+}
+
+/// relinks the migrations that don't belong to the main chain after its end, with fresh versions
+fn plan_rebase(names: Vec<MigrationName>) -> Result<Vec<(MigrationName, MigrationName)>> {
+	let (main_chain, branched) = split_main_chain(names);
+	let new_versions = versions_after(main_chain.last().map(|last| last.current_version.as_str()), branched.len())?;
+
+	let mut previous_version = main_chain.last().map(|last| last.current_version.clone()).unwrap_or_else(get_null_string);
+	let mut restamped = vec![];
+	for (old, current_version) in branched.into_iter().zip(new_versions) {
+		let new = MigrationName{current_version: current_version.clone(), previous_version, ..old.clone()};
+		previous_version = current_version;
+		restamped.push((old, new));
+	}
+	Ok(restamped)
+}
+
+#[test]
+fn test_plan_rebase() {
+	let name = |current_version: &str, previous_version: &str| MigrationName{
+		file_path: PathBuf::from(format!("migrations/{current_version}.{previous_version}.yo.sql")), description: "yo".into(),
+		current_version: current_version.into(), previous_version: previous_version.into(),
+	};
+	let versions = versions_after(Some("99990101000004"), 2).unwrap();
+
+	assert_eq!(plan_rebase(vec![name("99990101000001", "null"), name("99990101000002", "99990101000001")]).unwrap(), vec![]);
+	assert_eq!(
+		plan_rebase(vec![
+			name("99990101000001", "null"), name("99990101000002", "99990101000001"), name("99990101000003", "99990101000001"),
+			name("99990101000004", "99990101000002"), name("99990101000005", "99990101000003"),
+		]).unwrap(),
+		vec![
+			(name("99990101000003", "99990101000001"), MigrationName{
+				current_version: versions[0].clone(), previous_version: "99990101000004".into(), ..name("99990101000003", "99990101000001")
+			}),
+			(name("99990101000005", "99990101000003"), MigrationName{
+				current_version: versions[1].clone(), previous_version: versions[0].clone(), ..name("99990101000005", "99990101000003")
+			}),
+		],
+	);
+}
+
+pub(crate) fn command_rebase(args: &Args, dry_run: bool) -> Result<()> {
+	let names = list_usable_migration_files(args)?.iter()
+		.map(|file_path| MigrationName::parse(file_path))
+		.collect::<Result<Vec<_>>>()?;
+
+	let restamped = plan_rebase(names)?;
+	if restamped.is_empty() {
+		println!("migration chain is already linear, nothing to rebase");
+		return Ok(());
+	}
+
+	for (old, new) in &restamped {
+		println!("restamping {} as {} after {}", old.current_version, new.current_version, new.previous_version);
+	}
+	rename_migrations(&args.migrations_directory, &restamped, dry_run)
+}
+
+
+fn run_git(git_args: &[&str]) -> Result<std::process::Output> {
+	std::process::Command::new("git").args(git_args).output()
+		.map_err(|err| anyhow!("unable to run git: {err}"))
+}
+
+/// parses `git diff --name-status` output into the paths that existed before and were modified, deleted, or renamed.
+/// a file moved to another directory unchanged, such as by `archive`, keeps its name and so isn't a change
+fn parse_changed_paths(name_status: &str) -> Vec<PathBuf> {
+	name_status.lines().filter_map(|line| {
+		let mut fields = line.split('\t');
+		let status = fields.next()?;
+		let path = fields.next()?;
+		match status.chars().next()? {
+			'R' if status == "R100" && fields.next().is_some_and(|new_path| Path::new(new_path).file_name() == Path::new(path).file_name()) => None,
+			'M' | 'D' | 'R' | 'T' => Some(PathBuf::from(path)),
+			_ => None,
+		}
+	}).collect()
+}
+
+#[test]
+fn test_parse_changed_paths() {
+	let name_status = "M\tmigrations/20220101000000.null.a.sql\nA\tmigrations/20220103000000.20220102000000.c.sql\nD\tmigrations/20220102000000.20220101000000.b.sql\nR087\tmigrations/20220104000000.20220103000000.d.sql\tmigrations/20220105000000.20220103000000.d.sql\n";
+	assert_eq!(parse_changed_paths(name_status), vec![
+		PathBuf::from("migrations/20220101000000.null.a.sql"),
+		PathBuf::from("migrations/20220102000000.20220101000000.b.sql"),
+		PathBuf::from("migrations/20220104000000.20220103000000.d.sql"),
+	]);
+	assert_eq!(parse_changed_paths(""), Vec::<PathBuf>::new());
+	assert_eq!(parse_changed_paths("R100\tmigrations/20220101000000.null.a.sql\tmigrations/archive/20220101000000.null.a.sql\n"), Vec::<PathBuf>::new());
+}
+
+/// `base:./migrations/migrations.lock`, git wants forward slashes even on windows
+fn git_object_path(base: &str, migrations_directory: &str) -> String {
+	let migrations_directory = migrations_directory.replace('\\', "/");
+	format!("{base}:./{}/{CHECKSUMS_FILE_NAME}", migrations_directory.trim_end_matches('/'))
+}
+
+#[test]
+fn test_git_object_path() {
+	assert_eq!(git_object_path("origin/main", "migrations"), "origin/main:./migrations/migrations.lock");
+	assert_eq!(git_object_path("HEAD~1", "db\\migrations\\"), "HEAD~1:./db/migrations/migrations.lock");
+}
+
+pub(crate) fn command_check_immutable(args: &Args, base: &str, database: bool) -> Result<()> {
+	let diff = run_git(&["diff", "--name-status", "--relative", "-M", base, "--", &args.migrations_directory])?;
+	if !diff.status.success() {
+		return Err(anyhow!("git diff against {base} failed:\n{}", String::from_utf8_lossy(&diff.stderr)));
+	}
+
+	let changed: Vec<MigrationName> = parse_changed_paths(&String::from_utf8_lossy(&diff.stdout)).iter()
+		.filter(|path| has_sql_extension(path) && !is_down_migration(path))
+		.map(|path| MigrationName::parse(path))
+		.collect::<Result<_>>()?;
+
+	let base_checksums_path = git_object_path(base, &args.migrations_directory);
+	let base_checksums = run_git(&["show", &base_checksums_path])?;
+	let mut immutable_versions: Vec<String> = match base_checksums.status.success() {
+		true => parse_checksums(&String::from_utf8_lossy(&base_checksums.stdout), &base_checksums_path)?.into_keys().collect(),
+		// without a checksums file, every migration that existed in base is considered merged
+		false => changed.iter().map(|name| name.current_version.clone()).collect(),
+	};
+	if database {
+		let mut client = connections::connect(&args.pg_url)?;
+		immutable_versions.extend(query_applied_versions(&mut client)?);
+	}
+
+	let violations: Vec<_> = changed.iter()
+		.filter(|name| immutable_versions.contains(&name.current_version))
+		.map(|name| name.file_path.display().to_string())
+		.collect();
+	match violations.is_empty() {
+		true => {
+			println!("no merged or applied migrations have been changed relative to {base}");
+			Ok(())
+		},
+		false => Err(anyhow!(
+			"these migrations have already been merged or applied, but were changed relative to {base}:\n{}\n\nmake a new migration instead",
+			violations.join("\n"),
+		)),
+	}
+}
+
+/// fails if any of `migration_files` has been archived, applied to the database or any of `applied_urls`,
+/// or merged into `base`, telling the user to `remedy` it
+fn ensure_unshipped(args: &Args, migration_files: &[&MigrationFile], base: Option<&str>, applied_urls: &[Config], remedy: &str) -> Result<()> {
+	for MigrationFile{file_path, display_file_path, ..} in migration_files {
+		if is_archived(&args.migrations_directory, file_path) {
+			return Err(anyhow!("{display_file_path} has been archived, {remedy}"));
+		}
+	}
+
+	for config in std::iter::once(&args.pg_url).chain(applied_urls) {
+		let applied_versions = query_applied_versions(&mut connections::connect(config)?)?;
+		if let Some(MigrationFile{display_file_path, ..}) = migration_files.iter().find(|migration_file| applied_versions.contains(&migration_file.current_version)) {
+			return Err(anyhow!("{display_file_path} has already been applied to {}, {remedy}", to_connection_string(&redact_password(config))));
+		}
+	}
+	if let Some(base) = base {
+		let base_checksums_path = git_object_path(base, &args.migrations_directory);
+		let base_checksums = run_git(&["show", &base_checksums_path])?;
+		if base_checksums.status.success() {
+			let merged_versions = parse_checksums(&String::from_utf8_lossy(&base_checksums.stdout), &base_checksums_path)?;
+			if let Some(MigrationFile{display_file_path, ..}) = migration_files.iter().find(|migration_file| merged_versions.contains_key(&migration_file.current_version)) {
+				return Err(anyhow!("{display_file_path} has already been merged into {base}, {remedy}"));
+			}
+		}
+	}
+	Ok(())
+}
+
+/// accepts a hand edit of a migration that hasn't been merged or applied anywhere known yet,
+/// once the migrations still produce the schema, by recording the edited file's checksum
+pub(crate) fn command_amend(args: &Args, version: Option<&str>, base: Option<&str>, applied_urls: &[Config]) -> Result<()> {
+	let (migration_files, _) = gather_validated_migrations(args)?;
+	let amended = match version {
+		Some(version) => migration_files.iter().find(|migration_file| migration_file.current_version == version)
+			.ok_or_else(|| anyhow!("there's no migration with version {version} in {}", args.migrations_directory))?,
+		None => migration_files.last().ok_or_else(|| anyhow!("there are no migrations in {}", args.migrations_directory))?,
+	};
+	ensure_unshipped(args, &[amended], base, applied_urls, "make a new migration instead")?;
+	let MigrationFile{file_path, display_file_path, current_version, ..} = amended;
+
+	command_check(args, Backend::Schema, Backend::Migrations)
+		.with_context(|| format!("with the edit to {display_file_path}, the migrations no longer produce the schema"))?;
+	record_checksum(&args.migrations_directory, current_version, &fs::read(file_path)?)?;
+	println!("amended {display_file_path}, its new checksum is recorded in {CHECKSUMS_FILE_NAME}");
+	Ok(())
+}
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct MergeOptions {
+	/// version of the first migration to merge
+	from_version: String,
+	/// version of the last migration to merge, the latest one by default
+	to_version: Option<String>,
+	/// description of the merged migration, the first merged migration's by default
+	#[clap(long)]
+	description: Option<String>,
+	/// git ref whose checksums file lists the migrations that have been merged, and so can't be merged with others
+	#[clap(long)]
+	base: Option<String>,
+	/// connection string of another database the migrations must not have been applied to yet, such as staging,
+	/// can be given multiple times
+	#[clap(long = "applied-url", multiple_occurrences = true, parse(try_from_str = super::config_try_from_str))]
+	applied_urls: Vec<Config>,
+	/// only print the migrations that would be merged
+	#[clap(long)]
+	dry_run: bool,
+}
+
+/// the consecutive migrations from `from_version` up to `to_version`, or up to the latest one
+fn merge_range<'a>(migration_files: &'a [MigrationFile], from_version: &str, to_version: Option<&str>) -> Result<&'a [MigrationFile]> {
+	let position = |version: &str| migration_files.iter().position(|migration_file| migration_file.current_version == version)
+		.ok_or_else(|| anyhow!("there's no migration with version {version}"));
+	let start = position(from_version)?;
+	let end = match to_version {
+		Some(to_version) => position(to_version)?,
+		None => migration_files.len() - 1,
+	};
+	if end <= start {
+		return Err(anyhow!("there are no migrations after {from_version} to merge it with"));
+	}
+	Ok(&migration_files[start..=end])
+}
+
+/// `parts`, each the file name and sql of a migration, one after another under a comment naming the file it came from
+fn merged_sql(parts: &[(String, String)]) -> String {
+	parts.iter().map(|(file_name, sql)| {
+		let sql = sql.trim();
+		// a last statement without a semicolon would run into the next migration's first one
+		let terminator = if sql.is_empty() || sql.ends_with(';') { "" } else { "\n;" };
+		format!("-- merged from {file_name}\n{sql}{terminator}\n")
+	}).collect::<Vec<_>>().join("\n")
+}
+
+#[test]
+fn test_merge() {
+	let migration_files = MigrationFile::vec_from_paths(vec![
+		PathBuf::from("migrations/20220101000000.null.a.sql"),
+		PathBuf::from("migrations/20220102000000.20220101000000.b.sql"),
+		PathBuf::from("migrations/20220103000000.20220102000000.c.sql"),
+	]).unwrap();
+	let versions = |range: &[MigrationFile]| range.iter().map(|migration_file| migration_file.current_version.clone()).collect::<Vec<_>>();
+	assert_eq!(versions(merge_range(&migration_files, "20220102000000", None).unwrap()), vec!["20220102000000", "20220103000000"]);
+	assert_eq!(versions(merge_range(&migration_files, "20220101000000", Some("20220102000000")).unwrap()), vec!["20220101000000", "20220102000000"]);
+	assert!(merge_range(&migration_files, "20220103000000", None).is_err());
+	assert!(merge_range(&migration_files, "20220102000000", Some("20220101000000")).is_err());
+	assert!(merge_range(&migration_files, "20220104000000", None).is_err());
+
+	assert_eq!(
+		merged_sql(&[("a.sql".into(), "create table fruit ();\n".into()), ("b.sql".into(), "alter table fruit add name text\n-- done\n".into())]),
+		"-- merged from a.sql\ncreate table fruit ();\n\n-- merged from b.sql\nalter table fruit add name text\n-- done\n;\n",
+	);
+}
+
+/// concatenates consecutive migrations that haven't shipped anywhere known yet into a single migration
+/// with the version of the last one, so the migrations after them still follow it
+pub(crate) fn command_merge(args: &Args, options: &MergeOptions) -> Result<()> {
+	let MergeOptions{from_version, to_version, description, base, applied_urls, dry_run} = options;
+	let (migration_files, _) = gather_validated_migrations(args)?;
+	let merged: Vec<&MigrationFile> = merge_range(&migration_files, from_version, to_version.as_deref())?.iter().collect();
+	ensure_unshipped(args, &merged, base.as_deref(), applied_urls, "only migrations that haven't shipped can be merged")?;
+
+	let (first, last) = (MigrationName::parse(&merged[0].file_path)?, MigrationName::parse(&merged[merged.len() - 1].file_path)?);
+	let mut parts = vec![];
+	let mut down_parts = vec![];
+	for MigrationFile{file_path, ..} in &merged {
+		let file_name = MigrationName::parse(file_path)?.file_name();
+		parts.push((file_name.clone(), read_sql_file(file_path)?));
+		let down_path = down_migration_path(file_path);
+		if down_path.exists() {
+			down_parts.push((down_path.file_name().unwrap_or_default().to_string_lossy().to_string(), read_sql_file(&down_path)?));
+		}
+	}
+	let without_transaction: Vec<bool> = parts.iter().map(|(_, sql)| runs_without_transaction(sql)).collect();
+	if without_transaction.iter().any(|&without| without) && !without_transaction.iter().all(|&without| without) {
+		return Err(anyhow!("some of these migrations run without a transaction and some don't, so they can't be merged into one"));
+	}
+	// downs undo the migrations in reverse
+	down_parts.reverse();
+
+	let merged_name = MigrationName{
+		file_path: last.file_path.clone(),
+		current_version: last.current_version.clone(),
+		previous_version: first.previous_version.clone(),
+		description: description.as_deref().map(make_slug).unwrap_or_else(|| first.description.clone()),
+	};
+	let merged_file_name = match merged.iter().any(|migration_file| is_up_migration(&migration_file.file_path)) {
+		true => format!("{}{UP_MIGRATION_SUFFIX}", merged_name.file_stem()),
+		false => format!("{}.sql", merged_name.file_stem()),
+	};
+	let merged_path = last.file_path.with_file_name(merged_file_name);
+	for MigrationFile{display_file_path, ..} in &merged {
+		println!("{}{display_file_path}", if *dry_run { "would merge " } else { "merging " });
+	}
+	if *dry_run {
+		println!("into {}", merged_path.display());
+		return Ok(());
+	}
+
+	for MigrationFile{file_path, ..} in &merged {
+		fs::remove_file(file_path)?;
+		let down_path = down_migration_path(file_path);
+		if down_path.exists() {
+			fs::remove_file(down_path)?;
+		}
+		// leaves no empty subdirectories behind, remove_dir refuses ones that still have files
+		if let Some(directory) = file_path.parent().filter(|directory| *directory != Path::new(&args.migrations_directory)) {
+			let _ = fs::remove_dir(directory);
+		}
+	}
+	if let Some(parent) = merged_path.parent() {
+		fs::create_dir_all(parent)?;
+	}
+	let sql = merged_sql(&parts);
+	fs::write(&merged_path, &sql)?;
+	if !down_parts.is_empty() {
+		fs::write(merged_path.with_file_name(merged_name.down_file_name()), merged_sql(&down_parts))?;
+	}
+	if let Some(mut checksums) = read_checksums(&args.migrations_directory)? {
+		checksums.retain(|version, _| !merged.iter().any(|migration_file| &migration_file.current_version == version));
+		checksums.insert(merged_name.current_version.clone(), compute_checksum(sql.as_bytes()));
+		write_checksums(&args.migrations_directory, &checksums)?;
+	}
+	println!("merged {} migrations into {}", merged.len(), merged_path.display());
+	Ok(())
+}
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct SplitOptions {
+	/// version of the migration to split, the latest one by default
+	version: Option<String>,
+	/// pick the statements that start a new migration one by one, instead of using the `-- split` comments
+	#[clap(long)]
+	interactive: bool,
+	/// git ref whose checksums file lists the migrations that have been merged, and so can't be split
+	#[clap(long)]
+	base: Option<String>,
+	/// connection string of another database the migration must not have been applied to yet, such as staging,
+	/// can be given multiple times
+	#[clap(long = "applied-url", multiple_occurrences = true, parse(try_from_str = super::config_try_from_str))]
+	applied_urls: Vec<Config>,
+	/// only print the migrations the split would write
+	#[clap(long)]
+	dry_run: bool,
+}
+
+/// a line of a migration that starts a new migration when it's split, with the description that migration gets
+type SplitPoint = (usize, Option<String>);
+
+fn split_marker() -> Regex {
+	Regex::new(r"(?i)^--\s*split\b\s*:?\s*(.*)$").unwrap()
+}
+
+/// the `-- split` comment lines of `sql`, which can describe the migration that follows them as in `-- split: add index`
+fn split_markers(sql: &str) -> Vec<SplitPoint> {
+	let marker = split_marker();
+	sql.lines().enumerate()
+		.filter_map(|(index, line)| marker.captures(line.trim()).map(|captures| {
+			let description = captures[1].trim();
+			(index + 1, Some(description.to_string()).filter(|description| !description.is_empty()))
+		}))
+		.collect()
+}
+
+/// `sql` cut before each of `points`, leaving out `-- split` comments, as the description and sql of each part
+fn split_parts(sql: &str, points: &[SplitPoint]) -> Result<Vec<(Option<String>, String)>> {
+	let statements = split_statements(sql)?;
+	for (line, _) in points {
+		let spanning = statements.iter().find(|statement| statement.line < *line && *line < statement.line + statement.sql.lines().count());
+		if let Some(statement) = spanning {
+			return Err(anyhow!("line {line} is inside the statement starting on line {}, migrations can only be split between statements", statement.line));
+		}
+	}
+
+	let marker = split_marker();
+	let mut parts = vec![(None, String::new())];
+	for (index, line) in sql.split_inclusive('\n').enumerate() {
+		if let Some((_, description)) = points.iter().find(|(point_line, _)| *point_line == index + 1) {
+			parts.push((description.clone(), String::new()));
+			if marker.is_match(line.trim()) {
+				continue;
+			}
+		}
+		parts.last_mut().unwrap().1.push_str(line);
+	}
+	for (number, (_, part_sql)) in parts.iter_mut().enumerate() {
+		if split_statements(part_sql)?.is_empty() {
+			return Err(anyhow!("part {} of the split has no statements", number + 1));
+		}
+		*part_sql = format!("{}\n", part_sql.trim());
+	}
+	Ok(parts)
+}
+
+/// `count` consecutive versions that end with `current_version`, all after `previous_version`
+fn versions_ending_with(previous_version: &str, current_version: &str, count: usize) -> Result<Vec<String>> {
+	let current = NaiveDateTime::parse_from_str(current_version, VERSION_FORMAT)
+		.map_err(|err| anyhow!("invalid version {current_version}: {err}"))?;
+	let versions: Vec<String> = (0..count).rev()
+		.map(|offset| (current - chrono::Duration::seconds(offset as i64)).format(VERSION_FORMAT).to_string())
+		.collect();
+	// null and onboard come before every version
+	let after_previous = NaiveDateTime::parse_from_str(previous_version, VERSION_FORMAT).is_err() || versions[0].as_str() > previous_version;
+	match after_previous {
+		true => Ok(versions),
+		false => Err(anyhow!("there's no room for {count} versions between {previous_version} and {current_version}")),
+	}
+}
+
+#[test]
+fn test_split() {
+	let sql = "create table fruit (name text);\n-- split: index fruit\n-- no-transaction\ncreate index concurrently fruit_name on fruit (name);\n\n-- SPLIT\ncreate function f() returns int as $$\n-- split\nselect 1;\n$$ language sql;\n";
+	let markers = split_markers(sql);
+	assert_eq!(markers, vec![(2, Some("index fruit".into())), (6, None), (8, None)]);
+	assert!(split_parts(sql, &markers).unwrap_err().to_string().contains("line 8 is inside the statement starting on line 7"));
+	assert_eq!(split_parts(sql, &markers[..2]).unwrap(), vec![
+		(None, "create table fruit (name text);\n".into()),
+		(Some("index fruit".into()), "-- no-transaction\ncreate index concurrently fruit_name on fruit (name);\n".into()),
+		(None, "create function f() returns int as $$\n-- split\nselect 1;\n$$ language sql;\n".into()),
+	]);
+	assert_eq!(split_parts(sql, &[(4, None)]).unwrap()[1].1, "create index concurrently fruit_name on fruit (name);\n\n-- SPLIT\ncreate function f() returns int as $$\n-- split\nselect 1;\n$$ language sql;\n");
+	assert!(split_parts("-- split\ncreate table fruit ();", &[(1, None)]).unwrap_err().to_string().contains("part 1 of the split has no statements"));
+
+	assert_eq!(versions_ending_with("20220101000000", "20220101000300", 3).unwrap(), vec!["20220101000258", "20220101000259", "20220101000300"]);
+	assert_eq!(versions_ending_with("null", "20220101000000", 2).unwrap(), vec!["20211231235959", "20220101000000"]);
+	assert!(versions_ending_with("20220101000000", "20220101000001", 3).is_err());
+}
+
+/// asks, for each statement after the first, whether it starts a new migration
+fn pick_split_points(sql: &str) -> Result<Vec<SplitPoint>> {
+	let mut points = vec![];
+	for statement in split_statements(sql)?.into_iter().skip(1) {
+		let mut lines: Vec<&str> = statement.sql.lines().take(6).collect();
+		if statement.sql.lines().count() > lines.len() {
+			lines.push("...");
+		}
+		println!("\nline {}:\n{}", statement.line, lines.join("\n"));
+		if confirm("start a new migration with this statement?")? {
+			points.push((statement.line, None));
+		}
+	}
+	Ok(points)
+}
+
+/// splits a migration that hasn't shipped anywhere known yet into consecutive migrations,
+/// the last of which keeps its version so the migrations after it still follow it
+pub(crate) fn command_split(args: &Args, options: &SplitOptions) -> Result<()> {
+	let SplitOptions{version, interactive, base, applied_urls, dry_run} = options;
+	let (migration_files, _) = gather_validated_migrations(args)?;
+	let split = match version {
+		Some(version) => migration_files.iter().find(|migration_file| &migration_file.current_version == version)
+			.ok_or_else(|| anyhow!("there's no migration with version {version} in {}", args.migrations_directory))?,
+		None => migration_files.last().ok_or_else(|| anyhow!("there are no migrations in {}", args.migrations_directory))?,
+	};
+	let MigrationFile{file_path, display_file_path, ..} = split;
+	ensure_unshipped(args, &[split], base.as_deref(), applied_urls, "only migrations that haven't shipped can be split")?;
+	if down_migration_path(file_path).exists() {
+		return Err(anyhow!("{display_file_path} has a down migration, which can't be split along with it"));
+	}
+
+	let sql = read_sql_file(file_path)?;
+	let points = match interactive {
+		true => pick_split_points(&sql)?,
+		false => split_markers(&sql),
+	};
+	if points.is_empty() {
+		return Err(anyhow!("{display_file_path} has nowhere to split, add a `-- split` line between statements or use --interactive"));
+	}
+	let parts = split_parts(&sql, &points).with_context(|| format!("unable to split {display_file_path}"))?;
+
+	let original = MigrationName::parse(file_path)?;
+	let versions = versions_ending_with(&original.previous_version, &original.current_version, parts.len())?;
+	let mut previous_version = original.previous_version.clone();
+	let mut written = vec![];
+	for (number, ((description, part_sql), current_version)) in parts.into_iter().zip(versions).enumerate() {
+		let description = match (description, number) {
+			(Some(description), _) => make_slug(&description),
+			(None, 0) => original.description.clone(),
+			(None, number) => format!("{}_{}", original.description, number + 1),
+		};
+		let name = MigrationName{current_version: current_version.clone(), previous_version, description, ..original.clone()};
+		// a part that ends up with a statement that can't run in a transaction, or of a migration that ran without one, runs without one too
+		let part_sql = match (runs_without_transaction(&sql) || needs_no_transaction(&part_sql)) && !runs_without_transaction(&part_sql) {
+			true => format!("{NO_TRANSACTION_DIRECTIVE}\n{part_sql}"),
+			false => part_sql,
+		};
+		written.push((file_path.with_file_name(name.file_name()), part_sql));
+		previous_version = current_version;
+	}
+
+	for (new_file_path, _) in &written {
+		println!("{}{display_file_path} -> {}", if *dry_run { "would split " } else { "" }, new_file_path.display());
+	}
+	if *dry_run {
+		return Ok(());
+	}
+	fs::remove_file(file_path)?;
+	let mut checksums = read_checksums(&args.migrations_directory)?;
+	if let Some(checksums) = checksums.as_mut() {
+		checksums.remove(&original.current_version);
+	}
+	for (new_file_path, part_sql) in &written {
+		fs::write(new_file_path, part_sql)?;
+		if let Some(checksums) = checksums.as_mut() {
+			let version = MigrationName::parse(new_file_path)?.current_version;
+			checksums.insert(version, compute_checksum(part_sql.as_bytes()));
+		}
+	}
+	if let Some(checksums) = checksums {
+		write_checksums(&args.migrations_directory, &checksums)?;
+	}
+	println!("split {display_file_path} into {} migrations", written.len());
+	Ok(())
+}
+
+/// where each migration up to and including `archived_version` that isn't archived yet is moved,
+/// keeping its subdirectory within the archive
+fn plan_archive(migrations_directory: &str, migration_files: &[MigrationFile], archived_version: &str) -> Result<Vec<(PathBuf, PathBuf)>> {
+	let archive_directory = Path::new(migrations_directory).join(ARCHIVE_DIRECTORY_NAME);
+	migration_files.iter()
+		.filter(|migration_file| migration_file.current_version.as_str() <= archived_version && !is_archived(migrations_directory, &migration_file.file_path))
+		.map(|migration_file| {
+			let relative_path = migration_file.file_path.strip_prefix(migrations_directory)
+				.with_context(|| format!("{} isn't in {migrations_directory}", migration_file.display_file_path))?;
+			Ok((migration_file.file_path.clone(), archive_directory.join(relative_path)))
+		})
+		.collect()
+}
+
+#[test]
+fn test_plan_archive() {
+	let migration_files = MigrationFile::vec_from_paths(vec![
+		PathBuf::from("migrations/archive/20220101000000.null.a.sql"),
+		PathBuf::from("migrations/2022/20220102000000.20220101000000.b.sql"),
+		PathBuf::from("migrations/20220103000000.20220102000000.c.sql"),
+		PathBuf::from("migrations/20220104000000.20220103000000.d.sql"),
+	]).unwrap();
+	assert_eq!(plan_archive("migrations", &migration_files, "20220103000000").unwrap(), vec![
+		(PathBuf::from("migrations/2022/20220102000000.20220101000000.b.sql"), PathBuf::from("migrations/archive/2022/20220102000000.20220101000000.b.sql")),
+		(PathBuf::from("migrations/20220103000000.20220102000000.c.sql"), PathBuf::from("migrations/archive/20220103000000.20220102000000.c.sql")),
+	]);
+	assert_eq!(plan_archive("migrations", &migration_files, "20220101000000").unwrap(), vec![]);
+}
+
+/// moves the migrations that the database and every `applied_urls` database have all applied into the archive,
+/// along with their down migrations
+pub(crate) fn command_archive(args: &Args, applied_urls: &[Config], dry_run: bool) -> Result<()> {
+	let (migration_files, _) = gather_validated_migrations(args)?;
+	let actual_version = |config: &Config| query_actual_version(&mut connections::connect(config)?)?
+		.ok_or_else(|| anyhow!("{} hasn't been migrated yet, so it still needs every migration", to_connection_string(&redact_password(config))));
+	let mut archived_version = actual_version(&args.pg_url)?;
+	for config in applied_urls {
+		archived_version = archived_version.min(actual_version(config)?);
+	}
+
+	let moves = plan_archive(&args.migrations_directory, &migration_files, &archived_version)?;
+	if moves.is_empty() {
+		println!("every migration up to {archived_version} is already archived");
+		return Ok(());
+	}
+	for (old_path, new_path) in &moves {
+		println!("{}{} -> {}", if dry_run { "would move " } else { "" }, old_path.display(), new_path.display());
+		if dry_run {
+			continue;
+		}
+		let new_directory = new_path.parent().unwrap_or(Path::new(&args.migrations_directory));
+		fs::create_dir_all(new_directory)?;
+		fs::rename(old_path, new_path)?;
+		let down_file_name = MigrationName::parse(old_path)?.down_file_name();
+		let old_down_path = old_path.with_file_name(&down_file_name);
+		if old_down_path.exists() {
+			fs::rename(&old_down_path, new_directory.join(&down_file_name))?;
+		}
+		// leaves no empty subdirectories behind, remove_dir refuses ones that still have files
+		if let Some(old_directory) = old_path.parent().filter(|directory| *directory != Path::new(&args.migrations_directory)) {
+			let _ = fs::remove_dir(old_directory);
+		}
+	}
+	if !dry_run {
+		println!("archived {} migrations up to {archived_version}", moves.len());
+	}
+	Ok(())
+}
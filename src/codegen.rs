@@ -0,0 +1,271 @@
+//! Types for application code generated from the catalog of the schema directory.
+
+use std::fs;
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use super::Args;
+use super::catalog::schema_catalog;
+
+#[derive(clap::Subcommand, Debug)]
+pub(crate) enum CodegenTarget {
+	/// writes an interface for every table and view, and a type for every enum
+	Typescript(TypescriptOptions),
+}
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct TypescriptOptions {
+	/// file to write the types to, instead of printing them
+	#[clap(long)]
+	output: Option<String>,
+	/// how date and timestamp columns are typed, `string` for json apis or `date` for drivers like node-postgres
+	#[clap(long, arg_enum, default_value = "string")]
+	dates: DateMapping,
+	/// how numeric and bigint columns are typed, which can be too big or too precise for a javascript number
+	#[clap(long, arg_enum, default_value = "string")]
+	numerics: NumericMapping,
+	/// whether enums are written as unions of their values or as typescript enums
+	#[clap(long, arg_enum, default_value = "union")]
+	enum_style: EnumStyle,
+	/// also make nullable columns optional properties, for rows that are built rather than queried
+	#[clap(long)]
+	optional_nullable: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+pub(crate) enum DateMapping {
+	String,
+	Date,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+pub(crate) enum NumericMapping {
+	String,
+	Number,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+pub(crate) enum EnumStyle {
+	Union,
+	Enum,
+}
+
+/// `fruit_basket` as `FruitBasket`, prefixed with the schema outside of public
+fn pascal_case(schema: &str, name: &str) -> String {
+	let words = match schema {
+		"public" => name.to_string(),
+		schema => format!("{schema}_{name}"),
+	};
+	words.split(|character: char| !character.is_alphanumeric())
+		.filter(|word| !word.is_empty())
+		.map(|word| {
+			let mut characters = word.chars();
+			characters.next().map_or(String::new(), |first| first.to_uppercase().chain(characters).collect())
+		})
+		.collect()
+}
+
+fn is_identifier(name: &str) -> bool {
+	name.chars().next().is_some_and(|first| first.is_alphabetic() || first == '_' || first == '$')
+		&& name.chars().all(|character| character.is_alphanumeric() || character == '_' || character == '$')
+}
+
+fn quote_string(text: &str) -> String {
+	format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn property_name(name: &str) -> String {
+	match is_identifier(name) {
+		true => name.to_string(),
+		false => quote_string(name),
+	}
+}
+
+/// the typescript type of a column of `pg_type`, as `format_type` writes it
+fn typescript_type(options: &TypescriptOptions, enums: &[Value], pg_type: &str) -> String {
+	if let Some(element_type) = pg_type.strip_suffix("[]") {
+		let element_type = typescript_type(options, enums, element_type);
+		return match element_type.contains(' ') {
+			true => format!("({element_type})[]"),
+			false => format!("{element_type}[]"),
+		};
+	}
+	let base_type = pg_type.split('(').next().unwrap_or(pg_type).trim();
+	let unquoted = base_type.replace('"', "");
+	let found_enum = enums.iter().find(|found| {
+		let (schema, name) = (found["schema"].as_str().unwrap_or_default(), found["name"].as_str().unwrap_or_default());
+		// format_type only qualifies types outside of the search path
+		unquoted == format!("{schema}.{name}") || (schema == "public" && unquoted == name)
+	});
+	if let Some(found_enum) = found_enum {
+		return pascal_case(found_enum["schema"].as_str().unwrap_or_default(), found_enum["name"].as_str().unwrap_or_default());
+	}
+
+	let numeric = match options.numerics {
+		NumericMapping::String => "string",
+		NumericMapping::Number => "number",
+	};
+	let date = match options.dates {
+		DateMapping::String => "string",
+		DateMapping::Date => "Date",
+	};
+	match base_type {
+		"smallint" | "integer" | "real" | "double precision" | "oid" => "number",
+		"bigint" | "numeric" | "money" => numeric,
+		"boolean" => "boolean",
+		"date" | "timestamp without time zone" | "timestamp with time zone" => date,
+		"text" | "character varying" | "character" | "\"char\"" | "name" | "citext" | "uuid" | "inet" | "cidr" | "macaddr" | "macaddr8"
+			| "interval" | "time without time zone" | "time with time zone" | "bytea" | "bit" | "bit varying" | "tsvector" | "xml" => "string",
+		_ => "unknown",
+	}.to_string()
+}
+
+fn write_enum(options: &TypescriptOptions, found_enum: &Value, output: &mut String) {
+	let name = pascal_case(found_enum["schema"].as_str().unwrap_or_default(), found_enum["name"].as_str().unwrap_or_default());
+	let values: Vec<&str> = found_enum["values"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+	match options.enum_style {
+		EnumStyle::Union => {
+			let union = match values.is_empty() {
+				true => "never".to_string(),
+				false => values.iter().map(|value| quote_string(value)).collect::<Vec<_>>().join(" | "),
+			};
+			output.push_str(&format!("export type {name} = {union};\n\n"));
+		},
+		EnumStyle::Enum => {
+			output.push_str(&format!("export enum {name} {{\n"));
+			let mut members = std::collections::HashSet::new();
+			for value in values {
+				// values like `dark green` and `dark_green` would otherwise both be `DarkGreen`
+				let member = match pascal_case("public", value) {
+					member if is_identifier(&member) && members.insert(member.clone()) => member,
+					_ => quote_string(value),
+				};
+				output.push_str(&format!("\t{member} = {},\n", quote_string(value)));
+			}
+			output.push_str("}\n\n");
+		},
+	}
+}
+
+fn write_interface(options: &TypescriptOptions, enums: &[Value], relation: &Value, output: &mut String) {
+	let name = pascal_case(relation["schema"].as_str().unwrap_or_default(), relation["name"].as_str().unwrap_or_default());
+	if let Some(comment) = relation["comment"].as_str() {
+		output.push_str(&format!("/** {} */\n", comment.replace("*/", "* /")));
+	}
+	output.push_str(&format!("export interface {name} {{\n"));
+	for column in relation["columns"].as_array().into_iter().flatten() {
+		if let Some(comment) = column["comment"].as_str() {
+			output.push_str(&format!("\t/** {} */\n", comment.replace("*/", "* /")));
+		}
+		let nullable = column["nullable"].as_bool().unwrap_or(true);
+		let column_type = typescript_type(options, enums, column["type"].as_str().unwrap_or_default());
+		let column_type = if nullable { format!("{column_type} | null") } else { column_type };
+		let optional = if nullable && options.optional_nullable { "?" } else { "" };
+		output.push_str(&format!("\t{}{optional}: {column_type};\n", property_name(column["name"].as_str().unwrap_or_default())));
+	}
+	output.push_str("}\n\n");
+}
+
+fn typescript(options: &TypescriptOptions, catalog: &Value) -> String {
+	let mut output = "// generated from the schema by postgres_migrator codegen typescript, don't edit it by hand\n\n".to_string();
+	let enums: Vec<Value> = catalog["enums"].as_array().cloned().unwrap_or_default();
+	for found_enum in &enums {
+		write_enum(options, found_enum, &mut output);
+	}
+	for relation in catalog["tables"].as_array().into_iter().chain(catalog["views"].as_array()).flatten() {
+		write_interface(options, &enums, relation, &mut output);
+	}
+	format!("{}\n", output.trim_end())
+}
+
+#[test]
+fn test_typescript() {
+	let catalog = serde_json::json!({
+		"enums": [{"schema": "public", "name": "fruit_color", "values": ["red", "dark green"]}, {"schema": "app", "name": "state", "values": ["on"]}],
+		"tables": [{"schema": "public", "name": "fruit", "comment": "what we sell", "columns": [
+			{"name": "id", "type": "bigint", "nullable": false, "comment": null},
+			{"name": "color", "type": "fruit_color", "nullable": true, "comment": null},
+			{"name": "picked_at", "type": "timestamp with time zone", "nullable": false, "comment": "when it was picked"},
+			{"name": "Price Per Kilo", "type": "numeric(10,2)", "nullable": false, "comment": null},
+			{"name": "tags", "type": "character varying(20)[]", "nullable": false, "comment": null},
+			{"name": "states", "type": "app.state[]", "nullable": true, "comment": null},
+			{"name": "details", "type": "jsonb", "nullable": false, "comment": null},
+		]}],
+		"views": [{"schema": "app", "name": "ripe_fruit", "comment": null, "columns": [{"name": "id", "type": "integer", "nullable": true, "comment": null}]}],
+	});
+	let options = TypescriptOptions{ output: None, dates: DateMapping::String, numerics: NumericMapping::String, enum_style: EnumStyle::Union, optional_nullable: false };
+	assert_eq!(typescript(&options, &catalog), "\
+// generated from the schema by postgres_migrator codegen typescript, don't edit it by hand
+
+export type FruitColor = 'red' | 'dark green';
+
+export type AppState = 'on';
+
+/** what we sell */
+export interface Fruit {
+	id: string;
+	color: FruitColor | null;
+	/** when it was picked */
+	picked_at: string;
+	'Price Per Kilo': string;
+	tags: string[];
+	states: AppState[] | null;
+	details: unknown;
+}
+
+export interface AppRipeFruit {
+	id: number | null;
+}
+");
+
+	let options = TypescriptOptions{ dates: DateMapping::Date, numerics: NumericMapping::Number, enum_style: EnumStyle::Enum, optional_nullable: true, ..options };
+	let generated = typescript(&options, &catalog);
+	assert!(generated.contains("export enum FruitColor {\n\tRed = 'red',\n\tDarkGreen = 'dark green',\n}\n"));
+	assert!(generated.contains("\tid: number;\n\tcolor?: FruitColor | null;\n\t/** when it was picked */\n\tpicked_at: Date;\n\t'Price Per Kilo': number;\n"));
+}
+
+#[test]
+fn test_typescript_unusual_catalog() {
+	let options = TypescriptOptions{ output: None, dates: DateMapping::String, numerics: NumericMapping::String, enum_style: EnumStyle::Enum, optional_nullable: false };
+	assert_eq!(
+		typescript(&options, &serde_json::json!({})),
+		"// generated from the schema by postgres_migrator codegen typescript, don't edit it by hand\n",
+	);
+
+	let catalog = serde_json::json!({
+		"enums": [
+			{"schema": "public", "name": "grade", "values": ["dark green", "dark_green", "1st", "it's"]},
+			{"schema": "public", "name": "nothing", "values": []},
+		],
+		"tables": [{"schema": "public", "name": "fruit", "comment": "ends */ early", "columns": [
+			{"name": "shape", "type": "geometry", "nullable": false, "comment": null},
+			{"name": "grades", "type": "grade[]", "nullable": true, "comment": null},
+			{"name": "1st", "type": "text", "comment": null},
+		]}],
+	});
+	let generated = typescript(&options, &catalog);
+	assert!(generated.contains("export enum Grade {\n\tDarkGreen = 'dark green',\n\t'dark_green' = 'dark_green',\n\t'1st' = '1st',\n\tItS = 'it\\'s',\n}\n"));
+	assert!(generated.contains("export enum Nothing {\n}\n"));
+	assert!(generated.contains("/** ends * / early */\n"));
+	assert!(generated.contains("\tshape: unknown;\n\tgrades: Grade[] | null;\n\t'1st': string | null;\n"));
+
+	let options = TypescriptOptions{ enum_style: EnumStyle::Union, ..options };
+	assert!(typescript(&options, &catalog).contains("export type Nothing = never;\n"));
+}
+
+/// generates the types of `target` from the schema directory
+pub(crate) fn command_codegen(args: &Args, target: &CodegenTarget) -> Result<()> {
+	match target {
+		CodegenTarget::Typescript(options) => {
+			let generated = typescript(options, &schema_catalog(args)?);
+			match &options.output {
+				Some(output) => {
+					fs::write(output, generated).with_context(|| format!("unable to write {output}"))?;
+					println!("wrote {output}");
+				},
+				None => print!("{generated}"),
+			}
+		},
+	}
+	Ok(())
+}
@@ -0,0 +1,107 @@
+//! Reuses one connection per database across the phases of a command.
+
+use std::{io, sync::{Mutex, atomic::{AtomicUsize, Ordering}}, time::Duration};
+use anyhow::Result;
+use postgres::{Client, Config};
+
+use super::to_connection_string;
+
+/// idle connections, by the connection string of the database they're connected to.
+/// a static rather than a thread local, since clients can't be dropped while thread locals are torn down at exit
+static CLIENTS: Mutex<Vec<(String, Client)>> = Mutex::new(Vec::new());
+static OPENED: AtomicUsize = AtomicUsize::new(0);
+
+/// opens a new connection that isn't reused, counted in the total
+pub(crate) fn connect(config: &Config) -> Result<Client> {
+	let client = config.connect(postgres::NoTls)?;
+	OPENED.fetch_add(1, Ordering::SeqCst);
+	Ok(client)
+}
+
+fn take_idle(key: &str) -> Option<Client> {
+	let mut clients = CLIENTS.lock().unwrap();
+	let index = clients.iter().position(|(idle_key, _)| idle_key == key)?;
+	Some(clients.remove(index).1)
+}
+
+/// runs `f` with the idle connection to the database of `config`, opening one if there isn't any.
+/// the connection is checked out while `f` runs, so a nested call for the same database gets a connection of its own
+pub(crate) fn with_client<T>(config: &Config, f: impl FnOnce(&mut Client) -> Result<T>) -> Result<T> {
+	let key = to_connection_string(config);
+	let idle = take_idle(&key);
+	let mut client = match idle {
+		Some(client) if !client.is_closed() => client,
+		_ => connect(config)?,
+	};
+
+	let outcome = f(&mut client);
+	if !client.is_closed() {
+		CLIENTS.lock().unwrap().push((key, client));
+	}
+	outcome
+}
+
+/// closes the idle connection to the database of `config`, which has to happen before that database can be dropped
+pub(crate) fn close(config: &Config) {
+	drop(take_idle(&to_connection_string(config)));
+}
+
+/// how many connections this process has opened
+pub(crate) fn opened_count() -> usize {
+	OPENED.load(Ordering::SeqCst)
+}
+
+/// how `migrate` reconnects when the connection is lost: up to `attempts` times, waiting `backoff` before the first
+/// and doubling the wait before every one after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Retry {
+	pub(crate) attempts: u32,
+	pub(crate) backoff: Duration,
+}
+
+impl Retry {
+	/// how long to wait before `attempt`, counting from 1
+	pub(crate) fn delay(&self, attempt: u32) -> Duration {
+		self.backoff.saturating_mul(1 << attempt.saturating_sub(1).min(16))
+	}
+}
+
+#[test]
+fn test_retry_delay() {
+	let retry = Retry{ attempts: 3, backoff: Duration::from_secs(1) };
+	assert_eq!(retry.delay(1), Duration::from_secs(1));
+	assert_eq!(retry.delay(3), Duration::from_secs(4));
+	assert_eq!(retry.delay(100), Duration::from_secs(65536));
+}
+
+/// whether `err` came from losing the connection, like when the server restarts, fails over, or a pooler in front of it goes away,
+/// rather than from the sql, so the same work can be tried again on a new connection
+pub(crate) fn is_transient(err: &anyhow::Error) -> bool {
+	err.chain().any(|cause| match cause.downcast_ref::<postgres::Error>() {
+		Some(error) => is_connection_lost(error),
+		None => cause.downcast_ref::<io::Error>().is_some_and(|error| is_connection_io_error(error.kind())),
+	})
+}
+
+/// the io errors of a connection that went away, rather than ones like a missing file
+fn is_connection_io_error(kind: io::ErrorKind) -> bool {
+	matches!(kind, io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe | io::ErrorKind::UnexpectedEof | io::ErrorKind::TimedOut)
+}
+
+pub(crate) fn is_connection_lost(error: &postgres::Error) -> bool {
+	error.is_closed() || error.code().is_some_and(|code| is_transient_code(code.code()))
+}
+
+/// connection exceptions, and the server shutting down or not accepting connections yet
+fn is_transient_code(code: &str) -> bool {
+	code.starts_with("08") || matches!(code, "57P01" | "57P02" | "57P03")
+}
+
+#[test]
+fn test_is_transient() {
+	assert!(is_transient(&anyhow::Error::new(io::Error::new(io::ErrorKind::ConnectionReset, "reset")).context("unable to apply 1.null.first.sql")));
+	assert!(!is_transient(&anyhow::anyhow!("syntax error at or near \"tabel\"")));
+	assert!(!is_transient(&anyhow::Error::new(io::Error::new(io::ErrorKind::NotFound, "no such file")).context("unable to read 1.null.first.sql")));
+	assert!(is_transient_code("08006") && is_transient_code("57P01"));
+	assert!(!is_transient_code("23505"));
+}
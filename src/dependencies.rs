@@ -0,0 +1,143 @@
+//! Which files of the schema directory depend on which, so independent ones can be applied over several connections at once.
+
+use std::{collections::HashSet, path::PathBuf, sync::{Mutex, atomic::{AtomicUsize, Ordering}}};
+use anyhow::{Context, Result};
+use postgres::Config;
+use regex::Regex;
+
+use super::{connections, read_sql_to_apply, statements};
+use super::objects::{Action, ObjectKind, parse_ddl};
+
+/// statements that don't create anything another file could depend on, beyond the objects they mention
+const HARMLESS_STATEMENTS: &[&str] = &["insert", "update", "delete", "copy", "grant", "revoke", "comment"];
+
+#[derive(Debug, PartialEq, Eq)]
+struct Analysis {
+	/// the unqualified names of the objects the file creates, lowercased
+	created: Vec<String>,
+	/// every identifier the file mentions, lowercased
+	mentioned: HashSet<String>,
+	/// whether the file does something whose effects can't be told from names, like creating an extension or a role,
+	/// so it's applied on its own, after every file before it and before every file after it
+	barrier: bool,
+}
+
+fn analyze(sql: &str) -> Result<Analysis> {
+	let word = Regex::new(r"[\w$]+").unwrap();
+	let mut created = vec![];
+	let mut barrier = false;
+	for statement in statements::split_statements(sql)? {
+		match parse_ddl(&statement.sql) {
+			Some(ddl) if ddl.kind == ObjectKind::Extension => barrier = true,
+			Some(ddl) if ddl.action == Action::Create => {
+				if let Some(name) = ddl.name {
+					let unqualified = match ddl.kind {
+						ObjectKind::Schema => name,
+						_ => name.rsplit('.').next().unwrap_or_default().to_string(),
+					};
+					// a quoted name with spaces or punctuation can't be found among the words of other files
+					barrier |= !unqualified.chars().all(|character| character.is_alphanumeric() || character == '_' || character == '$');
+					created.push(unqualified);
+				}
+			},
+			Some(_) => {},
+			None => {
+				let first_word = word.find(&statement.sql).map(|found| found.as_str().to_lowercase()).unwrap_or_default();
+				barrier |= !HARMLESS_STATEMENTS.contains(&first_word.as_str());
+			},
+		}
+	}
+	let mentioned = word.find_iter(sql).map(|found| found.as_str().to_lowercase()).collect();
+	Ok(Analysis{ created, mentioned, barrier })
+}
+
+/// groups the files, by index, into waves that are applied one after another. a file is in a later wave than every
+/// earlier file that creates something it mentions, and than every barrier before it
+fn plan_waves(analyses: &[Analysis]) -> Vec<Vec<usize>> {
+	let mut levels: Vec<usize> = vec![];
+	let mut barrier_level = 0;
+	for (index, analysis) in analyses.iter().enumerate() {
+		let level = match analysis.barrier {
+			true => levels.iter().max().map_or(0, |level| level + 1),
+			false => {
+				let dependency_level = analyses[..index].iter().zip(&levels)
+					.filter(|(earlier, _)| earlier.created.iter().any(|name| analysis.mentioned.contains(name)))
+					.map(|(_, level)| level + 1)
+					.max()
+					.unwrap_or(0);
+				dependency_level.max(barrier_level)
+			},
+		};
+		if analysis.barrier {
+			barrier_level = level + 1;
+		}
+		levels.push(level);
+	}
+
+	let mut waves: Vec<Vec<usize>> = vec![];
+	for (index, level) in levels.into_iter().enumerate() {
+		if waves.len() <= level {
+			waves.resize(level + 1, vec![]);
+		}
+		waves[level].push(index);
+	}
+	waves
+}
+
+#[test]
+fn test_plan_waves() {
+	let sqls = [
+		"create extension if not exists citext;",
+		"create type fruit_kind as enum ('apple', 'pear');",
+		"create table shop (id int primary key);",
+		"create table fruit (id int, kind fruit_kind, shop_id int references shop);",
+		"create function count_shops() returns bigint language sql as $$ select count(*) from shop $$;",
+		"create table \"Basket\" (id int); grant select on \"Basket\" to public;",
+		"create role reader;",
+		"create table unrelated (id int);",
+	];
+	let analyses: Vec<Analysis> = sqls.iter().map(|sql| analyze(sql).unwrap()).collect();
+	assert!(analyses[0].barrier && analyses[6].barrier);
+	assert!(!analyses[5].barrier);
+	assert_eq!(analyses[3].created, vec!["fruit"]);
+	assert_eq!(plan_waves(&analyses), vec![vec![0], vec![1, 2, 5], vec![3, 4], vec![6], vec![7]]);
+	assert!(analyze("create table \"fruit basket\" (id int);").unwrap().barrier);
+	assert!(analyze("set search_path = shop;").unwrap().barrier);
+}
+
+/// applies `sql_files` to the database of `config` over up to `jobs` connections, each wave of files that
+/// don't depend on each other at once
+pub(crate) fn apply_concurrently(config: &Config, sql_files: Vec<PathBuf>, jobs: usize) -> Result<()> {
+	let sqls = sql_files.iter()
+		.map(|sql_file| read_sql_to_apply(sql_file, false))
+		.collect::<Result<Vec<_>>>()?;
+	let analyses = sql_files.iter().zip(&sqls)
+		.map(|(sql_file, sql)| analyze(sql).with_context(|| format!("unable to parse {}", sql_file.display())))
+		.collect::<Result<Vec<_>>>()?;
+	let waves = plan_waves(&analyses);
+
+	let widest = waves.iter().map(Vec::len).max().unwrap_or(0);
+	let mut clients = (0..jobs.min(widest)).map(|_| connections::connect(config)).collect::<Result<Vec<_>>>()?;
+	for wave in waves {
+		let next = AtomicUsize::new(0);
+		let failure: Mutex<Option<anyhow::Error>> = Mutex::new(None);
+		std::thread::scope(|scope| {
+			for client in clients.iter_mut().take(wave.len()) {
+				scope.spawn(|| while failure.lock().unwrap().is_none() {
+					let Some(&index) = wave.get(next.fetch_add(1, Ordering::SeqCst)) else { break };
+					if let Err(error) = statements::batch_execute(client, &sqls[index], &sql_files[index]) {
+						failure.lock().unwrap().get_or_insert(error.context(format!(
+							"unable to apply {}. it was applied alongside other schema files with --schema-jobs, \
+							which doesn't see dependencies that aren't spelled out, so try without it",
+							sql_files[index].display(),
+						)));
+					}
+				});
+			}
+		});
+		if let Some(error) = failure.into_inner().unwrap() {
+			return Err(error);
+		}
+	}
+	Ok(())
+}
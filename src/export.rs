@@ -0,0 +1,117 @@
+use std::{fs, path::PathBuf};
+use anyhow::{anyhow, Result};
+
+use postgres_migrator::{down_migration_path, migration_description};
+
+use super::{Args, MigrationFile, ensure_directory, gather_validated_migrations, list_sql_files};
+use super::catalog::{CatalogFormat, export_catalog};
+
+#[derive(clap::Subcommand, Debug)]
+pub(crate) enum ExportTarget {
+	/// write the migration chain as `<version>_<description>.sql` files that `sqlx migrate` and `sqlx::migrate!` can apply,
+	/// or as `.up.sql` and `.down.sql` pairs for migrations with a down migration
+	Sqlx {
+		/// directory to write the sqlx migrations to, any other sql files in it are removed
+		directory: String,
+	},
+	/// print the tables, views, columns, constraints, enums and function signatures the schema directory declares,
+	/// read from a temp database it's applied to
+	Catalog {
+		#[clap(long, arg_enum, default_value = "json")]
+		format: CatalogFormat,
+	},
+}
+
+/// `suffix` is `sql`, or `up.sql` and `down.sql` for a reversible migration
+fn sqlx_file_name(migration_file: &MigrationFile, suffix: &str) -> String {
+	let description = migration_description(&migration_file.file_path);
+	match description.is_empty() {
+		true => format!("{}_migration.{suffix}", migration_file.current_version),
+		false => format!("{}_{description}.{suffix}", migration_file.current_version),
+	}
+}
+
+fn export_sqlx_files(migration_files: &[MigrationFile], directory: &str) -> Result<()> {
+	ensure_directory(directory)?;
+
+	let mut exported = vec![];
+	for migration_file in migration_files {
+		if migration_file.is_onboard {
+			eprintln!("warning: {} is an onboard migration, but sqlx will apply it like any other migration", migration_file.display_file_path);
+		}
+		let down_path = down_migration_path(&migration_file.file_path);
+		let copies = match down_path.exists() {
+			true => vec![(migration_file.file_path.clone(), "up.sql"), (down_path, "down.sql")],
+			false => vec![(migration_file.file_path.clone(), "sql")],
+		};
+		for (file_path, suffix) in copies {
+			let export_path = PathBuf::from(directory).join(sqlx_file_name(migration_file, suffix));
+			fs::copy(&file_path, &export_path)
+				.map_err(|err| anyhow!("unable to write {}: {err}", export_path.display()))?;
+			println!("{} -> {}", file_path.display(), export_path.display());
+			exported.push(export_path);
+		}
+	}
+
+	// sqlx considers every sql file in its directory a migration, so stale ones from previous exports have to go
+	for stale_path in list_sql_files(directory)? {
+		if !exported.contains(&stale_path) {
+			fs::remove_file(&stale_path)?;
+			println!("removed {}", stale_path.display());
+		}
+	}
+
+	Ok(())
+}
+
+#[test]
+fn test_export_sqlx_files() -> Result<()> {
+	let root = std::env::temp_dir().join("postgres_migrator_test_export_sqlx_files");
+	let _ = fs::remove_dir_all(&root);
+	let (migrations_directory, directory) = (root.join("migrations"), root.join("sqlx"));
+	fs::create_dir_all(&migrations_directory)?;
+	fs::create_dir_all(&directory)?;
+
+	let sources = [
+		("20220101000000.null.create_fruit.up.sql", "create table fruit ();"),
+		("20220101000000.null.create_fruit.down.sql", "drop table fruit;"),
+		("20220102000000.20220101000000.sql", "alter table fruit add color text;"),
+	];
+	for (file_name, sql) in sources {
+		fs::write(migrations_directory.join(file_name), sql)?;
+	}
+	fs::write(directory.join("20210101000000_stale.sql"), "select 1;")?;
+	fs::write(directory.join("README.md"), "not a migration")?;
+
+	let migration_files = MigrationFile::vec_from_paths(sources.iter().map(|(file_name, _)| migrations_directory.join(file_name)).collect())?;
+	export_sqlx_files(&migration_files, directory.to_str().unwrap())?;
+
+	let mut exported: Vec<(String, String)> = fs::read_dir(&directory)?
+		.map(|entry| {
+			let entry = entry?;
+			Ok((entry.file_name().to_string_lossy().to_string(), fs::read_to_string(entry.path())?))
+		})
+		.collect::<Result<_>>()?;
+	exported.sort();
+	assert_eq!(exported, vec![
+		("20220101000000_create_fruit.down.sql".to_string(), "drop table fruit;".to_string()),
+		("20220101000000_create_fruit.up.sql".to_string(), "create table fruit ();".to_string()),
+		("20220102000000_migration.sql".to_string(), "alter table fruit add color text;".to_string()),
+		("README.md".to_string(), "not a migration".to_string()),
+	]);
+
+	// a directory that can't be written to
+	fs::remove_dir_all(&directory)?;
+	fs::write(&directory, "")?;
+	assert!(export_sqlx_files(&migration_files, directory.to_str().unwrap()).is_err());
+
+	fs::remove_dir_all(&root)?;
+	Ok(())
+}
+
+pub(crate) fn command_export(args: &Args, target: &ExportTarget) -> Result<()> {
+	match target {
+		ExportTarget::Sqlx{directory} => export_sqlx_files(&gather_validated_migrations(args)?.0, directory),
+		ExportTarget::Catalog{format} => export_catalog(args, *format),
+	}
+}
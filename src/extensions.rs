@@ -0,0 +1,120 @@
+//! Leaves the objects that extensions installed on the database created out of diffs against the database.
+
+use anyhow::Result;
+use regex::Regex;
+
+use super::objects::{IDENTIFIER, ObjectKind, normalize_name, parse_ddl};
+use super::statements::split_statements;
+
+/// the qualified names of the relations, functions and types that belong to an extension, and the schemas
+pub(crate) fn query_extension_objects(client: &mut impl postgres::GenericClient) -> Result<Vec<String>> {
+	let query = "
+		select n.nspname || '.' || c.relname as name
+		from pg_depend d join pg_class c on c.oid = d.objid join pg_namespace n on n.oid = c.relnamespace
+		where d.classid = 'pg_class'::regclass and d.deptype = 'e'
+		union
+		select n.nspname || '.' || p.proname
+		from pg_depend d join pg_proc p on p.oid = d.objid join pg_namespace n on n.oid = p.pronamespace
+		where d.classid = 'pg_proc'::regclass and d.deptype = 'e'
+		union
+		select n.nspname || '.' || t.typname
+		from pg_depend d join pg_type t on t.oid = d.objid join pg_namespace n on n.oid = t.typnamespace
+		where d.classid = 'pg_type'::regclass and d.deptype = 'e'
+		union
+		select n.nspname
+		from pg_depend d join pg_namespace n on n.oid = d.objid
+		where d.classid = 'pg_namespace'::regclass and d.deptype = 'e'
+	";
+	Ok(client.query(query, &[])?.into_iter().map(|row| normalize_name(row.get("name"))).collect())
+}
+
+/// the object a statement of a diff is about, with columns replaced by their table
+fn statement_object(sql: &str) -> Option<String> {
+	if let Some(ddl) = parse_ddl(sql) {
+		let name = ddl.name?;
+		return match ddl.kind {
+			ObjectKind::Column => name.rsplit_once('.').map(|(table, _)| table.to_string()),
+			_ => Some(name),
+		};
+	}
+	let privileges = Regex::new(&format!(
+		r"(?is)^(?:grant|revoke)\s.*?\son\s+(?:(?:table|sequence|function|procedure|schema|type|domain)\s+)?({IDENTIFIER})"
+	)).unwrap();
+	privileges.captures(sql).map(|captures| normalize_name(&captures[1]))
+}
+
+/// `diff` without the statements about `extension_objects`, and how many were left out
+pub(crate) fn without_extension_objects(diff: &str, extension_objects: &[String]) -> Result<(String, usize)> {
+	if extension_objects.is_empty() {
+		return Ok((diff.to_string(), 0));
+	}
+	let statements = split_statements(diff)?;
+	let total = statements.len();
+	let kept: Vec<String> = statements.into_iter()
+		// migra qualifies every name, so they can be compared as they are
+		.filter(|statement| !statement_object(&statement.sql).is_some_and(|object| extension_objects.contains(&object)))
+		.map(|statement| format!("{};", statement.sql))
+		.collect();
+	let left_out = total - kept.len();
+	match left_out {
+		0 => Ok((diff.to_string(), 0)),
+		_ => Ok((kept.join("\n\n"), left_out)),
+	}
+}
+
+#[test]
+fn test_without_extension_objects() {
+	let diff = r#"drop table "public"."spatial_ref_sys";
+
+revoke select on table "public"."spatial_ref_sys" from "reader";
+
+drop function if exists "cron"."schedule"(text, text);
+
+drop schema if exists "cron";
+
+comment on column "public"."spatial_ref_sys"."srid" is 'id';
+
+create table "public"."fruit" ("id" integer);
+
+alter table "cron"."fruit_jobs" add column "x" integer;"#;
+	let extension_objects = vec!["public.spatial_ref_sys".to_string(), "cron.schedule".to_string(), "cron".to_string()];
+	assert_eq!(
+		without_extension_objects(diff, &extension_objects).unwrap(),
+		("create table \"public\".\"fruit\" (\"id\" integer);\n\nalter table \"cron\".\"fruit_jobs\" add column \"x\" integer;".to_string(), 5),
+	);
+	assert_eq!(without_extension_objects("create table fruit ();", &extension_objects).unwrap(), ("create table fruit ();".to_string(), 0));
+}
+
+#[test]
+fn test_statement_object() {
+	assert_eq!(statement_object(r#"alter table "public"."spatial_ref_sys" drop column "srtext""#).as_deref(), Some("public.spatial_ref_sys"));
+	assert_eq!(statement_object(r#"grant usage on schema "cron" to "reader""#).as_deref(), Some("cron"));
+	assert_eq!(statement_object(r#"grant execute on function "cron"."schedule"(text, text) to "reader""#).as_deref(), Some("cron.schedule"));
+	// statements that aren't about an object are never left out
+	assert_eq!(statement_object("select pg_catalog.set_config('search_path', '', false)"), None);
+	assert_eq!(statement_object("set check_function_bodies = off"), None);
+}
+
+#[test]
+fn test_without_extension_objects_failures() {
+	let extension_objects = vec!["public.spatial_ref_sys".to_string()];
+	assert!(without_extension_objects("drop table \"public\".\"spatial_ref_sys\"; select 'unterminated", &extension_objects).is_err());
+	// without extension objects the diff isn't parsed at all
+	assert_eq!(without_extension_objects("select 'unterminated", &[]).unwrap(), ("select 'unterminated".to_string(), 0));
+	// only objects of the extension are left out, not others in its schema
+	assert_eq!(
+		without_extension_objects("drop table \"public\".\"spatial_ref_sys_backup\";", &extension_objects).unwrap(),
+		("drop table \"public\".\"spatial_ref_sys_backup\";".to_string(), 0),
+	);
+}
+
+#[test]
+#[ignore]
+fn test_query_extension_objects() -> Result<()> {
+	let mut client = std::env::var("PG_URL")?.parse::<postgres::Config>()?.connect(postgres::NoTls)?;
+	let extension_objects = query_extension_objects(&mut client)?;
+	// plpgsql is an extension of every database
+	assert!(extension_objects.contains(&"pg_catalog.plpgsql_call_handler".to_string()));
+	assert!(!extension_objects.iter().any(|object| object.starts_with("public.")));
+	Ok(())
+}
@@ -0,0 +1,86 @@
+use anyhow::{anyhow, Result};
+use regex::Regex;
+
+use super::{Args, Command};
+
+/// `*` matches any run of characters, everything else matches itself, case insensitively
+pub(crate) fn pattern_matches(pattern: &str, value: &str) -> bool {
+	let pattern = pattern.split('*').map(regex::escape).collect::<Vec<_>>().join(".*");
+	Regex::new(&format!("(?i)^{pattern}$")).unwrap().is_match(value)
+}
+
+#[test]
+fn test_pattern_matches() {
+	assert!(pattern_matches("prod-db", "prod-db"));
+	assert!(pattern_matches("prod-*", "PROD-db-1"));
+	assert!(pattern_matches("*.prod.internal", "primary.prod.internal"));
+	assert!(pattern_matches("*", "anything"));
+	assert!(!pattern_matches("prod-*", "staging-db"));
+	assert!(!pattern_matches("prod.internal", "prodXinternal"));
+	assert!(!pattern_matches("prod", "prod-db"));
+}
+
+/// the first host or dbname of the configured database that matches a protected target pattern
+fn find_protected(args: &Args) -> Option<String> {
+	let hosts = args.pg_url.get_hosts().iter().map(|host| match host {
+		postgres::config::Host::Tcp(host) => host.clone(),
+		#[cfg(unix)]
+		postgres::config::Host::Unix(path) => path.to_string_lossy().into_owned(),
+	});
+	hosts.chain(args.pg_url.get_dbname().map(str::to_string))
+		.find(|value| args.protected_targets.iter().any(|pattern| pattern_matches(pattern, value)))
+}
+
+/// refuses commands that would change a protected database, before anything runs:
+/// `compact` is refused unless it only uses temp databases, `sync`, `dev` and `apply` are always refused,
+/// and commands that change the database directly need `--confirm-production`
+pub(crate) fn enforce(args: &Args) -> Result<()> {
+	let Some(protected) = find_protected(args) else { return Ok(()) };
+
+	let command = match &args.command {
+		Command::Compact{offline: false, dry_run: false, phase: None, ..} => {
+			return Err(anyhow!("{protected} is a protected target, so compacting it is refused, use `compact --offline` instead"));
+		},
+		Command::Sync{..} | Command::Dev => {
+			return Err(anyhow!("{protected} is a protected target, and sync and dev are only meant for development databases, use generate and migrate instead"));
+		},
+		Command::Apply{..} => {
+			return Err(anyhow!("{protected} is a protected target, and apply is only meant for ephemeral databases, use migrate instead"));
+		},
+		Command::Migrate{options, ..} if options.dry_run => return Ok(()),
+		Command::Migrate{..} => "migrate",
+		Command::PruneHistory{..} => "prune-history",
+		Command::Backfill(_) => "backfill",
+		Command::CreateIndexes{..} => "create-indexes",
+		Command::Promote{..} => "promote",
+		_ => return Ok(()),
+	};
+	if !args.confirm_production {
+		return Err(anyhow!("{protected} is a protected target, pass --confirm-production to {command} it"));
+	}
+	Ok(())
+}
+
+#[test]
+fn test_enforce() {
+	let enforce_on = |pg_url: &str, arguments: &[&str]| {
+		let command_line = [&["--pg-url", pg_url, "--protected-target", "*.prod.internal,billing"], arguments].concat();
+		enforce(&super::test_args(&command_line)).map_err(|error| error.to_string())
+	};
+	let production = "postgres://app@primary.prod.internal/app";
+
+	assert_eq!(enforce_on("postgres://app@localhost/app", &["sync"]), Ok(()));
+	assert_eq!(enforce_on("postgres://app@localhost/billing", &["dev"]).unwrap_err(), "billing is a protected target, and sync and dev are only meant for development databases, use generate and migrate instead");
+	assert_eq!(enforce_on(production, &["compact"]).unwrap_err(), "primary.prod.internal is a protected target, so compacting it is refused, use `compact --offline` instead");
+	assert_eq!(enforce_on(production, &["compact", "--offline"]), Ok(()));
+	assert!(enforce_on(production, &["apply", "schema"]).is_err());
+	assert!(enforce_on(production, &["--confirm-production", "apply", "schema"]).is_err());
+
+	assert_eq!(enforce_on(production, &["migrate"]).unwrap_err(), "primary.prod.internal is a protected target, pass --confirm-production to migrate it");
+	assert_eq!(enforce_on(production, &["migrate", "--dry-run"]), Ok(()));
+	assert_eq!(enforce_on(production, &["--confirm-production", "migrate"]), Ok(()));
+	assert!(enforce_on(production, &["prune-history"]).is_err());
+	assert!(enforce_on(production, &["promote", "--role", "app", "green"]).is_err());
+	// commands that only read the database are never refused
+	assert_eq!(enforce_on(production, &["status"]), Ok(()));
+}
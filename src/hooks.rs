@@ -0,0 +1,149 @@
+use std::{fs, path::PathBuf, process::Command};
+use anyhow::{anyhow, Context, Result};
+use postgres_migrator::compute_checksum;
+
+use super::{Args, Backend, DEFAULT_MIGRATIONS_DIRECTORY, DEFAULT_SCHEMA_DIRECTORY, command_check, list_migration_files, list_sql_files};
+use super::snapshots::latest_snapshot;
+
+/// marks hooks written by `install-hooks`, which it's free to overwrite
+const HOOK_MARKER: &str = "# installed by postgres_migrator install-hooks";
+
+/// digest of everything a `check` between `source` and `target` depends on, when neither is the database
+fn sources_digest(args: &Args, source: Backend, target: Backend) -> Result<String> {
+	let mut contents = format!("{source:?} {target:?} {:?} {}\n", args.schema_arg, args.exclude_privileges).into_bytes();
+	let mut file_paths = list_sql_files(&args.schema_directory)?;
+	file_paths.extend(list_migration_files(&args.migrations_directory)?);
+	if source == Backend::Snapshot || target == Backend::Snapshot {
+		file_paths.push(latest_snapshot(&args.snapshot)?);
+	}
+	for file_path in file_paths {
+		contents.extend(file_path.to_string_lossy().as_bytes());
+		contents.push(b'\n');
+		contents.extend(fs::read(&file_path).with_context(|| format!("unable to read {}", file_path.display()))?);
+	}
+	Ok(compute_checksum(&contents))
+}
+
+/// `check`, skipped when the schema and migrations haven't changed since the last time it passed with this `cache_file`
+pub(crate) fn command_check_cached(args: &Args, source: Backend, target: Backend, cache_file: &str) -> Result<()> {
+	if source == Backend::Database || target == Backend::Database {
+		return Err(anyhow!("--cache-file can't be used when checking against the database, since it changes without the files changing"));
+	}
+
+	let digest = sources_digest(args, source, target)?;
+	if fs::read_to_string(cache_file).is_ok_and(|cached| cached.trim() == digest) {
+		println!("nothing changed since the last successful check, skipping it");
+		return Ok(());
+	}
+	command_check(args, source, target)?;
+	fs::write(cache_file, digest + "\n").with_context(|| format!("unable to write {cache_file}"))?;
+	Ok(())
+}
+
+/// the global options that point the hooks at the same directories as this invocation
+fn directory_options(args: &Args) -> String {
+	let mut options = String::new();
+	if args.migrations_directory != DEFAULT_MIGRATIONS_DIRECTORY {
+		options.push_str(&format!(" --migrations-directory '{}'", args.migrations_directory));
+	}
+	if args.schema_directory != DEFAULT_SCHEMA_DIRECTORY {
+		options.push_str(&format!(" --schema-directory '{}'", args.schema_directory));
+	}
+	options
+}
+
+fn pre_commit_hook(executable: &str, directory_options: &str) -> String {
+	format!("#!/bin/sh
+{HOOK_MARKER}
+# validates the migration chain, checksums and structure, which doesn't need a database
+PG_URL=\"${{PG_URL:-postgresql://localhost/postgres_migrator_offline}}\" exec {executable}{directory_options} ci --offline
+")
+}
+
+fn pre_push_hook(executable: &str, directory_options: &str) -> String {
+	format!("#!/bin/sh
+{HOOK_MARKER}
+# checks that the migrations produce the schema, skipped when neither changed since the last successful check
+if [ -z \"$PG_URL\" ]; then
+	echo \"PG_URL isn't set, skipping postgres_migrator check schema migrations\" >&2
+	exit 0
+fi
+exec {executable}{directory_options} check schema migrations --cache-file \"$(git rev-parse --git-path postgres_migrator-check)\"
+")
+}
+
+#[test]
+fn test_hooks() {
+	assert_eq!(
+		pre_commit_hook("postgres_migrator", " --migrations-directory 'db/migrations'"),
+		format!("#!/bin/sh\n{HOOK_MARKER}\n# validates the migration chain, checksums and structure, which doesn't need a database\nPG_URL=\"${{PG_URL:-postgresql://localhost/postgres_migrator_offline}}\" exec postgres_migrator --migrations-directory 'db/migrations' ci --offline\n"),
+	);
+	assert!(pre_push_hook("migrator", "").contains("\nexec migrator check schema migrations --cache-file \"$(git rev-parse --git-path postgres_migrator-check)\"\n"));
+}
+
+#[test]
+fn test_check_cached_failures() -> Result<()> {
+	let root = std::env::temp_dir().join("postgres_migrator_test_check_cached_failures");
+	let _ = fs::remove_dir_all(&root);
+	let (schema_directory, migrations_directory) = (root.join("schema"), root.join("migrations"));
+	fs::create_dir_all(&schema_directory)?;
+	fs::create_dir_all(&migrations_directory)?;
+	fs::write(schema_directory.join("fruit.sql"), "create table fruit (id int);")?;
+	let cache_file = root.join("check-cache").to_string_lossy().into_owned();
+	let args = super::test_args(&[
+		"--schema-directory", schema_directory.to_str().unwrap(),
+		"--migrations-directory", migrations_directory.to_str().unwrap(),
+		"check", "schema", "migrations",
+	]);
+
+	assert_eq!(
+		command_check_cached(&args, Backend::Schema, Backend::Database, &cache_file).unwrap_err().to_string(),
+		"--cache-file can't be used when checking against the database, since it changes without the files changing",
+	);
+
+	// a matching digest skips the check, which would fail without a database
+	fs::write(&cache_file, sources_digest(&args, Backend::Schema, Backend::Migrations)? + "\n")?;
+	command_check_cached(&args, Backend::Schema, Backend::Migrations, &cache_file)?;
+
+	// once the schema changes the check runs, and its failure leaves the cache alone
+	fs::write(schema_directory.join("fruit.sql"), "create table fruit (id int, color text);")?;
+	assert!(command_check_cached(&args, Backend::Schema, Backend::Migrations, &cache_file).is_err());
+	assert_ne!(fs::read_to_string(&cache_file)?.trim(), sources_digest(&args, Backend::Schema, Backend::Migrations)?);
+
+	let missing_args = super::test_args(&["--schema-directory", root.join("missing").to_str().unwrap(), "check", "schema", "migrations"]);
+	assert!(sources_digest(&missing_args, Backend::Schema, Backend::Migrations).is_err());
+
+	fs::remove_dir_all(&root)?;
+	Ok(())
+}
+
+/// writes git `pre-commit` and `pre-push` hooks running `executable`,
+/// refusing to overwrite hooks that weren't written by `install-hooks` unless `force` is set
+pub(crate) fn command_install_hooks(args: &Args, executable: &str, force: bool) -> Result<()> {
+	let output = Command::new("git").args(["rev-parse", "--git-path", "hooks"]).output()
+		.context("unable to run git to find the hooks directory")?;
+	if !output.status.success() {
+		return Err(anyhow!("not in a git repository: {}", String::from_utf8_lossy(&output.stderr).trim()));
+	}
+	let hooks_directory = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+	fs::create_dir_all(&hooks_directory)?;
+
+	let directory_options = directory_options(args);
+	let hooks = [("pre-commit", pre_commit_hook(executable, &directory_options)), ("pre-push", pre_push_hook(executable, &directory_options))];
+	for (name, _) in &hooks {
+		let hook_path = hooks_directory.join(name);
+		let is_foreign = fs::read_to_string(&hook_path).is_ok_and(|existing| !existing.contains(HOOK_MARKER));
+		if is_foreign && !force {
+			return Err(anyhow!("{} already exists, pass --force to overwrite it", hook_path.display()));
+		}
+	}
+
+	for (name, contents) in hooks {
+		let hook_path = hooks_directory.join(name);
+		fs::write(&hook_path, contents).with_context(|| format!("unable to write {}", hook_path.display()))?;
+		#[cfg(unix)]
+		fs::set_permissions(&hook_path, std::os::unix::fs::PermissionsExt::from_mode(0o755))?;
+		println!("wrote {}", hook_path.display());
+	}
+	Ok(())
+}
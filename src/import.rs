@@ -0,0 +1,547 @@
+use std::{fs, path::{Path, PathBuf}};
+use anyhow::{anyhow, Result};
+
+use super::{
+	Args, EXISTS_QUERY, connections,
+	DOWN_MIGRATION_SUFFIX,
+	create_versions_table, get_null_string, insert_version, is_down_migration, list_migration_files, quote_identifier, read_sql_file, list_sql_files, make_slug, record_checksum,
+};
+
+#[derive(clap::Subcommand, Debug)]
+pub(crate) enum ImportSource {
+	/// convert a directory of flyway `V<version>__<description>.sql` and `R__<description>.sql` files
+	Flyway {
+		/// directory containing the flyway migrations
+		directory: String,
+		/// also record the migrations flyway has already applied in `_schema_versions`,
+		/// reading them from flyway's history table
+		#[clap(long)]
+		backfill_history: bool,
+		/// name of flyway's history table
+		#[clap(long, default_value = "flyway_schema_history")]
+		history_table: String,
+	},
+	/// convert a sqitch project's `sqitch.plan` and deploy scripts
+	Sqitch {
+		/// directory containing `sqitch.plan`
+		directory: String,
+		/// directory of deploy scripts, relative to the project directory
+		#[clap(long, default_value = "deploy")]
+		deploy_directory: String,
+		/// also record the changes sqitch has already deployed in `_schema_versions`,
+		/// reading them from sqitch's registry
+		#[clap(long)]
+		backfill_history: bool,
+		/// schema of sqitch's registry tables
+		#[clap(long, default_value = "sqitch")]
+		registry: String,
+	},
+	/// convert a directory of golang-migrate `<version>_<description>.up.sql` files,
+	/// carrying along their `.down.sql` counterparts
+	GolangMigrate {
+		/// directory containing the golang-migrate migrations
+		directory: String,
+	},
+	/// convert a directory of dbmate `<version>_<description>.sql` files,
+	/// carrying along their `-- migrate:down` sections
+	Dbmate {
+		/// directory containing the dbmate migrations
+		directory: String,
+	},
+	/// convert the models and enums of a prisma schema into sql files in an empty schema directory
+	Prisma {
+		/// path of the `schema.prisma` file
+		file: String,
+	},
+}
+
+/// a migration from another tool, in the order it should appear in the chain
+#[derive(Debug, PartialEq, Eq)]
+struct ImportedMigration {
+	description: String,
+	source: PathBuf,
+	up: String,
+	down: Option<String>,
+}
+
+impl ImportedMigration {
+	fn from_file(description: &str, file_path: &Path) -> Result<ImportedMigration> {
+		let up = read_sql_file(file_path)?;
+		Ok(ImportedMigration{description: description.to_string(), source: file_path.to_path_buf(), up, down: None})
+	}
+}
+
+/// synthesizes `count` sequential versions that all end before now, so migrations generated afterwards come later in the chain
+fn synthesize_versions(count: usize) -> Vec<String> {
+	let start = chrono::Utc::now() - chrono::Duration::seconds(count as i64);
+	(1..=count)
+		.map(|offset| (start + chrono::Duration::seconds(offset as i64)).format("%Y%m%d%H%M%S").to_string())
+		.collect()
+}
+
+#[test]
+fn test_synthesize_versions() {
+	assert_eq!(synthesize_versions(0), Vec::<String>::new());
+
+	let versions = synthesize_versions(5);
+	assert_eq!(versions.len(), 5);
+	assert!(versions.iter().all(|version| version.len() == 14));
+	assert!(versions.windows(2).all(|pair| pair[0] < pair[1]));
+	assert!(versions.last().unwrap() <= &super::create_timestamp());
+}
+
+/// writes `migrations` into an empty migrations directory as a valid chain, returning the new versions
+fn write_imported_chain(migrations_directory: &str, migrations: &[ImportedMigration]) -> Result<Vec<String>> {
+	super::ensure_directory(migrations_directory)?;
+	if !list_migration_files(migrations_directory)?.is_empty() {
+		return Err(anyhow!("{migrations_directory} already contains migrations, imports can only be done into an empty migrations directory"));
+	}
+
+	let versions = synthesize_versions(migrations.len());
+	let mut previous_version = get_null_string();
+	for (ImportedMigration{description, source, up, down}, current_version) in migrations.iter().zip(&versions) {
+		let file_stem = format!("{current_version}.{previous_version}.{}", make_slug(description));
+		let new_file_path = PathBuf::from(migrations_directory).join(format!("{file_stem}.sql"));
+		fs::write(&new_file_path, up)?;
+		record_checksum(migrations_directory, current_version, up.as_bytes())?;
+		if let Some(down) = down {
+			fs::write(PathBuf::from(migrations_directory).join(format!("{file_stem}{DOWN_MIGRATION_SUFFIX}")), down)?;
+		}
+
+		println!("{} -> {}", source.display(), new_file_path.display());
+		previous_version = current_version.clone();
+	}
+
+	Ok(versions)
+}
+
+/// records the first `applied_count` of `versions` in `_schema_versions`, which must not exist yet
+fn backfill_versions(client: &mut postgres::Client, versions: &[String], applied_count: usize) -> Result<()> {
+	let table_exists = client.query_one(&format!("select exists ({EXISTS_QUERY}) as table_exists"), &[])?.get("table_exists");
+	if table_exists {
+		return Err(anyhow!("_schema_versions already exists, refusing to backfill it"));
+	}
+
+	let mut transaction = client.transaction()?;
+	create_versions_table(&mut transaction)?;
+	let mut previous_version = get_null_string();
+	for current_version in &versions[..applied_count] {
+		insert_version(&mut transaction, current_version, &previous_version)?;
+		previous_version = current_version.clone();
+	}
+	transaction.commit()?;
+
+	println!("backfilled {applied_count} applied migrations into _schema_versions");
+	Ok(())
+}
+
+/// the applied migrations must form a prefix of the chain, returns its length
+fn applied_prefix_len(applied: &[bool]) -> Result<usize> {
+	let applied_count = applied.iter().take_while(|is_applied| **is_applied).count();
+	match applied[applied_count..].iter().position(|is_applied| *is_applied) {
+		None => Ok(applied_count),
+		Some(index) => Err(anyhow!(
+			"migration {} has been applied but migration {} before it hasn't, so the history can't be represented as a chain",
+			applied_count + index + 1, applied_count + 1,
+		)),
+	}
+}
+
+#[test]
+fn test_applied_prefix_len() {
+	assert_eq!(applied_prefix_len(&[]).unwrap(), 0);
+	assert_eq!(applied_prefix_len(&[false, false]).unwrap(), 0);
+	assert_eq!(applied_prefix_len(&[true, true, false]).unwrap(), 2);
+	assert_eq!(applied_prefix_len(&[true, true, true]).unwrap(), 3);
+	assert!(applied_prefix_len(&[true, false, true]).is_err());
+	assert!(applied_prefix_len(&[false, true]).is_err());
+}
+
+
+#[derive(Debug, PartialEq, Eq)]
+enum FlywayMigration {
+	Versioned { version: Vec<u64>, description: String, file_path: PathBuf },
+	Repeatable { description: String, file_path: PathBuf },
+}
+
+/// parses flyway versions like `1`, `1.2` and `1_2`
+fn parse_flyway_version(version: &str) -> Option<Vec<u64>> {
+	version.split(['.', '_']).map(|portion| portion.parse().ok()).collect()
+}
+
+/// returns `None` for files flyway itself wouldn't run as migrations, such as undo migrations
+fn parse_flyway_file_name(file_path: &Path) -> Result<Option<FlywayMigration>> {
+	let display_file_path = file_path.display();
+	let file_stem = file_path.file_stem().and_then(|file_stem| file_stem.to_str())
+		.ok_or_else(|| anyhow!("file name isn't valid unicode: {display_file_path}"))?;
+	let (prefix, description) = match file_stem.split_once("__") {
+		Some(split) => split,
+		None => return Ok(None),
+	};
+	let description = description.replace('_', " ");
+	let file_path = file_path.to_path_buf();
+
+	if prefix == "R" {
+		return Ok(Some(FlywayMigration::Repeatable{description, file_path}));
+	}
+	match prefix.strip_prefix('V') {
+		Some(version) => {
+			let version = parse_flyway_version(version)
+				.ok_or_else(|| anyhow!("invalid flyway version in {display_file_path}"))?;
+			Ok(Some(FlywayMigration::Versioned{version, description, file_path}))
+		},
+		None => Ok(None),
+	}
+}
+
+#[test]
+fn test_parse_flyway_file_name() {
+	let path = PathBuf::from;
+	assert_eq!(
+		parse_flyway_file_name(&path("flyway/V1__create_fruit.sql")).unwrap(),
+		Some(FlywayMigration::Versioned{version: vec![1], description: "create fruit".into(), file_path: path("flyway/V1__create_fruit.sql")}),
+	);
+	assert_eq!(
+		parse_flyway_file_name(&path("flyway/V1.2__add_color.sql")).unwrap(),
+		Some(FlywayMigration::Versioned{version: vec![1, 2], description: "add color".into(), file_path: path("flyway/V1.2__add_color.sql")}),
+	);
+	assert_eq!(
+		parse_flyway_file_name(&path("flyway/V1_10__add_flavor.sql")).unwrap(),
+		Some(FlywayMigration::Versioned{version: vec![1, 10], description: "add flavor".into(), file_path: path("flyway/V1_10__add_flavor.sql")}),
+	);
+	assert_eq!(
+		parse_flyway_file_name(&path("flyway/R__fruit_view.sql")).unwrap(),
+		Some(FlywayMigration::Repeatable{description: "fruit view".into(), file_path: path("flyway/R__fruit_view.sql")}),
+	);
+	assert_eq!(parse_flyway_file_name(&path("flyway/U1__create_fruit.sql")).unwrap(), None);
+	assert_eq!(parse_flyway_file_name(&path("flyway/notes.sql")).unwrap(), None);
+	assert!(parse_flyway_file_name(&path("flyway/Vone__create_fruit.sql")).is_err());
+}
+
+/// orders flyway migrations the way flyway applies them: versioned migrations by version, then repeatables by description
+fn order_flyway_migrations(migrations: Vec<FlywayMigration>) -> Result<Vec<FlywayMigration>> {
+	let (mut versioned, mut repeatable): (Vec<_>, Vec<_>) = migrations.into_iter()
+		.partition(|migration| matches!(migration, FlywayMigration::Versioned{..}));
+
+	let version_of = |migration: &FlywayMigration| match migration {
+		FlywayMigration::Versioned{version, ..} => version.clone(),
+		FlywayMigration::Repeatable{..} => vec![],
+	};
+	versioned.sort_by_key(version_of);
+	for pair in versioned.windows(2) {
+		if version_of(&pair[0]) == version_of(&pair[1]) {
+			return Err(anyhow!("duplicate flyway version in {pair:?}"));
+		}
+	}
+	repeatable.sort_by(|a, b| match (a, b) {
+		(FlywayMigration::Repeatable{description: a, ..}, FlywayMigration::Repeatable{description: b, ..}) => a.cmp(b),
+		_ => std::cmp::Ordering::Equal,
+	});
+
+	versioned.extend(repeatable);
+	Ok(versioned)
+}
+
+#[test]
+fn test_order_flyway_migrations() {
+	let v = |version: Vec<u64>| FlywayMigration::Versioned{version, description: "".into(), file_path: PathBuf::new()};
+	let r = |description: &str| FlywayMigration::Repeatable{description: description.into(), file_path: PathBuf::new()};
+
+	assert_eq!(
+		order_flyway_migrations(vec![r("b"), v(vec![1, 10]), r("a"), v(vec![2]), v(vec![1, 2]), v(vec![1])]).unwrap(),
+		vec![v(vec![1]), v(vec![1, 2]), v(vec![1, 10]), v(vec![2]), r("a"), r("b")],
+	);
+	assert!(order_flyway_migrations(vec![v(vec![1, 2]), v(vec![1, 2])]).is_err());
+}
+
+fn import_flyway(args: &Args, directory: &str, backfill_history: bool, history_table: &str) -> Result<()> {
+	let mut migrations = vec![];
+	for file_path in list_sql_files(directory)? {
+		match parse_flyway_file_name(&file_path)? {
+			Some(migration) => migrations.push(migration),
+			None => println!("skipping {}, it isn't a versioned or repeatable flyway migration", file_path.display()),
+		}
+	}
+	let migrations = order_flyway_migrations(migrations)?;
+
+	let imported = migrations.iter().map(|migration| match migration {
+		FlywayMigration::Versioned{description, file_path, ..} | FlywayMigration::Repeatable{description, file_path} => {
+			ImportedMigration::from_file(description, file_path)
+		},
+	}).collect::<Result<Vec<_>>>()?;
+
+	if !backfill_history {
+		write_imported_chain(&args.migrations_directory, &imported)?;
+		return Ok(());
+	}
+
+	let mut client = connections::connect(&args.pg_url)?;
+	let mut applied_versions = vec![];
+	let mut applied_repeatables = vec![];
+	let query = format!("select version, description from {} where success", quote_identifier(history_table));
+	for row in client.query(&query, &[])? {
+		let version: Option<String> = row.get("version");
+		let description: String = row.get("description");
+		match version {
+			Some(version) => applied_versions.push(parse_flyway_version(&version)
+				.ok_or_else(|| anyhow!("invalid flyway version in {history_table}: {version}"))?),
+			None => applied_repeatables.push(make_slug(&description)),
+		}
+	}
+
+	let applied: Vec<bool> = migrations.iter().map(|migration| match migration {
+		FlywayMigration::Versioned{version, ..} => applied_versions.contains(version),
+		FlywayMigration::Repeatable{description, ..} => applied_repeatables.contains(&make_slug(description)),
+	}).collect();
+	let applied_count = applied_prefix_len(&applied)?;
+
+	let versions = write_imported_chain(&args.migrations_directory, &imported)?;
+	backfill_versions(&mut client, &versions, applied_count)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct SqitchChange {
+	name: String,
+	/// the deploy script of a change that is later reworked is named after the first tag following it
+	rework_tag: Option<String>,
+}
+
+/// returns the project name and the plan's changes in deploy order
+fn parse_sqitch_plan(plan: &str) -> Result<(Option<String>, Vec<SqitchChange>)> {
+	let mut project = None;
+	// (name, tags declared after this change)
+	let mut changes: Vec<(String, Vec<String>)> = vec![];
+
+	for line in plan.lines().map(str::trim) {
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		if let Some(pragma) = line.strip_prefix('%') {
+			if let Some(("project", name)) = pragma.split_once('=').map(|(key, value)| (key.trim(), value.trim())) {
+				project = Some(name.to_string());
+			}
+			continue;
+		}
+
+		let name = line.split_whitespace().next().unwrap_or_default();
+		if let Some(tag) = name.strip_prefix('@') {
+			for (_, tags) in &mut changes {
+				tags.push(tag.to_string());
+			}
+			continue;
+		}
+		if name.starts_with('-') {
+			return Err(anyhow!("sqitch plans containing reverts aren't supported: {line}"));
+		}
+		changes.push((name.trim_start_matches('+').to_string(), vec![]));
+	}
+
+	let mut result = vec![];
+	for (index, (name, tags)) in changes.iter().enumerate() {
+		let is_reworked = changes[index + 1..].iter().any(|(later_name, _)| later_name == name);
+		let rework_tag = match is_reworked {
+			false => None,
+			true => Some(tags.first().cloned()
+				.ok_or_else(|| anyhow!("change {name} is reworked without a tag in between"))?),
+		};
+		result.push(SqitchChange{name: name.clone(), rework_tag});
+	}
+	Ok((project, result))
+}
+
+#[test]
+fn test_parse_sqitch_plan() {
+	let plan = "
+		%syntax-version=1.0.0
+		%project=flipr
+		%uri=https://github.com/sqitchers/sqitch-intro/
+
+		appschema 2013-12-30T23:19:06Z Marge N. O'Vera <marge@example.com> # Add schema for all flipr objects.
+		users [appschema] 2013-12-31T00:26:59Z Marge N. O'Vera <marge@example.com> # Creates table to track our users.
+		@v1.0.0-dev1 2013-12-31T00:31:50Z Marge N. O'Vera <marge@example.com> # Tag v1.0.0-dev1.
+
+		# reworked for case insensitive names
+		flips [users] 2014-01-01T00:00:00Z Marge N. O'Vera <marge@example.com> # Adds flips.
+		@v1.0.0-dev2 2014-01-02T00:00:00Z Marge N. O'Vera <marge@example.com> # Tag v1.0.0-dev2.
+		users [users@v1.0.0-dev1] 2014-01-03T00:00:00Z Marge N. O'Vera <marge@example.com> # Lowercase user names.
+	";
+	let change = |name: &str, rework_tag: Option<&str>| SqitchChange{name: name.into(), rework_tag: rework_tag.map(Into::into)};
+	assert_eq!(
+		parse_sqitch_plan(plan).unwrap(),
+		(Some("flipr".to_string()), vec![
+			change("appschema", None),
+			change("users", Some("v1.0.0-dev1")),
+			change("flips", None),
+			change("users", None),
+		]),
+	);
+
+	assert!(parse_sqitch_plan("users 2014-01-03T00:00:00Z\nusers 2014-01-03T00:00:00Z").is_err());
+	assert!(parse_sqitch_plan("-users 2014-01-03T00:00:00Z").is_err());
+}
+
+fn import_sqitch(args: &Args, directory: &str, deploy_directory: &str, backfill_history: bool, registry: &str) -> Result<()> {
+	let project_directory = PathBuf::from(directory);
+	let (project, changes) = parse_sqitch_plan(&fs::read_to_string(project_directory.join("sqitch.plan"))?)?;
+
+	let deploy_directory = project_directory.join(deploy_directory);
+	let mut imported = vec![];
+	for SqitchChange{name, rework_tag} in &changes {
+		let file_name = match rework_tag {
+			Some(tag) => format!("{name}@{tag}.sql"),
+			None => format!("{name}.sql"),
+		};
+		imported.push(ImportedMigration::from_file(name, &deploy_directory.join(file_name))?);
+	}
+
+	if !backfill_history {
+		write_imported_chain(&args.migrations_directory, &imported)?;
+		return Ok(());
+	}
+
+	let project = project.ok_or_else(|| anyhow!("sqitch.plan has no %project pragma, which is needed to read the registry"))?;
+	let mut client = connections::connect(&args.pg_url)?;
+	let mut deployed: Vec<String> = client
+		.query(&format!("select change from {}.changes where project = $1 order by committed_at", quote_identifier(registry)), &[&project])?
+		.into_iter()
+		.map(|row| row.get("change"))
+		.collect();
+
+	// a reworked change is deployed once per rework, so each deployment is only matched once
+	let applied: Vec<bool> = changes.iter().map(|SqitchChange{name, ..}| {
+		match deployed.iter().position(|deployed_name| deployed_name == name) {
+			Some(index) => { deployed.remove(index); true },
+			None => false,
+		}
+	}).collect();
+	let applied_count = applied_prefix_len(&applied)?;
+
+	let versions = write_imported_chain(&args.migrations_directory, &imported)?;
+	backfill_versions(&mut client, &versions, applied_count)
+}
+
+/// splits names like `0001_create_fruit` into their numeric version and description
+fn parse_numbered_file_stem(file_stem: &str) -> Option<(u64, String)> {
+	let (version, description) = file_stem.split_once('_')?;
+	Some((version.parse().ok()?, description.replace('_', " ")))
+}
+
+#[test]
+fn test_parse_numbered_file_stem() {
+	assert_eq!(parse_numbered_file_stem("0001_create_fruit"), Some((1, "create fruit".to_string())));
+	assert_eq!(parse_numbered_file_stem("20151129054053_add_color"), Some((20151129054053, "add color".to_string())));
+	assert_eq!(parse_numbered_file_stem("create_fruit"), None);
+	assert_eq!(parse_numbered_file_stem("0001"), None);
+}
+
+/// sorts by numeric version, rejecting duplicate versions
+fn order_numbered_migrations(mut migrations: Vec<(u64, ImportedMigration)>) -> Result<Vec<ImportedMigration>> {
+	migrations.sort_by_key(|(version, _)| *version);
+	for pair in migrations.windows(2) {
+		if pair[0].0 == pair[1].0 {
+			return Err(anyhow!("duplicate version {} in {} and {}", pair[0].0, pair[0].1.source.display(), pair[1].1.source.display()));
+		}
+	}
+	Ok(migrations.into_iter().map(|(_, migration)| migration).collect())
+}
+
+fn import_golang_migrate(args: &Args, directory: &str) -> Result<()> {
+	let mut migrations = vec![];
+	for file_path in list_sql_files(directory)? {
+		let file_name = file_path.file_name().and_then(|file_name| file_name.to_str())
+			.ok_or_else(|| anyhow!("file name isn't valid unicode: {}", file_path.display()))?;
+		let file_stem = match file_name.strip_suffix(".up.sql") {
+			Some(file_stem) => file_stem,
+			None => {
+				if !is_down_migration(&file_path) {
+					println!("skipping {}, it isn't a golang-migrate migration", file_path.display());
+				}
+				continue;
+			},
+		};
+		let (version, description) = parse_numbered_file_stem(file_stem)
+			.ok_or_else(|| anyhow!("invalid golang-migrate file name: {}", file_path.display()))?;
+
+		let mut migration = ImportedMigration::from_file(&description, &file_path)?;
+		let down_file_path = file_path.with_file_name(format!("{file_stem}{DOWN_MIGRATION_SUFFIX}"));
+		if down_file_path.exists() {
+			migration.down = Some(read_sql_file(&down_file_path)?);
+		}
+		migrations.push((version, migration));
+	}
+
+	write_imported_chain(&args.migrations_directory, &order_numbered_migrations(migrations)?)?;
+	Ok(())
+}
+
+/// splits a dbmate migration into its `-- migrate:up` and `-- migrate:down` sections
+fn split_dbmate_migration(contents: &str) -> Result<(String, Option<String>)> {
+	let mut up = None;
+	let mut down = None;
+	let mut current: Option<&mut Option<String>> = None;
+	for line in contents.lines() {
+		match line.trim() {
+			marker if marker.starts_with("-- migrate:up") => { current = Some(&mut up); },
+			marker if marker.starts_with("-- migrate:down") => { current = Some(&mut down); },
+			_ => if let Some(section) = current.as_mut() {
+				let section = section.get_or_insert_with(String::new);
+				section.push_str(line);
+				section.push('\n');
+			},
+		}
+	}
+
+	let up = up.ok_or_else(|| anyhow!("no -- migrate:up section"))?;
+	let down = down.filter(|down| !down.trim().is_empty());
+	Ok((up.trim().to_string(), down.map(|down| down.trim().to_string())))
+}
+
+#[test]
+fn test_split_dbmate_migration() {
+	assert_eq!(
+		split_dbmate_migration("-- migrate:up\ncreate table fruit ();\n\n-- migrate:down\ndrop table fruit;\n").unwrap(),
+		("create table fruit ();".to_string(), Some("drop table fruit;".to_string())),
+	);
+	assert_eq!(
+		split_dbmate_migration("-- migrate:up transaction:false\ncreate index concurrently on fruit (name);\n-- migrate:down\n").unwrap(),
+		("create index concurrently on fruit (name);".to_string(), None),
+	);
+	assert!(split_dbmate_migration("create table fruit ();").is_err());
+}
+
+fn import_dbmate(args: &Args, directory: &str) -> Result<()> {
+	let mut migrations = vec![];
+	for file_path in list_sql_files(directory)? {
+		let display_file_path = file_path.display();
+		let file_stem = file_path.file_stem().and_then(|file_stem| file_stem.to_str())
+			.ok_or_else(|| anyhow!("file name isn't valid unicode: {display_file_path}"))?;
+		let (version, description) = match parse_numbered_file_stem(file_stem) {
+			Some(parsed) => parsed,
+			None => {
+				println!("skipping {display_file_path}, it isn't a dbmate migration");
+				continue;
+			},
+		};
+
+		let (up, down) = split_dbmate_migration(&read_sql_file(&file_path)?)
+			.map_err(|err| anyhow!("{err} in {display_file_path}"))?;
+		migrations.push((version, ImportedMigration{description, source: file_path.clone(), up, down}));
+	}
+
+	write_imported_chain(&args.migrations_directory, &order_numbered_migrations(migrations)?)?;
+	Ok(())
+}
+
+pub(crate) fn command_import(args: &Args, source: &ImportSource) -> Result<()> {
+	match source {
+		ImportSource::Flyway{directory, backfill_history, history_table} => {
+			import_flyway(args, directory, *backfill_history, history_table)
+		},
+		ImportSource::Sqitch{directory, deploy_directory, backfill_history, registry} => {
+			import_sqitch(args, directory, deploy_directory, *backfill_history, registry)
+		},
+		ImportSource::GolangMigrate{directory} => import_golang_migrate(args, directory),
+		ImportSource::Dbmate{directory} => import_dbmate(args, directory),
+		ImportSource::Prisma{file} => super::prisma::import_prisma(args, file),
+	}
+}
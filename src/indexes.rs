@@ -0,0 +1,132 @@
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+
+use super::{Args, MigrationFile, create_versions_table, gather_validated_migrations, insert_version, is_pending, query_actual_version};
+use super::{connections, read_sql_file};
+use super::statements::split_statements;
+
+/// a `create index` statement rewritten to build concurrently
+#[derive(Debug, PartialEq, Eq)]
+struct ConcurrentIndex {
+	/// name of the index, qualified with the schema of its table when that is qualified
+	name: String,
+	sql: String,
+}
+
+fn concurrent_index(sql: &str) -> Result<ConcurrentIndex> {
+	let identifier = r#"(?:"[^"]+"|[\w$]+)"#;
+	let pattern = format!(r"(?is)^create\s+(unique\s+)?index\s+(?:concurrently\s+)?(if\s+not\s+exists\s+)?({identifier})\s+on\s+(?:only\s+)?(?:({identifier})\.)?{identifier}\b");
+	let captures = Regex::new(&pattern).unwrap().captures(sql)
+		.ok_or_else(|| anyhow!("only named `create index` statements can be built concurrently, not:\n{sql}"))?;
+
+	let unique = if captures.get(1).is_some() { "unique " } else { "" };
+	let if_not_exists = if captures.get(2).is_some() { "if not exists " } else { "" };
+	let index_name = &captures[3];
+	let name = match captures.get(4) {
+		Some(schema) => format!("{}.{index_name}", schema.as_str()),
+		None => index_name.to_string(),
+	};
+	let definition = &sql[captures.get(3).unwrap().start()..];
+	Ok(ConcurrentIndex{ name, sql: format!("create {unique}index concurrently {if_not_exists}{definition}") })
+}
+
+#[test]
+fn test_concurrent_index() {
+	assert_eq!(concurrent_index("create index fruit_name on fruit (name)").unwrap(), ConcurrentIndex{
+		name: "fruit_name".into(),
+		sql: "create index concurrently fruit_name on fruit (name)".into(),
+	});
+	assert_eq!(concurrent_index("CREATE UNIQUE INDEX CONCURRENTLY IF NOT EXISTS \"Fruit_Name\" ON ONLY app.fruit USING btree (name)").unwrap(), ConcurrentIndex{
+		name: "app.\"Fruit_Name\"".into(),
+		sql: "create unique index concurrently if not exists \"Fruit_Name\" ON ONLY app.fruit USING btree (name)".into(),
+	});
+	assert!(concurrent_index("create index on fruit (name)").is_err());
+	assert!(concurrent_index("alter table fruit add color text").is_err());
+}
+
+/// `Some(is_valid)` if the index exists
+fn query_index_validity(client: &mut postgres::Client, name: &str) -> Result<Option<bool>> {
+	Ok(client.query_opt("select indisvalid from pg_catalog.pg_index where indexrelid = to_regclass($1)", &[&name])?
+		.map(|row| row.get("indisvalid")))
+}
+
+/// builds the index outside of any transaction, retrying after dropping the invalid index a failed concurrent build leaves behind
+fn build_concurrently(client: &mut postgres::Client, index: &ConcurrentIndex, retries: u32) -> Result<()> {
+	let mut attempt = 0;
+	loop {
+		match query_index_validity(client, &index.name)? {
+			Some(true) => {
+				println!("{} already exists", index.name);
+				return Ok(());
+			},
+			Some(false) => {
+				println!("dropping invalid index {}", index.name);
+				client.batch_execute(&format!("drop index concurrently {}", index.name))?;
+			},
+			None => {},
+		}
+
+		println!("building {}", index.name);
+		match client.batch_execute(&index.sql) {
+			Ok(()) => return Ok(()),
+			Err(err) if attempt < retries => {
+				attempt += 1;
+				eprintln!("building {} failed, retrying ({attempt} of {retries}): {err}", index.name);
+			},
+			Err(err) => return Err(err).with_context(|| format!("unable to build {}", index.name)),
+		}
+	}
+}
+
+#[test]
+#[ignore]
+fn test_build_concurrently_failures() -> Result<()> {
+	let mut client = std::env::var("PG_URL")?.parse::<postgres::Config>()?.connect(postgres::NoTls)?;
+	client.batch_execute("
+		drop table if exists indexed_fruit;
+		create table indexed_fruit (name text);
+		insert into indexed_fruit values ('apple'), ('apple');
+	")?;
+	let index = concurrent_index("create unique index indexed_fruit_name on indexed_fruit (name)")?;
+
+	// every attempt fails on the duplicates, leaving an invalid index behind
+	let error = build_concurrently(&mut client, &index, 1).unwrap_err();
+	assert_eq!(error.to_string(), "unable to build indexed_fruit_name");
+	assert_eq!(query_index_validity(&mut client, &index.name)?, Some(false));
+
+	// the invalid index is dropped and built again
+	client.batch_execute("delete from indexed_fruit where ctid = (select max(ctid) from indexed_fruit)")?;
+	build_concurrently(&mut client, &index, 0)?;
+	assert_eq!(query_index_validity(&mut client, &index.name)?, Some(true));
+
+	client.batch_execute("drop table indexed_fruit")?;
+	Ok(())
+}
+
+/// applies the next pending migration, which must consist only of `create index` statements,
+/// by building each index concurrently and only then recording the migration as applied
+pub(crate) fn command_create_indexes(args: &Args, retries: u32) -> Result<()> {
+	let (migration_files, _) = gather_validated_migrations(args)?;
+	let mut client = connections::connect(&args.pg_url)?;
+	let actual_version = query_actual_version(&mut client)?;
+
+	let MigrationFile{file_path, display_file_path, current_version, previous_version, is_onboard} = migration_files.iter()
+		.find(|migration_file| is_pending(&migration_file.current_version, &actual_version))
+		.ok_or_else(|| anyhow!("there are no pending migrations"))?;
+	if *is_onboard {
+		return Err(anyhow!("{display_file_path} is an onboard migration"));
+	}
+
+	let indexes = split_statements(&read_sql_file(file_path)?)?.iter()
+		.map(|statement| concurrent_index(&statement.sql).with_context(|| format!("{display_file_path}, line {}", statement.line)))
+		.collect::<Result<Vec<_>>>()?;
+
+	for index in &indexes {
+		build_concurrently(&mut client, index, retries)?;
+	}
+
+	create_versions_table(&mut client)?;
+	insert_version(&mut client, current_version, previous_version)?;
+	println!("recorded {display_file_path}");
+	Ok(())
+}
@@ -0,0 +1,109 @@
+use std::{process::Child, sync::{Mutex, atomic::{AtomicU64, Ordering}}};
+use postgres::{CancelToken, Config};
+
+use super::quote_identifier;
+
+/// exit code of a run stopped by SIGINT or SIGTERM, the one shells use for SIGINT
+pub(crate) const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// temp databases that exist right now, with the config of the server they're on
+static TEMP_DBS: Mutex<Vec<(String, Config)>> = Mutex::new(Vec::new());
+/// connections whose running queries are cancelled on interrupt, so their transactions roll back right away
+static CANCEL_TOKENS: Mutex<Vec<(u64, CancelToken)>> = Mutex::new(Vec::new());
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(0);
+/// long running helper processes, such as ssh tunnels, that have to be killed before exiting
+static CHILDREN: Mutex<Vec<Child>> = Mutex::new(Vec::new());
+
+pub(crate) fn register_temp_db(dbname: &str, base_config: &Config) {
+	TEMP_DBS.lock().unwrap().push((dbname.to_string(), base_config.clone()));
+}
+
+pub(crate) fn unregister_temp_db(dbname: &str) {
+	TEMP_DBS.lock().unwrap().retain(|(temp_dbname, _)| temp_dbname != dbname);
+}
+
+/// keeps the query of a registered client cancellable on interrupt until it's dropped
+#[must_use = "the client is unregistered as soon as this is dropped"]
+pub(crate) struct RegisteredClient(u64);
+
+impl Drop for RegisteredClient {
+	fn drop(&mut self) {
+		CANCEL_TOKENS.lock().unwrap().retain(|(id, _)| *id != self.0);
+	}
+}
+
+pub(crate) fn register_client(client: &postgres::Client) -> RegisteredClient {
+	let id = NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed);
+	CANCEL_TOKENS.lock().unwrap().push((id, client.cancel_token()));
+	RegisteredClient(id)
+}
+
+#[test]
+#[ignore]
+fn test_register_client() -> Result<(), Box<dyn std::error::Error>> {
+	let config = std::env::var("PG_URL")?.parse::<Config>()?;
+	let registered_ids = || CANCEL_TOKENS.lock().unwrap().iter().map(|(id, _)| *id).collect::<Vec<_>>();
+	let (client, other_client) = (config.connect(postgres::NoTls)?, config.connect(postgres::NoTls)?);
+
+	let registered = register_client(&client);
+	let other_registered = register_client(&other_client);
+	assert!(registered_ids().contains(&registered.0) && registered_ids().contains(&other_registered.0));
+
+	let id = registered.0;
+	drop(registered);
+	assert!(!registered_ids().contains(&id));
+	assert!(registered_ids().contains(&other_registered.0));
+	Ok(())
+}
+
+/// hands `child` over to be killed on interrupt, or with `kill_child` once it's no longer needed
+pub(crate) fn register_child(child: Child) -> u32 {
+	let id = child.id();
+	CHILDREN.lock().unwrap().push(child);
+	id
+}
+
+pub(crate) fn kill_child(id: u32) {
+	let mut children = CHILDREN.lock().unwrap();
+	if let Some(index) = children.iter().position(|child| child.id() == id) {
+		let mut child = children.remove(index);
+		let _ = child.kill();
+		let _ = child.wait();
+	}
+}
+
+/// drops a database even while something, like migra or the interrupted run itself, is still connected to it
+pub(crate) fn force_drop_database(mut base_config: Config, dbname: &str) -> Result<(), postgres::Error> {
+	let mut client = base_config.dbname("template1").connect(postgres::NoTls)?;
+	client.execute("select pg_terminate_backend(pid) from pg_catalog.pg_stat_activity where datname = $1 and pid <> pg_backend_pid()", &[&dbname])?;
+	client.batch_execute(&format!("drop database if exists {}", quote_identifier(dbname)))
+}
+
+fn clean_up() {
+	for (_, cancel_token) in CANCEL_TOKENS.lock().unwrap().iter() {
+		let _ = cancel_token.cancel_query(postgres::NoTls);
+	}
+	// taken rather than locked throughout, in case the main thread is in the middle of dropping one
+	let temp_dbs = std::mem::take(&mut *TEMP_DBS.lock().unwrap());
+	for (dbname, base_config) in temp_dbs {
+		if let Err(err) = force_drop_database(base_config, &dbname) {
+			eprintln!("unable to drop {dbname}: {err}");
+		}
+	}
+	// last, since dropping temp databases may go through a tunnel
+	for mut child in std::mem::take(&mut *CHILDREN.lock().unwrap()) {
+		let _ = child.kill();
+	}
+}
+
+/// on SIGINT or SIGTERM, cancels running queries, drops temp databases, and exits with `INTERRUPTED_EXIT_CODE`
+pub(crate) fn install_handler() {
+	let installed = ctrlc::set_handler(|| {
+		eprintln!("interrupted, rolling back and dropping temp databases");
+		clean_up();
+		std::process::exit(INTERRUPTED_EXIT_CODE);
+	});
+	if let Err(err) = installed {
+		eprintln!("warning: unable to handle interrupts, temp databases may be left behind if this run is stopped: {err}");
+	}
+}
@@ -0,0 +1,518 @@
+//! Reads and validates a `postgres_migrator` migrations directory.
+
+use std::{io, path::{Path, PathBuf}};
+use anyhow::{anyhow, Result};
+use walkdir::WalkDir;
+
+pub mod backfill;
+pub mod startup;
+
+/// the `previous_version` of the first migration in a chain
+pub fn get_null_string() -> String {
+	"null".to_string()
+}
+
+
+/// extensions are compared case insensitively, since editors on windows and macos happily save `.SQL` files
+pub fn has_sql_extension(path: &Path) -> bool {
+	path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("sql"))
+}
+
+pub fn list_sql_files(directory: &str) -> io::Result<Vec<PathBuf>> {
+	let mut entries = vec![];
+
+	for entry in WalkDir::new(directory) {
+		let path = entry?.into_path();
+		if !path.is_dir() && has_sql_extension(&path) {
+			entries.push(path);
+		}
+	}
+	entries.sort();
+	Ok(entries)
+}
+
+
+/// the table that records which migrations have been applied, each row pointing at the one before it
+pub const VERSIONS_TABLE_SQL: &str = "
+	create table _schema_versions (
+		current_version char(14) not null unique,
+		previous_version char(14) references _schema_versions(current_version) unique,
+		check (current_version > previous_version)
+	);
+	create unique index if not exists i_schema_versions on _schema_versions ((previous_version is null)) where previous_version is null;
+";
+
+
+/// down migrations are carried along next to their migration, but are never applied
+pub const DOWN_MIGRATION_SUFFIX: &str = ".down.sql";
+
+pub fn is_down_migration(path: &Path) -> bool {
+	path.file_name().is_some_and(|file_name| file_name.to_string_lossy().to_lowercase().ends_with(DOWN_MIGRATION_SUFFIX))
+}
+
+/// migrations can also be named like `<version>.<previous_version>.<description>.up.sql`, in which case
+/// their `.down.sql` has to be next to them, for repositories that already keep both directions side by side
+pub const UP_MIGRATION_SUFFIX: &str = ".up.sql";
+
+pub fn is_up_migration(path: &Path) -> bool {
+	path.file_name().is_some_and(|file_name| file_name.to_string_lossy().to_lowercase().ends_with(UP_MIGRATION_SUFFIX))
+}
+
+/// the down migration that belongs next to the migration at `path`, whether or not it exists
+pub fn down_migration_path(path: &Path) -> PathBuf {
+	let file_stem = path.file_stem().unwrap_or_default().to_string_lossy();
+	let file_stem = match is_up_migration(path) {
+		true => &file_stem[..file_stem.len() - ".up".len()],
+		false => &file_stem,
+	};
+	path.with_file_name(format!("{file_stem}{DOWN_MIGRATION_SUFFIX}"))
+}
+
+#[test]
+fn test_down_migration_path() {
+	assert_eq!(down_migration_path(Path::new("migrations/20220101000000.null.fruit.sql")), PathBuf::from("migrations/20220101000000.null.fruit.down.sql"));
+	assert_eq!(down_migration_path(Path::new("migrations/2022/20220101000000.null.fruit.up.sql")), PathBuf::from("migrations/2022/20220101000000.null.fruit.down.sql"));
+	assert_eq!(down_migration_path(Path::new("migrations/20220101000000.null.UP.SQL")), PathBuf::from("migrations/20220101000000.null.down.sql"));
+}
+
+/// the subdirectory of the migrations directory that `archive` moves migrations into. they're still part of the chain,
+/// so fresh databases and the migrations backend still replay them, but are left alone by commands that change migrations
+pub const ARCHIVE_DIRECTORY_NAME: &str = "archive";
+
+pub fn is_archived(migrations_directory: &str, path: &Path) -> bool {
+	path.strip_prefix(migrations_directory).is_ok_and(|relative_path| relative_path.starts_with(ARCHIVE_DIRECTORY_NAME))
+}
+
+#[test]
+fn test_is_archived() {
+	assert!(is_archived("migrations", Path::new("migrations/archive/20220101000000.null.a.sql")));
+	assert!(is_archived("migrations", Path::new("migrations/archive/2022/20220101000000.null.a.sql")));
+	assert!(!is_archived("migrations", Path::new("migrations/2022/20220101000000.null.a.sql")));
+	assert!(!is_archived("migrations", Path::new("migrations/archived/20220101000000.null.a.sql")));
+}
+
+/// sorted by file name rather than path, so migrations can be grouped into subdirectories like `migrations/2024/`
+/// and still be in the order of their versions
+pub fn list_migration_files(directory: &str) -> io::Result<Vec<PathBuf>> {
+	let mut migration_files: Vec<PathBuf> = list_sql_files(directory)?.into_iter()
+		.filter(|path| !is_down_migration(path))
+		.collect();
+	sort_by_file_name(&mut migration_files);
+	Ok(migration_files)
+}
+
+pub fn list_down_migration_files(directory: &str) -> io::Result<Vec<PathBuf>> {
+	Ok(list_sql_files(directory)?.into_iter().filter(|path| is_down_migration(path)).collect())
+}
+
+fn sort_by_file_name(paths: &mut [PathBuf]) {
+	paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()).then_with(|| a.cmp(b)));
+}
+
+#[test]
+fn test_sort_by_file_name() {
+	let mut paths = vec![
+		PathBuf::from("migrations/20240101000000.null.a.sql"),
+		PathBuf::from("migrations/2023/20231201000000.null.b.sql"),
+		PathBuf::from("migrations/2024/20240201000000.20240101000000.c.sql"),
+	];
+	sort_by_file_name(&mut paths);
+	assert_eq!(paths, vec![
+		PathBuf::from("migrations/2023/20231201000000.null.b.sql"),
+		PathBuf::from("migrations/20240101000000.null.a.sql"),
+		PathBuf::from("migrations/2024/20240201000000.20240101000000.c.sql"),
+	]);
+}
+
+/// separates paths whose file names are valid unicode, and so can be parsed as migrations, from those that aren't
+pub fn split_non_unicode_file_names(file_paths: Vec<PathBuf>) -> (Vec<PathBuf>, Vec<PathBuf>) {
+	file_paths.into_iter().partition(|file_path| file_path.file_name().is_some_and(|file_name| file_name.to_str().is_some()))
+}
+
+#[cfg(unix)]
+#[test]
+fn test_split_non_unicode_file_names() {
+	use std::os::unix::ffi::OsStrExt;
+	let vendor_file = PathBuf::from("migrations").join(std::ffi::OsStr::from_bytes(b"vendor\xFF.sql"));
+	let migration_file = PathBuf::from("migrations/20220101000000.null.sql");
+	assert_eq!(
+		split_non_unicode_file_names(vec![migration_file.clone(), vendor_file.clone()]),
+		(vec![migration_file], vec![vendor_file]),
+	);
+}
+
+#[test]
+fn test_sql_file_names() {
+	assert!(has_sql_extension(Path::new("migrations/20220101000000.null.sql")));
+	assert!(has_sql_extension(Path::new("schema/FRUIT.SQL")));
+	assert!(!has_sql_extension(Path::new("migrations/migrations.lock")));
+	assert!(!has_sql_extension(Path::new("schema/sql")));
+
+	assert!(is_down_migration(Path::new("migrations/20220101000000.null.fruit.down.sql")));
+	assert!(is_down_migration(Path::new("migrations/20220101000000.null.fruit.DOWN.SQL")));
+	assert!(!is_down_migration(Path::new("migrations/20220101000000.null.down_with_fruit.sql")));
+}
+
+#[cfg(windows)]
+#[test]
+fn test_windows_paths() {
+	assert!(is_down_migration(Path::new(r"migrations\20220101000000.null.fruit.down.sql")));
+	let migration_files = MigrationFile::vec_from_paths(vec![PathBuf::from(r"migrations\20220101000000.null.fruit.sql")]).unwrap();
+	assert_eq!(migration_files[0].current_version, "20220101000000");
+	assert_eq!(migration_files[0].display_file_path, r"migrations\20220101000000.null.fruit.sql");
+}
+
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct MigrationFile {
+	pub file_path: PathBuf,
+	pub display_file_path: String,
+	pub current_version: String,
+	pub previous_version: String,
+	pub is_onboard: bool,
+}
+
+impl MigrationFile {
+	/// file_paths is expected to be sorted by file name. down migrations among them are only checked against
+	/// the migrations they belong to, and every `.up.sql` migration needs its down migration among them
+	pub fn vec_from_paths(file_paths: Vec<PathBuf>) -> Result<Vec<MigrationFile>> {
+		let (down_paths, file_paths): (Vec<PathBuf>, Vec<PathBuf>) = file_paths.into_iter().partition(|file_path| is_down_migration(file_path));
+		let mut migration_files = vec![];
+		let mut last_seen_current_version = get_null_string();
+
+		for (index, file_path) in file_paths.into_iter().enumerate() {
+			let display_file_path = file_path.to_string_lossy().to_string();
+
+			// first parse the file_name and version strings
+			let file_name = file_path.file_name().ok_or_else(|| anyhow!("no file name for this path: {display_file_path}"))?;
+			let file_name = file_name.to_str().ok_or_else(|| anyhow!("file name isn't valid unicode: {display_file_path}"))?;
+			let mut portions = file_name.split(".");
+			let current_version = portions.next()
+				.ok_or_else(|| anyhow!("no version strings in this path: {display_file_path}"))?.to_string();
+			let previous_version = portions.next()
+				.ok_or_else(|| anyhow!("no previous version string in this path: {display_file_path}"))?.to_string();
+
+			// then check that the version strings align with the previous one
+			if previous_version == "onboard" && last_seen_current_version == "null" {
+				last_seen_current_version = "onboard".to_string()
+			}
+			if current_version == last_seen_current_version {
+				return Err(anyhow!("{display_file_path} has the same version as the migration before it, a migration can only be in one directory"));
+			}
+			if previous_version != last_seen_current_version {
+				return Err(anyhow!("misaligned versions in {display_file_path}: expected {last_seen_current_version}, got {previous_version}"));
+			}
+			last_seen_current_version = current_version.clone();
+
+			let validate_version_string = |version_string: String| {
+				match version_string.len() {
+					14 => Ok(version_string),
+					_ => Err(anyhow!("{version_string} is supposed to have exactly 14 characters")),
+				}
+			};
+			let current_version = validate_version_string(current_version)?;
+			let is_onboard = previous_version == "onboard";
+			let previous_version = match previous_version == "null" || is_onboard {
+				true => {
+					// check that nulls are only allowed in the first spot
+					if index != 0 {
+						return Err(anyhow!("null or onboard previous_version in migration that isn't the first: {display_file_path}"));
+					}
+					get_null_string()
+				},
+				false => {
+					if current_version <= previous_version {
+						return Err(anyhow!("all migration versions have to be sequential, so {current_version} must be greater than {previous_version}"));
+					}
+					validate_version_string(previous_version)?
+				}
+			};
+
+			migration_files.push(MigrationFile{file_path, display_file_path, current_version, previous_version, is_onboard});
+		}
+
+		let same_path = |a: &Path, b: &Path| a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase();
+		for migration_file in migration_files.iter().filter(|migration_file| is_up_migration(&migration_file.file_path)) {
+			let down_path = down_migration_path(&migration_file.file_path);
+			if !down_paths.iter().any(|file_path| same_path(file_path, &down_path)) {
+				return Err(anyhow!("{} has no {} next to it, a {UP_MIGRATION_SUFFIX} migration needs its down migration", migration_file.display_file_path, down_path.display()));
+			}
+		}
+		for down_path in &down_paths {
+			if !migration_files.iter().any(|migration_file| same_path(&down_migration_path(&migration_file.file_path), down_path)) {
+				return Err(anyhow!("{} isn't next to the migration it belongs to", down_path.display()));
+			}
+		}
+
+		Ok(migration_files)
+	}
+}
+
+
+#[test]
+fn test_migration_files_vec_from_paths() {
+	let ex = |file_path: PathBuf, current_version: &str, previous_version: &str| {
+		let display_file_path = file_path.to_string_lossy().to_string();
+		let is_onboard = previous_version == "onboard";
+		MigrationFile{
+			file_path, display_file_path,
+			current_version: current_version.to_string(),
+			previous_version: if is_onboard { get_null_string() } else { previous_version.to_string() },
+			is_onboard,
+		}
+	};
+	let version = "20220101000000";
+
+	assert!(MigrationFile::vec_from_paths(vec![PathBuf::from("err/short.sql")]).is_err());
+	assert!(MigrationFile::vec_from_paths(vec![PathBuf::from("err/short.short.sql")]).is_err());
+	assert!(MigrationFile::vec_from_paths(vec![PathBuf::from(format!("err/{version}.{version}.sql"))]).is_err());
+	assert!(MigrationFile::vec_from_paths(vec![PathBuf::from(format!("err/null.{version}.sql"))]).is_err());
+	assert!(MigrationFile::vec_from_paths(vec![
+		PathBuf::from(format!("err/{version}.null.sql")),
+		PathBuf::from("err/90000000000000.null.sql"),
+	]).is_err());
+	assert!(MigrationFile::vec_from_paths(vec![
+		PathBuf::from(format!("err/{version}.null.sql")),
+		PathBuf::from(format!("err/null.{version}.sql")),
+	]).is_err());
+
+	assert_eq!(MigrationFile::vec_from_paths(vec![]).unwrap(), vec![]);
+
+	let file_path = PathBuf::from(format!("ok/{version}.null.sql"));
+	assert_eq!(
+		MigrationFile::vec_from_paths(vec![file_path.clone()]).unwrap(),
+		vec![ex(file_path, version, "null")],
+	);
+	let file_path = PathBuf::from(format!("ok/{version}.onboard.sql"));
+	assert_eq!(
+		MigrationFile::vec_from_paths(vec![file_path.clone()]).unwrap(),
+		vec![ex(file_path, version, "onboard")],
+	);
+
+	let file_path1 = PathBuf::from(format!("ok/{version}.null.sql"));
+	let file_path2 = PathBuf::from(format!("ok/90000000000000.{version}.sql"));
+	let file_path3 = PathBuf::from("ok/90000000000001.90000000000000.sql");
+	let file_path4 = PathBuf::from("ok/90000000000002.90000000000001.sql");
+	assert_eq!(
+		MigrationFile::vec_from_paths(vec![file_path1.clone(), file_path2.clone(), file_path3.clone(), file_path4.clone()]).unwrap(),
+		vec![
+			ex(file_path1, version, "null"),
+			ex(file_path2, "90000000000000", version),
+			ex(file_path3, "90000000000001", "90000000000000"),
+			ex(file_path4, "90000000000002", "90000000000001"),
+		],
+	);
+
+	let file_path1 = PathBuf::from(format!("ok/{version}.onboard.sql"));
+	let file_path2 = PathBuf::from(format!("ok/90000000000000.{version}.sql"));
+	let file_path3 = PathBuf::from("ok/90000000000001.90000000000000.sql");
+	let file_path4 = PathBuf::from("ok/90000000000002.90000000000001.sql");
+	assert_eq!(
+		MigrationFile::vec_from_paths(vec![file_path1.clone(), file_path2.clone(), file_path3.clone(), file_path4.clone()]).unwrap(),
+		vec![
+			ex(file_path1, version, "onboard"),
+			ex(file_path2, "90000000000000", version),
+			ex(file_path3, "90000000000001", "90000000000000"),
+			ex(file_path4, "90000000000002", "90000000000001"),
+		],
+	);
+
+	let file_path1 = PathBuf::from(format!("ok/{version}.null.create_fruit.up.sql"));
+	let file_path2 = PathBuf::from(format!("ok/90000000000000.{version}.add_color.sql"));
+	assert_eq!(
+		MigrationFile::vec_from_paths(vec![
+			file_path1.clone(), PathBuf::from(format!("ok/{version}.null.create_fruit.down.sql")),
+			file_path2.clone(), PathBuf::from(format!("ok/90000000000000.{version}.add_color.down.sql")),
+		]).unwrap(),
+		vec![ex(file_path1.clone(), version, "null"), ex(file_path2.clone(), "90000000000000", version)],
+	);
+	assert!(MigrationFile::vec_from_paths(vec![file_path1, file_path2.clone()]).is_err());
+	assert!(MigrationFile::vec_from_paths(vec![file_path2, PathBuf::from("ok/90000000000000.null.add_colour.down.sql")]).is_err());
+}
+
+/// line endings are normalized first, so a checkout with `core.autocrlf` has the same checksums as one without
+pub fn compute_checksum(contents: &[u8]) -> String {
+	use sha2::Digest;
+	let mut hasher = sha2::Sha256::new();
+	let mut rest = contents;
+	while let Some(index) = rest.windows(2).position(|pair| pair == b"\r\n") {
+		hasher.update(&rest[..index]);
+		rest = &rest[index + 1..];
+	}
+	hasher.update(rest);
+	hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+
+#[test]
+fn test_compute_checksum() {
+	assert_eq!(compute_checksum(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+	assert_eq!(compute_checksum(b"create table fruit ();").len(), 64);
+	assert_ne!(compute_checksum(b"create table fruit ();"), compute_checksum(b"create table fruits ();"));
+	assert_eq!(compute_checksum(b"create table fruit ();\r\ncreate table tree ();\r\n"), compute_checksum(b"create table fruit ();\ncreate table tree ();\n"));
+	assert_ne!(compute_checksum(b"select '\r';"), compute_checksum(b"select '';"));
+}
+
+
+/// decodes the contents of a sql file, stripping a utf-8 byte order mark and converting utf-16,
+/// both of which some windows editors save by default
+pub fn decode_sql(contents: &[u8]) -> Result<String> {
+	let decode_utf16 = |contents: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+		if !contents.len().is_multiple_of(2) {
+			return Err(anyhow!("it looks like utf-16, but has an odd number of bytes"));
+		}
+		let units = contents.chunks_exact(2).map(|pair| from_bytes([pair[0], pair[1]])).collect::<Vec<_>>();
+		String::from_utf16(&units).map_err(|_| anyhow!("it looks like utf-16, but isn't valid utf-16"))
+	};
+
+	match contents {
+		[0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8(rest.to_vec()).map_err(|err| anyhow!("it isn't valid utf-8: {err}")),
+		[0xFF, 0xFE, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+		[0xFE, 0xFF, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+		// utf-16 without a byte order mark, recognizable since sql starts with an ascii character
+		[first, 0, ..] if *first != 0 => decode_utf16(contents, u16::from_le_bytes),
+		[0, second, ..] if *second != 0 => decode_utf16(contents, u16::from_be_bytes),
+		_ => String::from_utf8(contents.to_vec()).map_err(|err| anyhow!("it isn't valid utf-8 or utf-16: {err}")),
+	}
+}
+
+#[test]
+fn test_decode_sql() {
+	let sql = "create table fruit (name text default 'pomme de terre, ou là');";
+	let utf16 = |to_bytes: fn(u16) -> [u8; 2]| sql.encode_utf16().flat_map(to_bytes).collect::<Vec<u8>>();
+
+	assert_eq!(decode_sql(sql.as_bytes()).unwrap(), sql);
+	assert_eq!(decode_sql(b"").unwrap(), "");
+	assert_eq!(decode_sql(&[b"\xEF\xBB\xBF".as_slice(), sql.as_bytes()].concat()).unwrap(), sql);
+	assert_eq!(decode_sql(&[[0xFF, 0xFE].as_slice(), &utf16(u16::to_le_bytes)].concat()).unwrap(), sql);
+	assert_eq!(decode_sql(&[[0xFE, 0xFF].as_slice(), &utf16(u16::to_be_bytes)].concat()).unwrap(), sql);
+	assert_eq!(decode_sql(&utf16(u16::to_le_bytes)).unwrap(), sql);
+
+	assert!(decode_sql(b"select '\xE9';").is_err());
+	assert!(decode_sql(&[0xFF, 0xFE, b's']).is_err());
+}
+
+/// reads a sql file, naming the file in any decoding error
+pub fn read_sql_file(file_path: &Path) -> Result<String> {
+	let contents = std::fs::read(file_path).map_err(|err| anyhow!("unable to read {}: {err}", file_path.display()))?;
+	decode_sql(&contents).map_err(|err| anyhow!("unable to decode {}, {err}", file_path.display()))
+}
+
+
+/// the description portion of a `current_version.previous_version.description.sql` file name
+pub fn migration_description(file_path: &Path) -> String {
+	let file_stem = file_path.file_stem().and_then(|file_stem| file_stem.to_str()).unwrap_or("");
+	let file_stem = match is_up_migration(file_path) {
+		true => &file_stem[..file_stem.len() - ".up".len()],
+		false => file_stem,
+	};
+	file_stem.splitn(3, '.').nth(2).unwrap_or("").to_string()
+}
+
+#[test]
+fn test_migration_description() {
+	assert_eq!(migration_description(Path::new("migrations/20220102000000.20220101000000.add_color.sql")), "add_color");
+	assert_eq!(migration_description(Path::new("migrations/20220101000000.null.sql")), "");
+	assert_eq!(migration_description(Path::new("migrations/20220102000000.20220101000000.add_color.up.sql")), "add_color");
+	assert_eq!(migration_description(Path::new("migrations/20220101000000.null.up.sql")), "");
+}
+
+/// a migration from a validated chain, ready to be applied by an embedded runner
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Migration {
+	/// 14 digit version, which is also the value `postgres_migrator` stores in `_schema_versions`
+	pub version: String,
+	/// `None` for the first migration in the chain
+	pub previous_version: Option<String>,
+	pub description: String,
+	pub sql: String,
+	/// hex encoded sha256 of `sql` once [`compute_checksum`] normalizes its line endings, the same checksum `generate` records in `migrations.lock`
+	pub checksum: String,
+	/// onboard migrations describe a schema the database already had before it was managed by `postgres_migrator`,
+	/// so runners should only record them as applied rather than executing them against existing databases
+	pub is_onboard: bool,
+}
+
+impl Migration {
+	/// the `V{version}__{description}` name refinery's `Migration::unapplied` expects.
+	/// versions are 14 digits, so refinery needs its `int8-versions` feature enabled
+	pub fn refinery_name(&self) -> String {
+		format!("V{}__{}", self.version, self.description)
+	}
+}
+
+fn build_migrations(sources: Vec<(PathBuf, String)>) -> Result<Vec<Migration>> {
+	let (down_sources, mut sources): (Vec<_>, Vec<_>) = sources.into_iter().partition(|(file_path, _)| is_down_migration(file_path));
+	sources.sort_by(|(a, _), (b, _)| a.file_name().cmp(&b.file_name()).then_with(|| a.cmp(b)));
+	let (file_paths, sqls): (Vec<_>, Vec<_>) = sources.into_iter().unzip();
+
+	let down_paths = down_sources.into_iter().map(|(file_path, _)| file_path);
+	let migration_files = MigrationFile::vec_from_paths(file_paths.into_iter().chain(down_paths).collect())?;
+	Ok(migration_files.into_iter().zip(sqls).map(|(migration_file, sql)| {
+		let MigrationFile{file_path, current_version, previous_version, is_onboard, ..} = migration_file;
+		Migration {
+			description: migration_description(&file_path),
+			checksum: compute_checksum(sql.as_bytes()),
+			previous_version: if previous_version == get_null_string() { None } else { Some(previous_version) },
+			version: current_version,
+			sql, is_onboard,
+		}
+	}).collect())
+}
+
+/// reads and validates the migration chain in `migrations_directory`
+pub fn load_migrations(migrations_directory: &str) -> Result<Vec<Migration>> {
+	let mut sources = vec![];
+	for file_path in list_migration_files(migrations_directory)? {
+		let sql = read_sql_file(&file_path)?;
+		sources.push((file_path, sql));
+	}
+	// down migrations are only needed to check that they're next to their migrations
+	sources.extend(list_down_migration_files(migrations_directory)?.into_iter().map(|file_path| (file_path, String::new())));
+	build_migrations(sources)
+}
+
+/// validates a migration chain from `(file name, sql)` pairs, for example ones embedded with `include_str!` or `include_dir`.
+/// the pairs don't need to be in any particular order
+pub fn migrations_from_sources<N: AsRef<str>, S: Into<String>>(sources: impl IntoIterator<Item = (N, S)>) -> Result<Vec<Migration>> {
+	build_migrations(sources.into_iter().map(|(file_name, sql)| (PathBuf::from(file_name.as_ref()), sql.into())).collect())
+}
+
+#[test]
+fn test_migrations_from_sources() {
+	let migrations = migrations_from_sources([
+		("20220102000000.20220101000000.add_color.sql", "alter table fruit add color text;"),
+		("20220101000000.null.create_fruit.sql", "create table fruit ();"),
+		("20220101000000.null.create_fruit.down.sql", "drop table fruit;"),
+	]).unwrap();
+
+	assert_eq!(migrations, vec![
+		Migration {
+			version: "20220101000000".into(), previous_version: None,
+			description: "create_fruit".into(), sql: "create table fruit ();".into(),
+			checksum: compute_checksum(b"create table fruit ();"), is_onboard: false,
+		},
+		Migration {
+			version: "20220102000000".into(), previous_version: Some("20220101000000".into()),
+			description: "add_color".into(), sql: "alter table fruit add color text;".into(),
+			checksum: compute_checksum(b"alter table fruit add color text;"), is_onboard: false,
+		},
+	]);
+	assert_eq!(migrations[1].refinery_name(), "V20220102000000__add_color");
+
+	let onboard = migrations_from_sources([("20220101000000.onboard.existing.sql", "create table fruit ();")]).unwrap();
+	assert!(onboard[0].is_onboard);
+	assert_eq!(onboard[0].previous_version, None);
+
+	assert!(migrations_from_sources([
+		("20220101000000.null.create_fruit.sql", ""),
+		("20220103000000.20220102000000.add_color.sql", ""),
+	]).is_err());
+
+	let paired = migrations_from_sources([
+		("20220101000000.null.create_fruit.up.sql", "create table fruit ();"),
+		("20220101000000.null.create_fruit.down.sql", "drop table fruit;"),
+	]).unwrap();
+	assert_eq!(paired[0].description, "create_fruit");
+	assert!(migrations_from_sources([("20220101000000.null.create_fruit.up.sql", "create table fruit ();")]).is_err());
+}
@@ -0,0 +1,585 @@
+//! The rules `lint` and `ci` check, of the sql of each migration and of the schema itself.
+
+use anyhow::{anyhow, Result};
+use postgres::{Client, Config};
+use postgres_migrator::read_sql_file;
+use regex::Regex;
+use serde::Deserialize;
+
+use super::{Args, MigrationFile, TempDb, apply_sql_files, connections, gather_validated_migrations, is_pending, list_sql_files, query_actual_version, quote_identifier};
+use super::guard_rails::pattern_matches;
+use super::settings::Settings;
+use super::structure::structure_problems;
+
+/// which migrations a migration rule checks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+	/// every migration, since any of them may still be applied to some database
+	Every,
+	/// only the ones the database hasn't applied, since the applied ones can't be changed anymore
+	Pending,
+}
+
+/// a rule of the sql of a single migration
+struct MigrationRule {
+	scope: Scope,
+	/// whether its violations fail, or are only warnings
+	fails: bool,
+	check: fn(&str) -> Vec<String>,
+}
+
+const MIGRATION_RULES: [MigrationRule; 3] = [
+	MigrationRule{ scope: Scope::Every, fails: true, check: structure_problems },
+	MigrationRule{ scope: Scope::Pending, fails: true, check: empty_migration },
+	MigrationRule{ scope: Scope::Pending, fails: false, check: destructive_statements },
+];
+
+fn empty_migration(sql: &str) -> Vec<String> {
+	match sql.trim().is_empty() {
+		true => vec!["migration is empty".to_string()],
+		false => vec![],
+	}
+}
+
+fn destructive_statements(sql: &str) -> Vec<String> {
+	let destructive = Regex::new(r"(?im)^\s*(drop\s+(table|schema|column|type|view|materialized\s+view|function)\b.*|alter\s+table\b.*\bdrop\s+column\b.*)$").unwrap();
+	destructive.find_iter(sql)
+		.map(|found| format!("destructive statement: {}", found.as_str().trim()))
+		.collect()
+}
+
+#[test]
+fn test_migration_rules() {
+	assert_eq!(empty_migration(""), vec!["migration is empty".to_string()]);
+	assert_eq!(empty_migration("create table fruit ();"), Vec::<String>::new());
+	assert_eq!(destructive_statements("create table fruit ();"), Vec::<String>::new());
+	assert_eq!(
+		destructive_statements("create table fruit ();\ndrop table \"public\".\"person\";\nalter table \"public\".\"fruit\" drop column \"color\";"),
+		vec![
+			"destructive statement: drop table \"public\".\"person\";".to_string(),
+			"destructive statement: alter table \"public\".\"fruit\" drop column \"color\";".to_string(),
+		],
+	);
+	// mentioning drop in a comment or later in a line isn't flagged
+	assert_eq!(destructive_statements("-- drop table fruit\ncreate table fruit ();"), Vec::<String>::new());
+}
+
+/// the (errors, warnings) of the migration rules, each naming its migration.
+/// rules scoped to pending migrations only check the ones `is_pending` says are
+fn migration_violations(migration_files: &[&MigrationFile], is_pending: impl Fn(&MigrationFile) -> bool) -> Result<(Vec<String>, Vec<String>)> {
+	let mut errors = vec![];
+	let mut warnings = vec![];
+	for migration_file in migration_files {
+		let pending = is_pending(migration_file);
+		let rules: Vec<_> = MIGRATION_RULES.iter().filter(|rule| rule.scope == Scope::Every || pending).collect();
+		if rules.is_empty() {
+			continue;
+		}
+		let sql = read_sql_file(&migration_file.file_path)?;
+		for rule in rules {
+			let violations = (rule.check)(&sql).into_iter().map(|violation| format!("{}: {violation}", migration_file.display_file_path));
+			match rule.fails {
+				true => errors.extend(violations),
+				false => warnings.extend(violations),
+			}
+		}
+	}
+	Ok((errors, warnings))
+}
+
+/// fails listing what keeps `migration_files` from being applied the way `migrate` applies them,
+/// the violations of the rules of every migration
+pub(crate) fn check_migrations(migration_files: &[&MigrationFile]) -> Result<()> {
+	let (errors, _) = migration_violations(migration_files, |_| false)?;
+	match errors.is_empty() {
+		true => Ok(()),
+		false => Err(anyhow!("{}", errors.join("\n"))),
+	}
+}
+
+pub(crate) struct Table {
+	pub(crate) schema: String,
+	pub(crate) name: String,
+	pub(crate) columns: Vec<String>,
+	pub(crate) has_primary_key: bool,
+}
+
+pub(crate) struct Index {
+	pub(crate) schema: String,
+	pub(crate) table: String,
+	pub(crate) name: String,
+	/// empty for expressions
+	pub(crate) columns: Vec<String>,
+	/// whether it was created for a primary key, unique or exclusion constraint
+	pub(crate) backs_constraint: bool,
+}
+
+pub(crate) struct Constraint {
+	pub(crate) schema: String,
+	pub(crate) table: String,
+	pub(crate) name: String,
+	/// `p`, `u` or `f`, as in `pg_constraint.contype`
+	pub(crate) kind: String,
+	pub(crate) columns: Vec<String>,
+}
+
+/// what the schema declares, without anything extensions created
+pub(crate) struct Catalog {
+	pub(crate) tables: Vec<Table>,
+	pub(crate) indexes: Vec<Index>,
+	pub(crate) constraints: Vec<Constraint>,
+	/// the kind and qualified name of every other object with a name of its own
+	pub(crate) others: Vec<(String, String)>,
+}
+
+pub(crate) const USER_NAMESPACES: &str = "n.nspname not in ('pg_catalog', 'information_schema') and n.nspname not like 'pg_toast%' and n.nspname not like 'pg_temp%'";
+
+pub(crate) fn not_from_extension(object: &str) -> String {
+	format!("not exists (select from pg_depend d where d.objid = {object} and d.deptype = 'e')")
+}
+
+/// the names of the attributes `attnums` of `relation`, in order
+fn column_names(attnums: &str, relation: &str) -> String {
+	format!("array(
+		select coalesce(a.attname::text, '')
+		from unnest({attnums}::int2[]) with ordinality k(attnum, position)
+		left join pg_attribute a on a.attrelid = {relation} and a.attnum = k.attnum
+		order by k.position
+	)")
+}
+
+pub(crate) fn introspect(client: &mut Client) -> Result<Catalog> {
+	let tables = client.query(&format!("
+		select n.nspname::text as schema, c.relname::text as name,
+			array(select a.attname::text from pg_attribute a where a.attrelid = c.oid and a.attnum > 0 and not a.attisdropped order by a.attnum) as columns,
+			exists (select from pg_constraint con where con.conrelid = c.oid and con.contype = 'p') as has_primary_key
+		from pg_class c join pg_namespace n on n.oid = c.relnamespace
+		where c.relkind in ('r', 'p') and {USER_NAMESPACES} and {}
+		order by 1, 2
+	", not_from_extension("c.oid")), &[])?
+		.into_iter()
+		.map(|row| Table{ schema: row.get("schema"), name: row.get("name"), columns: row.get("columns"), has_primary_key: row.get("has_primary_key") })
+		.collect();
+
+	let indexes = client.query(&format!("
+		select n.nspname::text as schema, t.relname::text as table, i.relname::text as name,
+			{} as columns,
+			exists (select from pg_constraint con where con.conindid = i.oid and con.contype in ('p', 'u', 'x')) as backs_constraint
+		from pg_index x
+		join pg_class i on i.oid = x.indexrelid
+		join pg_class t on t.oid = x.indrelid
+		join pg_namespace n on n.oid = t.relnamespace
+		where {USER_NAMESPACES} and {}
+		order by 1, 2, 3
+	", column_names("x.indkey", "t.oid"), not_from_extension("t.oid")), &[])?
+		.into_iter()
+		.map(|row| Index{ schema: row.get("schema"), table: row.get("table"), name: row.get("name"), columns: row.get("columns"), backs_constraint: row.get("backs_constraint") })
+		.collect();
+
+	let constraints = client.query(&format!("
+		select n.nspname::text as schema, t.relname::text as table, con.conname::text as name, con.contype::text as kind,
+			{} as columns
+		from pg_constraint con
+		join pg_class t on t.oid = con.conrelid
+		join pg_namespace n on n.oid = t.relnamespace
+		where con.contype in ('p', 'u', 'f') and {USER_NAMESPACES} and {}
+		order by 1, 2, 3
+	", column_names("con.conkey", "t.oid"), not_from_extension("t.oid")), &[])?
+		.into_iter()
+		.map(|row| Constraint{ schema: row.get("schema"), table: row.get("table"), name: row.get("name"), kind: row.get("kind"), columns: row.get("columns") })
+		.collect();
+
+	let others = client.query(&format!("
+		select case c.relkind when 'v' then 'view' when 'm' then 'materialized view' else 'sequence' end as kind, n.nspname || '.' || c.relname as name
+		from pg_class c join pg_namespace n on n.oid = c.relnamespace
+		where c.relkind in ('v', 'm', 'S') and {USER_NAMESPACES} and {}
+		union all
+		select 'function', n.nspname || '.' || p.proname
+		from pg_proc p join pg_namespace n on n.oid = p.pronamespace
+		where {USER_NAMESPACES} and {}
+		union all
+		select 'type', n.nspname || '.' || t.typname
+		from pg_type t join pg_namespace n on n.oid = t.typnamespace
+		where t.typtype in ('e', 'd') and {USER_NAMESPACES} and {}
+		union all
+		select 'schema', n.nspname from pg_namespace n where {USER_NAMESPACES} and n.nspname != 'public' and {}
+		order by 2, 1
+	", not_from_extension("c.oid"), not_from_extension("p.oid"), not_from_extension("t.oid"), not_from_extension("n.oid")), &[])?
+		.into_iter()
+		.map(|row| (row.get("kind"), row.get("name")))
+		.collect();
+
+	Ok(Catalog{ tables, indexes, constraints, others })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Plurality {
+	Plural,
+	Singular,
+}
+
+/// the `[lint.naming]` table
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct NamingRules {
+	/// every name is lowercase letters, digits and underscores
+	snake_case: bool,
+	/// the last word of every table name is plural, or singular
+	table_names: Option<Plurality>,
+	/// pattern, with `*` and `{table}`, that names of indexes that don't back a constraint match
+	index_pattern: Option<String>,
+	foreign_key_prefix: Option<String>,
+	unique_prefix: Option<String>,
+	/// names that none of the rules apply to, qualified or not
+	exceptions: Vec<String>,
+}
+
+impl NamingRules {
+	fn is_exception(&self, schema: &str, name: &str) -> bool {
+		self.exceptions.iter().any(|exception| exception == name || *exception == format!("{schema}.{name}"))
+	}
+}
+
+fn is_snake_case(name: &str) -> bool {
+	Regex::new("^[a-z_][a-z0-9_]*$").unwrap().is_match(name)
+}
+
+/// judged by the last word, `fruit_categories` is plural. words ending in `ss`, `us` or `is` are singular
+fn is_plural(name: &str) -> bool {
+	let word = name.rsplit('_').next().unwrap_or(name);
+	word.ends_with('s') && !["ss", "us", "is"].iter().any(|ending| word.ends_with(ending))
+}
+
+#[test]
+fn test_is_plural() {
+	assert!(is_plural("fruits"));
+	assert!(is_plural("fruit_categories"));
+	assert!(!is_plural("fruit"));
+	assert!(!is_plural("order_status"));
+	assert!(!is_plural("access"));
+	assert!(!is_plural("analysis"));
+}
+
+fn naming_violations(rules: &NamingRules, catalog: &Catalog) -> Vec<String> {
+	let mut violations = vec![];
+	let split = |qualified: &str| qualified.split_once('.').map(|(schema, name)| (schema.to_string(), name.to_string())).unwrap_or_default();
+
+	for table in &catalog.tables {
+		if rules.is_exception(&table.schema, &table.name) {
+			continue;
+		}
+		if rules.snake_case && !is_snake_case(&table.name) {
+			violations.push(format!("table {}.{} isn't snake_case", table.schema, table.name));
+		}
+		for column in table.columns.iter().filter(|column| rules.snake_case && !is_snake_case(column)) {
+			violations.push(format!("column {}.{}.{column} isn't snake_case", table.schema, table.name));
+		}
+		match rules.table_names {
+			Some(Plurality::Plural) if !is_plural(&table.name) => violations.push(format!("table {}.{} isn't plural", table.schema, table.name)),
+			Some(Plurality::Singular) if is_plural(&table.name) => violations.push(format!("table {}.{} isn't singular", table.schema, table.name)),
+			_ => {},
+		}
+	}
+
+	for index in &catalog.indexes {
+		if rules.is_exception(&index.schema, &index.name) {
+			continue;
+		}
+		if rules.snake_case && !is_snake_case(&index.name) {
+			violations.push(format!("index {}.{} isn't snake_case", index.schema, index.name));
+		}
+		if let Some(index_pattern) = rules.index_pattern.as_ref().filter(|_| !index.backs_constraint) {
+			let index_pattern = index_pattern.replace("{table}", &index.table);
+			if !pattern_matches(&index_pattern, &index.name) {
+				violations.push(format!("index {}.{} on {} doesn't match {index_pattern}", index.schema, index.name, index.table));
+			}
+		}
+	}
+
+	for constraint in &catalog.constraints {
+		if rules.is_exception(&constraint.schema, &constraint.name) {
+			continue;
+		}
+		let (description, prefix) = match constraint.kind.as_str() {
+			"f" => ("foreign key", &rules.foreign_key_prefix),
+			"u" => ("unique constraint", &rules.unique_prefix),
+			_ => ("primary key", &None),
+		};
+		if rules.snake_case && !is_snake_case(&constraint.name) {
+			violations.push(format!("{description} {} on {}.{} isn't snake_case", constraint.name, constraint.schema, constraint.table));
+		}
+		if let Some(prefix) = prefix.as_ref().filter(|prefix| !constraint.name.starts_with(prefix.as_str())) {
+			violations.push(format!("{description} {} on {}.{} doesn't start with {prefix}", constraint.name, constraint.schema, constraint.table));
+		}
+	}
+
+	for (kind, qualified) in &catalog.others {
+		let (schema, name) = split(qualified);
+		let name = if kind == "schema" { qualified.clone() } else { name };
+		if rules.snake_case && !rules.is_exception(&schema, &name) && !is_snake_case(&name) {
+			violations.push(format!("{kind} {qualified} isn't snake_case"));
+		}
+	}
+	violations
+}
+
+#[test]
+fn test_naming_violations() {
+	let catalog = Catalog{
+		tables: vec![
+			Table{ schema: "public".to_string(), name: "fruits".to_string(), columns: vec!["id".to_string(), "colorName".to_string()], has_primary_key: true },
+			Table{ schema: "public".to_string(), name: "person".to_string(), columns: vec!["id".to_string()], has_primary_key: true },
+			Table{ schema: "public".to_string(), name: "people".to_string(), columns: vec!["id".to_string()], has_primary_key: true },
+		],
+		indexes: vec![
+			Index{ schema: "public".to_string(), table: "fruits".to_string(), name: "fruits_pkey".to_string(), columns: vec!["id".to_string()], backs_constraint: true },
+			Index{ schema: "public".to_string(), table: "fruits".to_string(), name: "ix_fruits_color".to_string(), columns: vec!["colorName".to_string()], backs_constraint: false },
+			Index{ schema: "public".to_string(), table: "person".to_string(), name: "person_id_idx".to_string(), columns: vec!["id".to_string()], backs_constraint: false },
+		],
+		constraints: vec![
+			Constraint{ schema: "public".to_string(), table: "fruits".to_string(), name: "fruits_pkey".to_string(), kind: "p".to_string(), columns: vec!["id".to_string()] },
+			Constraint{ schema: "public".to_string(), table: "fruits".to_string(), name: "fruits_person_id_fkey".to_string(), kind: "f".to_string(), columns: vec!["person_id".to_string()] },
+			Constraint{ schema: "public".to_string(), table: "fruits".to_string(), name: "uq_fruits_name".to_string(), kind: "u".to_string(), columns: vec!["name".to_string()] },
+		],
+		others: vec![("function".to_string(), "public.calculateTotals".to_string())],
+	};
+	let rules = NamingRules{
+		snake_case: true,
+		table_names: Some(Plurality::Plural),
+		index_pattern: Some("ix_{table}_*".to_string()),
+		foreign_key_prefix: Some("fk_".to_string()),
+		unique_prefix: Some("uq_".to_string()),
+		exceptions: vec!["public.people".to_string()],
+	};
+	assert_eq!(naming_violations(&rules, &catalog), vec![
+		"column public.fruits.colorName isn't snake_case",
+		"table public.person isn't plural",
+		"index public.person_id_idx on person doesn't match ix_person_*",
+		"foreign key fruits_person_id_fkey on public.fruits doesn't start with fk_",
+		"function public.calculateTotals isn't snake_case",
+	]);
+	assert_eq!(naming_violations(&NamingRules::default(), &catalog), Vec::<String>::new());
+}
+
+/// the `[lint.required]` table
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RequiredRules {
+	/// columns every table has, such as `created_at`
+	columns: Vec<String>,
+	primary_key: bool,
+	/// tables, qualified or not, that don't need the columns or a primary key
+	exceptions: Vec<String>,
+}
+
+fn required_violations(rules: &RequiredRules, catalog: &Catalog) -> Vec<String> {
+	let mut violations = vec![];
+	for table in &catalog.tables {
+		if rules.exceptions.iter().any(|exception| *exception == table.name || *exception == format!("{}.{}", table.schema, table.name)) {
+			continue;
+		}
+		if rules.primary_key && !table.has_primary_key {
+			violations.push(format!("table {}.{} has no primary key", table.schema, table.name));
+		}
+		for column in rules.columns.iter().filter(|column| !table.columns.contains(column)) {
+			violations.push(format!("table {}.{} has no {column} column", table.schema, table.name));
+		}
+	}
+	violations
+}
+
+#[test]
+fn test_required_violations() {
+	let table = |name: &str, columns: &[&str], has_primary_key| Table{
+		schema: "public".to_string(), name: name.to_string(), columns: columns.iter().map(|column| column.to_string()).collect(), has_primary_key,
+	};
+	let catalog = Catalog{
+		tables: vec![
+			table("fruits", &["id", "created_at", "updated_at"], true),
+			table("people", &["id", "created_at"], false),
+			table("schema_log", &["line"], false),
+		],
+		indexes: vec![], constraints: vec![], others: vec![],
+	};
+	let rules = RequiredRules{
+		columns: vec!["created_at".to_string(), "updated_at".to_string()],
+		primary_key: true,
+		exceptions: vec!["public.schema_log".to_string()],
+	};
+	assert_eq!(required_violations(&rules, &catalog), vec![
+		"table public.people has no primary key",
+		"table public.people has no updated_at column",
+	]);
+}
+
+/// the `[lint.foreign_keys]` table
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ForeignKeyRules {
+	/// every foreign key's columns lead some index, so deleting or updating a referenced row doesn't scan the table
+	indexed: bool,
+	/// foreign keys, by name or `table.name`, that don't need an index
+	exceptions: Vec<String>,
+}
+
+impl Default for ForeignKeyRules {
+	fn default() -> ForeignKeyRules {
+		ForeignKeyRules{ indexed: true, exceptions: vec![] }
+	}
+}
+
+/// whether the first columns of `index`, in any order, are exactly `columns`
+fn index_leads_with(index: &Index, columns: &[String]) -> bool {
+	index.columns.len() >= columns.len() && columns.iter().all(|column| index.columns[..columns.len()].contains(column))
+}
+
+/// foreign keys without an index their columns lead, each with a `create index` that would support it,
+/// named after `index_pattern` when it has a single `*`
+fn foreign_key_violations(rules: &ForeignKeyRules, index_pattern: Option<&str>, catalog: &Catalog) -> Vec<String> {
+	let mut violations = vec![];
+	if !rules.indexed {
+		return violations;
+	}
+	for constraint in catalog.constraints.iter().filter(|constraint| constraint.kind == "f") {
+		if rules.exceptions.iter().any(|exception| *exception == constraint.name || *exception == format!("{}.{}", constraint.table, constraint.name)) {
+			continue;
+		}
+		let indexed = catalog.indexes.iter()
+			.filter(|index| index.schema == constraint.schema && index.table == constraint.table)
+			.any(|index| index_leads_with(index, &constraint.columns));
+		if indexed {
+			continue;
+		}
+
+		let name = index_pattern
+			.map(|index_pattern| index_pattern.replace("{table}", &constraint.table).replacen('*', &constraint.columns.join("_"), 1))
+			.filter(|name| !name.contains('*'))
+			.map(|name| format!("{} ", quote_identifier(&name)))
+			.unwrap_or_default();
+		let columns = constraint.columns.iter().map(|column| quote_identifier(column)).collect::<Vec<_>>().join(", ");
+		violations.push(format!(
+			"foreign key {} on {}.{} ({}) has no index, add one with: create index {name}on {}.{} ({columns});",
+			constraint.name, constraint.schema, constraint.table, constraint.columns.join(", "),
+			quote_identifier(&constraint.schema), quote_identifier(&constraint.table),
+		));
+	}
+	violations
+}
+
+#[test]
+fn test_foreign_key_violations() {
+	let index = |name: &str, columns: &[&str]| Index{
+		schema: "public".to_string(), table: "fruits".to_string(), name: name.to_string(), columns: columns.iter().map(|column| column.to_string()).collect(), backs_constraint: false,
+	};
+	let foreign_key = |name: &str, columns: &[&str]| Constraint{
+		schema: "public".to_string(), table: "fruits".to_string(), name: name.to_string(), kind: "f".to_string(), columns: columns.iter().map(|column| column.to_string()).collect(),
+	};
+	let catalog = Catalog{
+		tables: vec![],
+		indexes: vec![index("ix_fruits_person", &["person_id", "created_at"]), index("ix_fruits_created_farm", &["created_at", "farm_id"])],
+		constraints: vec![
+			foreign_key("fk_person", &["person_id"]),
+			foreign_key("fk_farm", &["farm_id"]),
+			foreign_key("fk_owner", &["owner_id", "owner_kind"]),
+			foreign_key("fk_legacy", &["legacy_id"]),
+		],
+		others: vec![],
+	};
+	let rules = ForeignKeyRules{ indexed: true, exceptions: vec!["fruits.fk_legacy".to_string()] };
+	assert_eq!(foreign_key_violations(&rules, None, &catalog), vec![
+		r#"foreign key fk_farm on public.fruits (farm_id) has no index, add one with: create index on "public"."fruits" ("farm_id");"#,
+		r#"foreign key fk_owner on public.fruits (owner_id, owner_kind) has no index, add one with: create index on "public"."fruits" ("owner_id", "owner_kind");"#,
+	]);
+	assert_eq!(foreign_key_violations(&rules, Some("ix_{table}_*"), &catalog)[0], r#"foreign key fk_farm on public.fruits (farm_id) has no index, add one with: create index "ix_fruits_farm_id" on "public"."fruits" ("farm_id");"#);
+	assert_eq!(foreign_key_violations(&ForeignKeyRules{ indexed: false, exceptions: vec![] }, None, &catalog), Vec::<String>::new());
+}
+
+/// the lints configured in `migrator.toml`. only the foreign key lint is on without configuration
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Lints {
+	naming: Option<NamingRules>,
+	required: Option<RequiredRules>,
+	foreign_keys: ForeignKeyRules,
+}
+
+impl Lints {
+	pub(crate) fn read() -> Result<Lints> {
+		Ok(Settings::read()?.lint)
+	}
+
+	pub(crate) fn is_empty(&self) -> bool {
+		self.naming.is_none() && self.required.is_none() && !self.foreign_keys.indexed
+	}
+
+	pub(crate) fn violations(&self, catalog: &Catalog) -> Vec<String> {
+		SCHEMA_RULES.iter().flat_map(|rule| rule(self, catalog)).collect()
+	}
+}
+
+/// the rules of the schema, each checking the catalog with its table of `migrator.toml`, and nothing when that table isn't there
+const SCHEMA_RULES: [fn(&Lints, &Catalog) -> Vec<String>; 3] = [
+	|lints, catalog| lints.naming.as_ref().map(|naming| naming_violations(naming, catalog)).unwrap_or_default(),
+	|lints, catalog| lints.required.as_ref().map(|required| required_violations(required, catalog)).unwrap_or_default(),
+	|lints, catalog| {
+		let index_pattern = lints.naming.as_ref().and_then(|naming| naming.index_pattern.as_deref());
+		foreign_key_violations(&lints.foreign_keys, index_pattern, catalog)
+	},
+];
+
+/// the violations of every lint configured in `migrator.toml`, by the schema directory.
+/// doesn't create a temp database when every lint is turned off
+pub(crate) fn lint_schema(args: &Args) -> Result<Vec<String>> {
+	let lints = Lints::read()?;
+	if lints.is_empty() {
+		return Ok(vec![]);
+	}
+
+	let dbname = args.pg_url.get_dbname().ok_or_else(|| anyhow!("need a dbname to run lint command"))?;
+	let schema = TempDb::new(args, dbname, "schema")?;
+	apply_sql_files(&schema.config, list_sql_files(&args.schema_directory)?, false)?;
+	let catalog = connections::with_client(&schema.config, introspect)?;
+
+	Ok(lints.violations(&catalog))
+}
+
+/// the violations by the database of `target` that the database of `source` doesn't have,
+/// so `generate` only flags what the schema change introduced
+pub(crate) fn introduced_violations(lints: &Lints, source: &Config, target: &Config) -> Result<Vec<String>> {
+	if lints.is_empty() {
+		return Ok(vec![]);
+	}
+	let existing = lints.violations(&connections::with_client(source, introspect)?);
+	let violations = lints.violations(&connections::with_client(target, introspect)?);
+	Ok(violations.into_iter().filter(|violation| !existing.contains(violation)).collect())
+}
+
+/// the (errors, warnings) of every rule: the migration rules, checking the pending ones against the database,
+/// and the schema rules. `offline` only checks the rules of every migration, which don't need a database
+pub(crate) fn lint(args: &Args, migration_files: &[MigrationFile], offline: bool) -> Result<(Vec<String>, Vec<String>)> {
+	let migration_files: Vec<_> = migration_files.iter().collect();
+	if offline {
+		return migration_violations(&migration_files, |_| false);
+	}
+	let actual_version = connections::with_client(&args.pg_url, query_actual_version)?;
+	let (mut errors, warnings) = migration_violations(&migration_files, |migration_file| is_pending(&migration_file.current_version, &actual_version))?;
+	errors.extend(lint_schema(args)?);
+	Ok((errors, warnings))
+}
+
+/// prints every lint violation of the migrations and the schema directory, failing if there are any
+pub(crate) fn command_lint(args: &Args) -> Result<()> {
+	let (migration_files, _) = gather_validated_migrations(args)?;
+	let (errors, warnings) = lint(args, &migration_files, false)?;
+	for warning in &warnings {
+		println!("warning: {warning}");
+	}
+	for error in &errors {
+		println!("{error}");
+	}
+	match errors.len() {
+		0 => Ok(()),
+		count => Err(anyhow!("{count} lint violations")),
+	}
+}
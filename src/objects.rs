@@ -0,0 +1,302 @@
+//! Recognizes which object a ddl statement creates, alters, drops or comments on, from the start of the statement.
+
+use std::path::PathBuf;
+use anyhow::{anyhow, Result};
+use postgres_migrator::{migration_description, read_sql_file};
+use regex::Regex;
+use serde_json::json;
+
+use super::{Args, gather_validated_migrations, list_sql_files};
+use super::statements::{Statement, split_statements};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Action {
+	Create,
+	Alter,
+	Drop,
+	Comment,
+}
+
+impl Action {
+	fn verb(&self) -> &'static str {
+		match self {
+			Action::Create => "created",
+			Action::Alter => "altered",
+			Action::Drop => "dropped",
+			Action::Comment => "commented on",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+pub(crate) enum ObjectKind {
+	Schema,
+	Extension,
+	Type,
+	Domain,
+	Sequence,
+	Table,
+	View,
+	MaterializedView,
+	Function,
+	Procedure,
+	Index,
+	Trigger,
+	Column,
+}
+
+impl ObjectKind {
+	pub(crate) fn name(&self) -> &'static str {
+		match self {
+			ObjectKind::Schema => "schema",
+			ObjectKind::Extension => "extension",
+			ObjectKind::Type => "type",
+			ObjectKind::Domain => "domain",
+			ObjectKind::Sequence => "sequence",
+			ObjectKind::Table => "table",
+			ObjectKind::View => "view",
+			ObjectKind::MaterializedView => "materialized view",
+			ObjectKind::Function => "function",
+			ObjectKind::Procedure => "procedure",
+			ObjectKind::Index => "index",
+			ObjectKind::Trigger => "trigger",
+			ObjectKind::Column => "column",
+		}
+	}
+}
+
+/// what a ddl statement does to which object
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Ddl {
+	pub(crate) action: Action,
+	pub(crate) kind: ObjectKind,
+	/// lowercased and unquoted, possibly schema qualified. `None` for indexes created without a name
+	pub(crate) name: Option<String>,
+	/// the table of an index or trigger
+	pub(crate) on: Option<String>,
+}
+
+pub(crate) const IDENTIFIER: &str = r#"(?:"[^"]+"|[\w$]+)(?:\s*\.\s*(?:"[^"]+"|[\w$]+))*"#;
+
+pub(crate) fn normalize_name(name: &str) -> String {
+	name.split('.').map(|part| part.trim().trim_matches('"').to_lowercase()).collect::<Vec<_>>().join(".")
+}
+
+/// parses the start of a statement like `create unique index concurrently if not exists i_fruit on "public"."fruit"`
+pub(crate) fn parse_ddl(sql: &str) -> Option<Ddl> {
+	let head = Regex::new(&format!(
+		r"(?is)^(create(?:\s+or\s+replace)?|alter|drop|comment\s+on)\s+(?:(?:global|local|temp|temporary|unlogged|unique|recursive|constraint)\s+)*(materialized\s+view|table|view|function|procedure|index|trigger|type|sequence|schema|extension|domain|column)\s+(?:concurrently\s+)?(?:if\s+(?:not\s+)?exists\s+)?({IDENTIFIER})?"
+	)).unwrap();
+	let captures = head.captures(sql)?;
+	let action = match captures[1].to_lowercase().split_whitespace().next()? {
+		"create" => Action::Create,
+		"alter" => Action::Alter,
+		"drop" => Action::Drop,
+		_ => Action::Comment,
+	};
+	let kind = match captures[2].to_lowercase().split_whitespace().collect::<Vec<_>>().join(" ").as_str() {
+		"schema" => ObjectKind::Schema,
+		"extension" => ObjectKind::Extension,
+		"type" => ObjectKind::Type,
+		"domain" => ObjectKind::Domain,
+		"sequence" => ObjectKind::Sequence,
+		"table" => ObjectKind::Table,
+		"view" => ObjectKind::View,
+		"materialized view" => ObjectKind::MaterializedView,
+		"function" => ObjectKind::Function,
+		"procedure" => ObjectKind::Procedure,
+		"index" => ObjectKind::Index,
+		"trigger" => ObjectKind::Trigger,
+		_ => ObjectKind::Column,
+	};
+	// `create index on fruit (name)` has no name
+	let name = captures.get(3).map(|name| normalize_name(name.as_str())).filter(|name| name != "on");
+
+	let on = match kind {
+		ObjectKind::Index | ObjectKind::Trigger => {
+			let on = Regex::new(&format!(r"(?is)\son\s+(?:only\s+)?({IDENTIFIER})")).unwrap();
+			on.captures(&sql[captures.get(2).unwrap().end()..]).map(|on| normalize_name(&on[1]))
+		},
+		_ => None,
+	};
+	Some(Ddl{ action, kind, name, on })
+}
+
+#[test]
+fn test_parse_ddl() {
+	let ddl = |action, kind, name: Option<&str>, on: Option<&str>| Some(Ddl{ action, kind, name: name.map(str::to_string), on: on.map(str::to_string) });
+
+	assert_eq!(parse_ddl("create table \"public\".\"Fruit\" (id int)"), ddl(Action::Create, ObjectKind::Table, Some("public.fruit"), None));
+	assert_eq!(parse_ddl("CREATE UNLOGGED TABLE if not exists fruit ()"), ddl(Action::Create, ObjectKind::Table, Some("fruit"), None));
+	assert_eq!(parse_ddl("alter table fruit add column color text"), ddl(Action::Alter, ObjectKind::Table, Some("fruit"), None));
+	assert_eq!(parse_ddl("create or replace function calculate_totals(a int) returns int as $$ select 1 $$ language sql"), ddl(Action::Create, ObjectKind::Function, Some("calculate_totals"), None));
+	assert_eq!(parse_ddl("create materialized view\n  totals as select 1"), ddl(Action::Create, ObjectKind::MaterializedView, Some("totals"), None));
+	assert_eq!(parse_ddl("create unique index concurrently i_fruit on only public.fruit (name)"), ddl(Action::Create, ObjectKind::Index, Some("i_fruit"), Some("public.fruit")));
+	assert_eq!(parse_ddl("create index on fruit (name)"), ddl(Action::Create, ObjectKind::Index, None, Some("fruit")));
+	assert_eq!(parse_ddl("create constraint trigger t_fruit after insert on fruit for each row execute function f()"), ddl(Action::Create, ObjectKind::Trigger, Some("t_fruit"), Some("fruit")));
+	assert_eq!(parse_ddl("drop view if exists fruit_v"), ddl(Action::Drop, ObjectKind::View, Some("fruit_v"), None));
+	assert_eq!(parse_ddl("comment on column fruit.color is 'hex'"), ddl(Action::Comment, ObjectKind::Column, Some("fruit.color"), None));
+	assert_eq!(parse_ddl("insert into fruit values (1)"), None);
+	assert_eq!(parse_ddl("grant select on fruit to reader"), None);
+}
+
+/// whether `name` is the object `query` names, where either can leave out the schema
+pub(crate) fn names_match(query: &str, name: &str) -> bool {
+	let (query, name) = (normalize_name(query), normalize_name(name));
+	let split = |name: &str| name.rsplit_once('.').map(|(schema, object)| (Some(schema.to_string()), object.to_string())).unwrap_or((None, name.to_string()));
+	match (split(&query), split(&name)) {
+		((Some(query_schema), query_object), (Some(schema), object)) => query_schema == schema && query_object == object,
+		((_, query_object), (_, object)) => query_object == object,
+	}
+}
+
+#[test]
+fn test_names_match() {
+	assert!(names_match("fruit", "public.fruit"));
+	assert!(names_match("public.Fruit", "fruit"));
+	assert!(names_match("\"public\".\"fruit\"", "public.fruit"));
+	assert!(!names_match("other.fruit", "public.fruit"));
+	assert!(!names_match("fruit", "fruits"));
+}
+
+/// a statement of a sql file, with the file's version if it's a migration
+pub(crate) struct Located {
+	pub(crate) file_path: PathBuf,
+	pub(crate) version: Option<String>,
+	pub(crate) statement: Statement,
+	pub(crate) ddl: Ddl,
+}
+
+impl Located {
+	pub(crate) fn location(&self) -> String {
+		format!("{}:{}", self.file_path.display(), self.statement.line)
+	}
+
+	/// the first line of the statement, for listing it
+	pub(crate) fn summary(&self) -> String {
+		let first_line = self.statement.sql.lines().next().unwrap_or_default().trim();
+		match first_line.chars().count() > 100 {
+			true => format!("{}...", first_line.chars().take(100).collect::<String>()),
+			false => first_line.to_string(),
+		}
+	}
+}
+
+/// every ddl statement of `file_paths`, in order
+pub(crate) fn locate_ddl(file_paths: impl IntoIterator<Item = (PathBuf, Option<String>)>) -> Result<Vec<Located>> {
+	let mut located = vec![];
+	for (file_path, version) in file_paths {
+		let statements = split_statements(&read_sql_file(&file_path)?)
+			.map_err(|err| anyhow!("unable to parse {}: {err}", file_path.display()))?;
+		for statement in statements {
+			if let Some(ddl) = parse_ddl(&statement.sql) {
+				located.push(Located{ file_path: file_path.clone(), version: version.clone(), statement, ddl });
+			}
+		}
+	}
+	Ok(located)
+}
+
+pub(crate) fn locate_schema_ddl(args: &Args) -> Result<Vec<Located>> {
+	locate_ddl(list_sql_files(&args.schema_directory)?.into_iter().map(|file_path| (file_path, None)))
+}
+
+/// whether `located` creates, changes or comments on `query`, an object or a `table.column`
+fn touches(located: &Located, query: &str) -> bool {
+	let Some(name) = &located.ddl.name else { return false };
+	if names_match(query, name) {
+		return true;
+	}
+	// `fruit.color` as a column: statements on the table that mention the column
+	let Some((table, column)) = normalize_name(query).rsplit_once('.').map(|(table, column)| (table.to_string(), column.to_string())) else { return false };
+	match located.ddl.kind {
+		ObjectKind::Column => name.rsplit_once('.').is_some_and(|(name_table, name_column)| name_column == column && names_match(&table, name_table)),
+		ObjectKind::Table if located.ddl.action != Action::Drop && names_match(&table, name) => {
+			let mentioned = Regex::new(&format!(r#"(?i)(^|[^\w$]){}($|[^\w$])"#, regex::escape(&column))).unwrap();
+			mentioned.is_match(&located.statement.sql)
+		},
+		_ => false,
+	}
+}
+
+/// prints the migrations with statements that created, altered, dropped or commented on `object`,
+/// and where the schema directory declares it
+pub(crate) fn command_blame(args: &Args, object: &str) -> Result<()> {
+	let (migration_files, _) = gather_validated_migrations(args)?;
+	let migrations = locate_ddl(migration_files.into_iter().map(|migration_file| (migration_file.file_path, Some(migration_file.current_version))))?;
+	let changes: Vec<&Located> = migrations.iter().filter(|located| touches(located, object)).collect();
+	let schema = locate_schema_ddl(args)?;
+	let declarations: Vec<&Located> = schema.iter().filter(|located| touches(located, object)).collect();
+	if changes.is_empty() && declarations.is_empty() {
+		return Err(anyhow!("no migration or schema file mentions {object}"));
+	}
+
+	for located in &changes {
+		println!(
+			"{} {} {}\n    {}",
+			located.version.as_deref().unwrap_or_default(), migration_description(&located.file_path), located.location(), located.summary(),
+		);
+	}
+	if let Some(last) = changes.last() {
+		if let Some(created) = changes.iter().rev().find(|located| located.ddl.action == Action::Create) {
+			println!("\ncreated by {}", created.location());
+		} else {
+			println!();
+		}
+		println!("last changed by {}", last.location());
+	}
+	for located in &declarations {
+		println!("{} declared in {}\n    {}", located.ddl.kind.name(), located.location(), located.summary());
+	}
+	Ok(())
+}
+
+/// prints where the schema directory creates, alters or comments on the `kind` object `name`
+pub(crate) fn command_search(args: &Args, kind: ObjectKind, name: &str) -> Result<()> {
+	let schema = locate_schema_ddl(args)?;
+	let found: Vec<&Located> = schema.iter()
+		.filter(|located| match kind {
+			ObjectKind::Column => touches(located, name),
+			// `create index on fruit (name)` can only be found by its table
+			ObjectKind::Index | ObjectKind::Trigger if located.ddl.name.is_none() => located.ddl.kind == kind && located.ddl.on.as_deref().is_some_and(|on| names_match(name, on)),
+			_ => located.ddl.kind == kind && touches(located, name),
+		})
+		.collect();
+	if found.is_empty() {
+		return Err(anyhow!("no {} named {name} in {}", kind.name(), args.schema_directory));
+	}
+
+	for located in found {
+		println!("{} {} {}
+    {}", located.ddl.action.verb(), located.ddl.kind.name(), located.location(), located.summary());
+	}
+	Ok(())
+}
+
+/// lists every object the schema directory creates, with the file and line that creates it
+pub(crate) fn command_inventory(args: &Args, as_json: bool) -> Result<()> {
+	let schema = locate_schema_ddl(args)?;
+	let created: Vec<&Located> = schema.iter().filter(|located| located.ddl.action == Action::Create).collect();
+
+	if as_json {
+		let objects: Vec<_> = created.iter().map(|located| json!({
+			"kind": located.ddl.kind.name(),
+			"name": located.ddl.name,
+			"on": located.ddl.on,
+			"file": located.file_path.display().to_string(),
+			"line": located.statement.line,
+		})).collect();
+		println!("{}", serde_json::to_string_pretty(&objects)?);
+		return Ok(());
+	}
+
+	for located in created {
+		let name = match (&located.ddl.name, &located.ddl.on) {
+			(Some(name), Some(on)) => format!("{name} on {on}"),
+			(Some(name), None) => name.clone(),
+			(None, on) => format!("(unnamed) on {}", on.as_deref().unwrap_or_default()),
+		};
+		println!("{:<17} {name:<40} {}", located.ddl.kind.name(), located.location());
+	}
+	Ok(())
+}
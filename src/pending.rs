@@ -0,0 +1,88 @@
+//! Hand written sql from `pending_pre.sql` and `pending_post.sql` that `generate` puts around the diff of the next migration.
+
+use std::{fs, path::Path};
+use anyhow::{anyhow, Context, Result};
+
+const PRE_FILE_NAME: &str = "pending_pre.sql";
+const POST_FILE_NAME: &str = "pending_post.sql";
+
+/// the sql of `file_name`, if it has any
+fn read_section(file_name: &str) -> Result<Option<String>> {
+	let path = Path::new(file_name);
+	if !path.exists() {
+		return Ok(None);
+	}
+	let sql = fs::read_to_string(path).with_context(|| format!("unable to read {file_name}"))?;
+	Ok(Some(sql.trim().to_string()).filter(|sql| !sql.is_empty()))
+}
+
+fn attach(pre: Option<&str>, generated_migration: &str, post: Option<&str>) -> String {
+	let sections: Vec<String> = [
+		pre.map(|pre| format!("-- from {PRE_FILE_NAME}\n{pre}")),
+		Some(generated_migration.trim().to_string()).filter(|generated_migration| !generated_migration.is_empty()),
+		post.map(|post| format!("-- from {POST_FILE_NAME}\n{post}")),
+	].into_iter().flatten().collect();
+	match sections.is_empty() {
+		true => String::new(),
+		false => sections.join("\n\n") + "\n",
+	}
+}
+
+#[test]
+fn test_attach() {
+	assert_eq!(
+		attach(Some("update fruit set color = 'red';"), "alter table fruit alter column color set not null;\n", Some("drop table old_fruit;")),
+		"-- from pending_pre.sql\nupdate fruit set color = 'red';\n\nalter table fruit alter column color set not null;\n\n-- from pending_post.sql\ndrop table old_fruit;\n",
+	);
+	assert_eq!(attach(None, "create table fruit ();\n", None), "create table fruit ();\n");
+	assert_eq!(attach(Some("delete from fruit where color is null;"), "", None), "-- from pending_pre.sql\ndelete from fruit where color is null;\n");
+	assert_eq!(attach(None, "", None), "");
+}
+
+#[test]
+fn test_read_section_failures() -> Result<()> {
+	let root = std::env::temp_dir().join("postgres_migrator_test_read_section_failures");
+	let _ = fs::remove_dir_all(&root);
+	fs::create_dir_all(root.join("pending_pre.sql"))?;
+	let file_name = |name: &str| root.join(name).to_string_lossy().into_owned();
+
+	assert_eq!(read_section(&file_name("pending_post.sql"))?, None);
+	fs::write(root.join("pending_post.sql"), "\n  \n")?;
+	assert_eq!(read_section(&file_name("pending_post.sql"))?, None);
+	fs::write(root.join("pending_post.sql"), [0xff, 0xfe])?;
+	assert_eq!(read_section(&file_name("pending_post.sql")).unwrap_err().to_string(), format!("unable to read {}", file_name("pending_post.sql")));
+	assert_eq!(read_section(&file_name("pending_pre.sql")).unwrap_err().to_string(), format!("unable to read {}", file_name("pending_pre.sql")));
+
+	fs::remove_dir_all(&root)?;
+	Ok(())
+}
+
+/// `generated_migration` with the pending sections around it, and a function that empties their files,
+/// to be called once the migration has been written
+pub(crate) fn attach_pending(generated_migration: &str) -> Result<(String, impl FnOnce() -> Result<()>)> {
+	let (pre, post) = (read_section(PRE_FILE_NAME)?, read_section(POST_FILE_NAME)?);
+	let attached = match (&pre, &post) {
+		(None, None) => generated_migration.to_string(),
+		_ => attach(pre.as_deref(), generated_migration, post.as_deref()),
+	};
+	let clear = move || {
+		for (file_name, section) in [(PRE_FILE_NAME, pre), (POST_FILE_NAME, post)] {
+			if section.is_some() {
+				fs::write(file_name, "").with_context(|| format!("unable to empty {file_name}"))?;
+				println!("moved the sql of {file_name} into the migration");
+			}
+		}
+		Ok(())
+	};
+	Ok((attached, clear))
+}
+
+/// fails while the pending sections have sql, for commands that write migrations it shouldn't go in
+pub(crate) fn ensure_none_pending(command: &str) -> Result<()> {
+	for file_name in [PRE_FILE_NAME, POST_FILE_NAME] {
+		if read_section(file_name)?.is_some() {
+			return Err(anyhow!("{file_name} has sql that isn't in a migration yet, generate one with it before running {command}"));
+		}
+	}
+	Ok(())
+}
@@ -0,0 +1,168 @@
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+
+use super::{Args, MigrationFile, create_versions_table, gather_validated_migrations, insert_version, read_sql_file, statements, templates};
+
+#[derive(clap::Subcommand, Debug)]
+pub(crate) enum MigratePhase {
+	/// applies pending migrations up to and including the next phased migration,
+	/// but only runs the start phase of that one
+	Start,
+	/// runs the complete phase of the started migration
+	Complete,
+	/// runs the rollback phase of the started migration, and removes it from `_schema_versions`
+	Rollback,
+}
+
+/// adds the column that marks a migration whose start phase has run, but whose complete phase hasn't
+pub(crate) const PHASE_COLUMN_SQL: &str = "
+	alter table _schema_versions add column if not exists phase text;
+";
+
+/// a migration split into phases by `-- phase: start`, `-- phase: complete` and `-- phase: rollback` lines
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct PhasedMigration {
+	start: String,
+	complete: String,
+	rollback: Option<String>,
+}
+
+/// none if the migration has no phase markers
+pub(crate) fn parse_phased(sql: &str) -> Result<Option<PhasedMigration>> {
+	let marker = Regex::new(r"(?im)^--\s*phase:\s*(\w+)\s*$").unwrap();
+	let markers = marker.captures_iter(sql).collect::<Vec<_>>();
+	if markers.is_empty() {
+		return Ok(None);
+	}
+
+	let preamble = &sql[..markers[0].get(0).unwrap().start()];
+	if preamble.lines().any(|line| !line.trim().is_empty() && !line.trim_start().starts_with("--")) {
+		return Err(anyhow!("phased migrations can't have sql before their first phase marker"));
+	}
+
+	let (mut start, mut complete, mut rollback) = (None, None, None);
+	for (index, captures) in markers.iter().enumerate() {
+		let body_start = captures.get(0).unwrap().end();
+		let body_end = markers.get(index + 1).map_or(sql.len(), |next| next.get(0).unwrap().start());
+		let body = sql[body_start..body_end].trim().to_string();
+
+		let phase = match captures[1].to_lowercase().as_str() {
+			"start" => &mut start,
+			"complete" => &mut complete,
+			"rollback" => &mut rollback,
+			unknown => return Err(anyhow!("unknown phase {unknown}, phases are start, complete and rollback")),
+		};
+		if phase.replace(body).is_some() {
+			return Err(anyhow!("phase {} is given more than once", &captures[1]));
+		}
+	}
+
+	Ok(Some(PhasedMigration {
+		start: start.ok_or_else(|| anyhow!("phased migrations need a start phase"))?,
+		complete: complete.unwrap_or_default(),
+		rollback,
+	}))
+}
+
+#[test]
+fn test_parse_phased() {
+	assert_eq!(parse_phased("create table fruit ();").unwrap(), None);
+	assert_eq!(
+		parse_phased("-- rename fruit.name\n-- phase: start\nalter table fruit add title text;\n\n-- phase: complete\nalter table fruit drop name;\n-- phase: rollback\nalter table fruit drop title;\n").unwrap(),
+		Some(PhasedMigration {
+			start: "alter table fruit add title text;".into(),
+			complete: "alter table fruit drop name;".into(),
+			rollback: Some("alter table fruit drop title;".into()),
+		}),
+	);
+	assert_eq!(
+		parse_phased("-- PHASE: Start\ncreate view fruit_v2 as select 1;").unwrap(),
+		Some(PhasedMigration{ start: "create view fruit_v2 as select 1;".into(), complete: "".into(), rollback: None }),
+	);
+
+	assert!(parse_phased("create table fruit ();\n-- phase: start\nselect 1;").is_err());
+	assert!(parse_phased("-- phase: complete\nselect 1;").is_err());
+	assert!(parse_phased("-- phase: start\n-- phase: start\n").is_err());
+	assert!(parse_phased("-- phase: finish\n").is_err());
+}
+
+/// the sql to run when applying a migration all at once, which for phased migrations is the start and then the complete phase,
+/// with the variables of `--env` substituted
+pub(crate) fn full_sql(sql: &str) -> Result<String> {
+	let sql = &templates::render(sql)?;
+	Ok(match parse_phased(sql)? {
+		None => sql.to_string(),
+		Some(PhasedMigration{start, complete, ..}) => format!("{start}\n{complete}"),
+	})
+}
+
+fn phase_column_exists(client: &mut impl postgres::GenericClient) -> Result<bool> {
+	let query = "select exists (
+		select true from pg_catalog.pg_attribute
+		where attrelid = to_regclass('_schema_versions') and attname = 'phase' and not attisdropped
+	) as phase_column_exists";
+	Ok(client.query_one(query, &[])?.get("phase_column_exists"))
+}
+
+/// the bookkeeping sql mirroring the phase column, if the database has it
+pub(crate) fn query_phase_bookkeeping_sql(client: &mut impl postgres::GenericClient) -> Result<&'static str> {
+	Ok(if phase_column_exists(client)? { PHASE_COLUMN_SQL } else { "" })
+}
+
+/// the version whose start phase has run but whose complete phase hasn't, if there is one
+pub(crate) fn query_started_version(client: &mut impl postgres::GenericClient) -> Result<Option<String>> {
+	if !phase_column_exists(client)? {
+		return Ok(None);
+	}
+	Ok(client.query_opt("select current_version from _schema_versions where phase = 'started'", &[])?
+		.map(|row| row.get("current_version")))
+}
+
+/// runs the start phase of `migration_file` and records it as started
+pub(crate) fn start_migration(client: &mut postgres::Client, migration_file: &MigrationFile, phased: &PhasedMigration) -> Result<()> {
+	let MigrationFile{current_version, previous_version, ..} = migration_file;
+	create_versions_table(client)?;
+
+	let mut transaction = client.transaction()?;
+	statements::batch_execute(&mut transaction, &phased.start, &migration_file.file_path)?;
+	transaction.batch_execute(PHASE_COLUMN_SQL)?;
+	insert_version(&mut transaction, current_version, previous_version)?;
+	transaction.execute("update _schema_versions set phase = 'started' where current_version = $1", &[current_version])?;
+	transaction.commit()?;
+	Ok(())
+}
+
+fn find_started_migration(args: &Args, client: &mut postgres::Client) -> Result<(MigrationFile, PhasedMigration)> {
+	let started_version = query_started_version(client)?.ok_or_else(|| anyhow!("no phased migration has been started"))?;
+	let migration_file = gather_validated_migrations(args)?.0.into_iter()
+		.find(|migration_file| migration_file.current_version == started_version)
+		.ok_or_else(|| anyhow!("started migration {started_version} isn't in {}", args.migrations_directory))?;
+	let phased = parse_phased(&templates::render(&read_sql_file(&migration_file.file_path)?)?)
+		.with_context(|| migration_file.display_file_path.clone())?
+		.ok_or_else(|| anyhow!("{} was started, but no longer has phases", migration_file.display_file_path))?;
+	Ok((migration_file, phased))
+}
+
+pub(crate) fn command_complete(args: &Args, client: &mut postgres::Client) -> Result<()> {
+	let (migration_file, phased) = find_started_migration(args, client)?;
+	println!("completing {}", migration_file.display_file_path);
+
+	let mut transaction = client.transaction()?;
+	statements::batch_execute(&mut transaction, &phased.complete, &migration_file.file_path)?;
+	transaction.execute("update _schema_versions set phase = null where current_version = $1", &[&migration_file.current_version])?;
+	transaction.commit()?;
+	Ok(())
+}
+
+pub(crate) fn command_rollback(args: &Args, client: &mut postgres::Client) -> Result<()> {
+	let (migration_file, phased) = find_started_migration(args, client)?;
+	let rollback = phased.rollback
+		.ok_or_else(|| anyhow!("{} has no rollback phase", migration_file.display_file_path))?;
+	println!("rolling back {}", migration_file.display_file_path);
+
+	let mut transaction = client.transaction()?;
+	statements::batch_execute(&mut transaction, &rollback, &migration_file.file_path)?;
+	transaction.execute("delete from _schema_versions where current_version = $1", &[&migration_file.current_version])?;
+	transaction.commit()?;
+	Ok(())
+}
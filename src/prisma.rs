@@ -0,0 +1,714 @@
+//! Converts a Prisma schema into sql files for the schema directory.
+
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}};
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+
+use super::{Args, list_sql_files, make_slug, quote_identifier, quote_literal};
+
+/// an `@attribute(arguments)` of a field, or an `@@attribute(arguments)` of a block
+#[derive(Debug, PartialEq, Eq)]
+struct Attribute {
+	name: String,
+	arguments: Vec<(Option<String>, String)>,
+}
+
+impl Attribute {
+	/// the argument called `key`, or the first unnamed one
+	fn argument(&self, key: &str) -> Option<&str> {
+		self.arguments.iter().find(|(name, _)| name.as_deref() == Some(key))
+			.or_else(|| self.arguments.iter().find(|(name, _)| name.is_none()))
+			.map(|(_, value)| value.as_str())
+	}
+
+	fn named(&self, key: &str) -> Option<&str> {
+		self.arguments.iter().find(|(name, _)| name.as_deref() == Some(key)).map(|(_, value)| value.as_str())
+	}
+}
+
+#[derive(Debug)]
+struct Field {
+	name: String,
+	field_type: String,
+	optional: bool,
+	list: bool,
+	attributes: Vec<Attribute>,
+}
+
+impl Field {
+	fn attribute(&self, name: &str) -> Option<&Attribute> {
+		self.attributes.iter().find(|attribute| attribute.name == name)
+	}
+
+	fn column(&self) -> String {
+		mapped_name(&self.attributes, "map", &self.name)
+	}
+}
+
+#[derive(Debug)]
+struct Model {
+	name: String,
+	fields: Vec<Field>,
+	attributes: Vec<Attribute>,
+}
+
+impl Model {
+	fn table(&self) -> String {
+		mapped_name(&self.attributes, "map", &self.name)
+	}
+
+	fn field(&self, name: &str) -> Result<&Field> {
+		self.fields.iter().find(|field| field.name == name).ok_or_else(|| anyhow!("model {} has no field {name}", self.name))
+	}
+}
+
+#[derive(Debug)]
+struct Enum {
+	name: String,
+	/// each value with the name it has in the database
+	values: Vec<(String, String)>,
+	attributes: Vec<Attribute>,
+}
+
+impl Enum {
+	fn type_name(&self) -> String {
+		mapped_name(&self.attributes, "map", &self.name)
+	}
+}
+
+#[derive(Debug, Default)]
+struct PrismaSchema {
+	models: Vec<Model>,
+	enums: Vec<Enum>,
+}
+
+fn mapped_name(attributes: &[Attribute], attribute: &str, name: &str) -> String {
+	attributes.iter().find(|found| found.name == attribute)
+		.and_then(|found| found.argument("name"))
+		.and_then(unquote)
+		.unwrap_or_else(|| name.to_string())
+}
+
+/// splits `text` on each `separator` that isn't inside brackets, parentheses or a string
+fn split_top_level(text: &str, separator: char) -> Vec<String> {
+	let (mut parts, mut current, mut depth, mut in_string, mut escaped) = (vec![], String::new(), 0, false, false);
+	for character in text.chars() {
+		match character {
+			_ if escaped => escaped = false,
+			'\\' if in_string => escaped = true,
+			'"' => in_string = !in_string,
+			'(' | '[' | '{' if !in_string => depth += 1,
+			')' | ']' | '}' if !in_string => depth -= 1,
+			_ if character == separator && !in_string && depth == 0 => {
+				parts.push(current.trim().to_string());
+				current.clear();
+				continue;
+			},
+			_ => {},
+		}
+		current.push(character);
+	}
+	if !current.trim().is_empty() {
+		parts.push(current.trim().to_string());
+	}
+	parts
+}
+
+#[test]
+fn test_split_top_level() {
+	assert_eq!(split_top_level("a, b(c, d), [e, f], \"g, h\"", ','), vec!["a", "b(c, d)", "[e, f]", "\"g, h\""]);
+	assert_eq!(split_top_level("\"an \\\" escaped, quote\", i", ','), vec!["\"an \\\" escaped, quote\"", "i"]);
+	assert_eq!(split_top_level("id @default(\"a@b\") @unique", '@'), vec!["id", "default(\"a@b\")", "unique"]);
+	assert_eq!(split_top_level("  ", ','), Vec::<String>::new());
+	assert_eq!(split_top_level("a,,b,", ','), vec!["a", "", "b"]);
+}
+
+/// the contents of a `"string"` value
+fn unquote(value: &str) -> Option<String> {
+	value.strip_prefix('"')?.strip_suffix('"').map(|inner| inner.replace("\\\"", "\"").replace("\\\\", "\\"))
+}
+
+/// the values of a `[list]` value
+fn list(value: &str) -> Vec<String> {
+	let inner = value.trim().strip_prefix('[').and_then(|value| value.strip_suffix(']')).unwrap_or(value);
+	split_top_level(inner, ',')
+}
+
+/// the attributes in `text`, which is everything on a line after a field's type
+fn parse_attributes(text: &str) -> Result<Vec<Attribute>> {
+	let named_argument = Regex::new(r"^(\w+)\s*:\s*(.*)$").unwrap();
+	let mut attributes = vec![];
+	for part in split_top_level(text, '@').into_iter().filter(|part| !part.is_empty()) {
+		let (name, arguments) = match part.split_once('(') {
+			Some((name, arguments)) => {
+				let arguments = arguments.trim_end().strip_suffix(')').ok_or_else(|| anyhow!("unclosed arguments in @{part}"))?;
+				(name, split_top_level(arguments, ','))
+			},
+			None => (part.as_str(), vec![]),
+		};
+		let arguments = arguments.into_iter().map(|argument| match named_argument.captures(&argument) {
+			Some(captures) => (Some(captures[1].to_string()), captures[2].trim().to_string()),
+			None => (None, argument),
+		}).collect();
+		attributes.push(Attribute{name: name.trim().to_string(), arguments});
+	}
+	Ok(attributes)
+}
+
+#[test]
+fn test_parse_attributes() {
+	assert_eq!(parse_attributes("").unwrap(), vec![]);
+	assert_eq!(parse_attributes("@id @default(autoincrement())").unwrap(), vec![
+		Attribute{name: "id".into(), arguments: vec![]},
+		Attribute{name: "default".into(), arguments: vec![(None, "autoincrement()".into())]},
+	]);
+	let relation = parse_attributes("@relation(\"author\", fields: [authorId], references: [id])").unwrap();
+	assert_eq!(relation[0].arguments, vec![
+		(None, "\"author\"".into()),
+		(Some("fields".into()), "[authorId]".into()),
+		(Some("references".into()), "[id]".into()),
+	]);
+	assert_eq!(relation[0].argument("name"), Some("\"author\""));
+	assert_eq!(relation[0].named("name"), None);
+	assert_eq!(parse_attributes("@db.VarChar(255)").unwrap()[0].arguments, vec![(None, "255".into())]);
+
+	assert_eq!(parse_attributes("@map(\"fruit\" @id").unwrap_err().to_string(), "unclosed arguments in @map(\"fruit\" @id");
+}
+
+/// the line without its `//` comment
+fn strip_comment(line: &str) -> &str {
+	let mut in_string = false;
+	let mut previous = ' ';
+	for (index, character) in line.char_indices() {
+		match character {
+			'"' if previous != '\\' => in_string = !in_string,
+			'/' if !in_string && previous == '/' => return &line[..index - 1],
+			_ => {},
+		}
+		previous = character;
+	}
+	line
+}
+
+/// the attributes a field can have, besides `@db.Type` native types
+const FIELD_ATTRIBUTES: [&str; 7] = ["id", "default", "unique", "map", "relation", "updatedAt", "ignore"];
+
+/// the kind, name and numbered lines of a block
+type Block = (String, String, Vec<(usize, String)>);
+
+fn parse_schema(source: &str) -> Result<PrismaSchema> {
+	let block_start = Regex::new(r"^(\w+)\s+(\w+)\s*\{$").unwrap();
+	let field_line = Regex::new(r"^(\w+)\s+(\w+(?:\([^)]*\))?)(\[\])?(\?)?\s*(.*)$").unwrap();
+	let mut schema = PrismaSchema::default();
+	let mut block: Option<Block> = None;
+
+	for (index, line) in source.lines().enumerate() {
+		let line = strip_comment(line).trim();
+		if line.is_empty() {
+			continue;
+		}
+		let Some((kind, name, body)) = block.as_mut() else {
+			let captures = block_start.captures(line).ok_or_else(|| anyhow!("line {}: expected the start of a block, found {line}", index + 1))?;
+			block = Some((captures[1].to_string(), captures[2].to_string(), vec![]));
+			continue;
+		};
+		if line != "}" {
+			body.push((index + 1, line.to_string()));
+			continue;
+		}
+
+		let (kind, name, body) = (kind.clone(), name.clone(), std::mem::take(body));
+		block = None;
+		let (block_attributes, members): (Vec<_>, Vec<_>) = body.into_iter().partition(|(_, line)| line.starts_with("@@"));
+		let mut attributes = vec![];
+		for (_, line) in block_attributes {
+			attributes.extend(parse_attributes(&line[1..])?);
+		}
+		if attributes.iter().any(|attribute| attribute.name == "schema") {
+			return Err(anyhow!("{kind} {name} uses @@schema, multiple schemas aren't supported"));
+		}
+
+		match kind.as_str() {
+			"model" => {
+				let mut fields = vec![];
+				for (line_number, line) in members {
+					let captures = field_line.captures(&line).ok_or_else(|| anyhow!("line {line_number}: expected a field, found {line}"))?;
+					let attributes = parse_attributes(&captures[5]).with_context(|| format!("line {line_number}"))?;
+					let unknown = attributes.iter().find(|attribute| !FIELD_ATTRIBUTES.contains(&attribute.name.as_str()) && !attribute.name.starts_with("db."));
+					if let Some(unknown) = unknown {
+						return Err(anyhow!("line {line_number}: unknown attribute @{} on field {}", unknown.name, &captures[1]));
+					}
+					fields.push(Field{
+						name: captures[1].to_string(),
+						field_type: captures[2].to_string(),
+						list: captures.get(3).is_some(),
+						optional: captures.get(4).is_some(),
+						attributes,
+					});
+				}
+				schema.models.push(Model{name, fields, attributes});
+			},
+			"enum" => {
+				let mut values = vec![];
+				for (line_number, line) in members {
+					let (value, rest) = line.split_once(char::is_whitespace).unwrap_or((&line, ""));
+					let value_attributes = parse_attributes(rest).with_context(|| format!("line {line_number}"))?;
+					values.push((value.to_string(), mapped_name(&value_attributes, "map", value)));
+				}
+				schema.enums.push(Enum{name, values, attributes});
+			},
+			"view" => eprintln!("skipping view {name}, write the sql of the view into the schema directory by hand"),
+			_ => {},
+		}
+	}
+
+	match block {
+		Some((kind, name, _)) => Err(anyhow!("{kind} {name} is never closed")),
+		None => Ok(schema),
+	}
+}
+
+/// the sql type of a `@db.Type(arguments)` native type attribute
+fn native_type(attribute: &Attribute) -> Option<String> {
+	let name = attribute.name.strip_prefix("db.")?;
+	let name = match name {
+		"DoublePrecision" => "double precision".to_string(),
+		name => name.to_lowercase(),
+	};
+	Some(match attribute.arguments.is_empty() {
+		true => name,
+		false => format!("{name}({})", attribute.arguments.iter().map(|(_, value)| value.as_str()).collect::<Vec<_>>().join(",")),
+	})
+}
+
+fn column_type(schema: &PrismaSchema, field: &Field) -> Result<String> {
+	let base_type = match field.attributes.iter().find_map(native_type) {
+		Some(native_type) => native_type,
+		None => match field.field_type.as_str() {
+			"String" => "text".to_string(),
+			"Boolean" => "boolean".to_string(),
+			"Int" => "integer".to_string(),
+			"BigInt" => "bigint".to_string(),
+			"Float" => "double precision".to_string(),
+			"Decimal" => "decimal(65,30)".to_string(),
+			"DateTime" => "timestamp(3)".to_string(),
+			"Json" => "jsonb".to_string(),
+			"Bytes" => "bytea".to_string(),
+			unsupported if unsupported.starts_with("Unsupported(") => {
+				unsupported.strip_prefix("Unsupported(").and_then(|rest| rest.strip_suffix(')')).and_then(unquote)
+					.ok_or_else(|| anyhow!("{} has an unreadable type {unsupported}", field.name))?
+			},
+			other => match schema.enums.iter().find(|found| found.name == other) {
+				Some(found) => quote_identifier(&found.type_name()),
+				None => return Err(anyhow!("{} has type {other}, which isn't a scalar, an enum or a model", field.name)),
+			},
+		},
+	};
+	Ok(if field.list { format!("{base_type}[]") } else { base_type })
+}
+
+/// the sql of the `@default` of `field`, or a note about why the database doesn't have one
+fn default_sql(schema: &PrismaSchema, field: &Field, value: &str, column_type: &str) -> Result<std::result::Result<String, String>> {
+	let client_generated = Regex::new(r"^(uuid|cuid|nanoid|ulid)\(.*\)$").unwrap();
+	if client_generated.is_match(value) {
+		return Ok(Err(format!("@default({value}) was filled in by the prisma client")));
+	}
+	if value == "now()" {
+		return Ok(Ok("current_timestamp".to_string()));
+	}
+	if let Some(expression) = value.strip_prefix("dbgenerated(").and_then(|rest| rest.strip_suffix(')')) {
+		return match unquote(expression) {
+			Some(expression) => Ok(Ok(expression)),
+			None => Ok(Err("@default(dbgenerated()) was left to the database".to_string())),
+		};
+	}
+	if value.starts_with('[') {
+		let element_type = column_type.strip_suffix("[]").unwrap_or(column_type);
+		let elements = list(value).iter()
+			.map(|element| default_sql(schema, field, element, element_type))
+			.collect::<Result<std::result::Result<Vec<_>, _>>>()?;
+		return Ok(elements.map(|elements| format!("array[{}]::{column_type}", elements.join(", "))));
+	}
+	if let Some(text) = unquote(value) {
+		return Ok(Ok(quote_literal(&text)));
+	}
+	if value == "true" || value == "false" || value.parse::<f64>().is_ok() {
+		return Ok(Ok(value.to_string()));
+	}
+	match schema.enums.iter().find(|found| found.name == field.field_type).and_then(|found| found.values.iter().find(|(name, _)| name == value)) {
+		Some((_, database_value)) => Ok(Ok(quote_literal(database_value))),
+		None => Err(anyhow!("{} has a default of {value}, which can't be converted", field.name)),
+	}
+}
+
+#[test]
+fn test_default_sql() {
+	let schema = parse_schema("enum Role {\n\tUSER\n\tADMIN @map(\"admin\")\n}\nmodel User {\n\trole Role\n\tname String\n}").unwrap();
+	let (role, name) = (&schema.models[0].fields[0], &schema.models[0].fields[1]);
+	let default = |field, value, column_type| default_sql(&schema, field, value, column_type).unwrap();
+
+	assert_eq!(default(role, "ADMIN", "\"Role\""), Ok("'admin'".to_string()));
+	assert_eq!(default(role, "[USER, ADMIN]", "\"Role\"[]"), Ok("array['USER', 'admin']::\"Role\"[]".to_string()));
+	assert_eq!(default(name, "\"it's\"", "text"), Ok("'it''s'".to_string()));
+	assert_eq!(default(name, "now()", "timestamp(3)"), Ok("current_timestamp".to_string()));
+	assert_eq!(default(name, "dbgenerated(\"gen_random_uuid()\")", "uuid"), Ok("gen_random_uuid()".to_string()));
+	assert_eq!(default(name, "dbgenerated()", "uuid"), Err("@default(dbgenerated()) was left to the database".to_string()));
+	assert_eq!(default(name, "uuid()", "text"), Err("@default(uuid()) was filled in by the prisma client".to_string()));
+	assert_eq!(default(name, "[uuid(), \"a\"]", "text[]"), Err("@default(uuid()) was filled in by the prisma client".to_string()));
+	assert_eq!(default(name, "-1.5", "double precision"), Ok("-1.5".to_string()));
+
+	assert_eq!(default_sql(&schema, role, "OWNER", "\"Role\"").unwrap_err().to_string(), "role has a default of OWNER, which can't be converted");
+	assert!(default_sql(&schema, name, "[USER]", "text[]").is_err());
+	assert!(default_sql(&schema, name, "env(\"NAME\")", "text").is_err());
+}
+
+fn is_model(schema: &PrismaSchema, field: &Field) -> bool {
+	schema.models.iter().any(|model| model.name == field.field_type)
+}
+
+/// the database columns of the prisma `fields` of `model`, quoted and joined, with any `sort: Desc` turned into `desc`
+fn index_columns(model: &Model, fields: &[String]) -> Result<(String, Vec<String>)> {
+	let (mut quoted, mut columns) = (vec![], vec![]);
+	for field in fields {
+		let (name, options) = field.split_once('(').unwrap_or((field, ""));
+		let column = model.field(name.trim())?.column();
+		let descending = options.replace(' ', "").contains("sort:Desc");
+		quoted.push(format!("{}{}", quote_identifier(&column), if descending { " desc" } else { "" }));
+		columns.push(column);
+	}
+	Ok((quoted.join(", "), columns))
+}
+
+fn referential_action(action: &str) -> Result<&'static str> {
+	match action {
+		"Cascade" => Ok("cascade"),
+		"Restrict" => Ok("restrict"),
+		"NoAction" => Ok("no action"),
+		"SetNull" => Ok("set null"),
+		"SetDefault" => Ok("set default"),
+		other => Err(anyhow!("unknown referential action {other}")),
+	}
+}
+
+/// the `create table` and index statements of `model`
+fn model_sql(schema: &PrismaSchema, model: &Model) -> Result<String> {
+	let table = model.table();
+	let mut definitions: Vec<(String, Option<String>)> = vec![];
+	let mut primary_key = None;
+	let mut indexes = vec![];
+
+	for field in model.fields.iter().filter(|field| !is_model(schema, field)) {
+		let mut sql_type = column_type(schema, field)?;
+		let mut definition = vec![];
+		let mut note = None;
+		if let Some(value) = field.attribute("default").and_then(|attribute| attribute.argument("value")) {
+			match (value, sql_type.as_str()) {
+				("autoincrement()", "integer") => sql_type = "serial".to_string(),
+				("autoincrement()", "bigint") => sql_type = "bigserial".to_string(),
+				("autoincrement()", "smallint") => sql_type = "smallserial".to_string(),
+				_ => match default_sql(schema, field, value, &sql_type).with_context(|| format!("model {}", model.name))? {
+					Ok(default) => definition.push(format!("default {default}")),
+					Err(reason) => note = Some(reason),
+				},
+			}
+		}
+		if field.attribute("updatedAt").is_some() {
+			note = Some("@updatedAt was set by the prisma client".to_string());
+		}
+		if !field.optional {
+			definition.insert(0, "not null".to_string());
+		}
+		definition.insert(0, format!("{} {sql_type}", quote_identifier(&field.column())));
+		definitions.push((definition.join(" "), note));
+
+		if let Some(id) = field.attribute("id") {
+			primary_key = Some((id.named("map").and_then(unquote), quote_identifier(&field.column())));
+		}
+		if let Some(unique) = field.attribute("unique") {
+			let name = unique.named("map").and_then(unquote).unwrap_or_else(|| format!("{table}_{}_key", field.column()));
+			let descending = if unique.named("sort") == Some("Desc") { " desc" } else { "" };
+			indexes.push(format!("create unique index {} on {} ({}{descending});", quote_identifier(&name), quote_identifier(&table), quote_identifier(&field.column())));
+		}
+	}
+
+	for attribute in model.attributes.iter().filter(|attribute| ["id", "unique", "index"].contains(&attribute.name.as_str())) {
+		let Some(fields) = attribute.argument("fields") else { continue };
+		let (quoted, columns) = index_columns(model, &list(fields)).with_context(|| format!("@@{} of model {}", attribute.name, model.name))?;
+		let map = attribute.named("map").and_then(unquote);
+		match attribute.name.as_str() {
+			"id" => primary_key = Some((map, quoted)),
+			"unique" => {
+				let name = map.unwrap_or_else(|| format!("{table}_{}_key", columns.join("_")));
+				indexes.push(format!("create unique index {} on {} ({quoted});", quote_identifier(&name), quote_identifier(&table)));
+			},
+			"index" => {
+				let name = map.unwrap_or_else(|| format!("{table}_{}_idx", columns.join("_")));
+				let method = attribute.named("type").map(|method| format!(" using {}", method.to_lowercase())).unwrap_or_default();
+				indexes.push(format!("create index {} on {}{method} ({quoted});", quote_identifier(&name), quote_identifier(&table)));
+			},
+			_ => {},
+		}
+	}
+
+	if let Some((name, columns)) = primary_key {
+		let name = name.unwrap_or_else(|| format!("{table}_pkey"));
+		definitions.push((format!("constraint {} primary key ({columns})", quote_identifier(&name)), None));
+	}
+	let count = definitions.len();
+	let body: Vec<String> = definitions.into_iter().enumerate().map(|(index, (definition, note))| {
+		let separator = if index + 1 < count { "," } else { "" };
+		match note {
+			Some(note) => format!("\t{definition}{separator} -- {note}"),
+			None => format!("\t{definition}{separator}"),
+		}
+	}).collect();
+
+	let mut sql = format!("create table {} (\n{}\n);\n", quote_identifier(&table), body.join("\n"));
+	for index in indexes {
+		sql.push_str(&format!("\n{index}\n"));
+	}
+	Ok(sql)
+}
+
+/// the column type of the single `@id` of `model`, for the columns that refer to it
+fn id_column(schema: &PrismaSchema, model: &Model) -> Result<(String, String)> {
+	let id = model.fields.iter().find(|field| field.attribute("id").is_some())
+		.ok_or_else(|| anyhow!("model {} needs a single @id to be in an implicit many-to-many relation", model.name))?;
+	Ok((id.column(), column_type(schema, id)?))
+}
+
+/// the implicit many-to-many tables and the foreign keys of every model
+fn relations_sql(schema: &PrismaSchema) -> Result<String> {
+	let mut join_tables = BTreeMap::new();
+	let mut foreign_keys = vec![];
+
+	for model in &schema.models {
+		let table = model.table();
+		for field in model.fields.iter().filter(|field| is_model(schema, field)) {
+			let relation = field.attribute("relation");
+			let relation_name = relation.and_then(|relation| relation.argument("name")).and_then(unquote);
+			let referenced = schema.models.iter().find(|found| found.name == field.field_type).unwrap();
+
+			let Some(fields) = relation.and_then(|relation| relation.named("fields")) else {
+				let back_relation = referenced.fields.iter().find(|back| {
+					back.field_type == model.name && back.list && !std::ptr::eq(*back, field)
+						&& back.attribute("relation").and_then(|relation| relation.argument("name")).and_then(unquote) == relation_name
+				});
+				if field.list && back_relation.is_some() {
+					let (first, second) = if model.name <= referenced.name { (model, referenced) } else { (referenced, model) };
+					let name = format!("_{}", relation_name.unwrap_or_else(|| format!("{}To{}", first.name, second.name)));
+					join_tables.insert(name, (first, second));
+				}
+				continue;
+			};
+
+			let fields = list(fields);
+			let references = list(relation.and_then(|relation| relation.named("references")).ok_or_else(|| {
+				anyhow!("{}.{} has relation fields but no references", model.name, field.name)
+			})?);
+			let (quoted, columns) = index_columns(model, &fields)?;
+			let (referenced_quoted, _) = index_columns(referenced, &references)?;
+			let name = relation.and_then(|relation| relation.named("map")).and_then(unquote)
+				.unwrap_or_else(|| format!("{table}_{}_fkey", columns.join("_")));
+			let all_optional = fields.iter().map(|name| model.field(name)).collect::<Result<Vec<_>>>()?.iter().all(|field| field.optional);
+			let on_delete = match relation.and_then(|relation| relation.named("onDelete")) {
+				Some(action) => referential_action(action)?,
+				None => if all_optional { "set null" } else { "restrict" },
+			};
+			let on_update = referential_action(relation.and_then(|relation| relation.named("onUpdate")).unwrap_or("Cascade"))?;
+			foreign_keys.push(format!(
+				"alter table {} add constraint {} foreign key ({quoted}) references {} ({referenced_quoted}) on delete {on_delete} on update {on_update};",
+				quote_identifier(&table), quote_identifier(&name), quote_identifier(&referenced.table()),
+			));
+		}
+	}
+
+	let mut sql = vec![];
+	for (name, (first, second)) in join_tables {
+		let ((first_column, first_type), (second_column, second_type)) = (id_column(schema, first)?, id_column(schema, second)?);
+		sql.push(format!(
+			"create table {} (\n\t\"A\" {first_type} not null,\n\t\"B\" {second_type} not null,\n\tconstraint {} primary key (\"A\", \"B\")\n);\n\ncreate index {} on {} (\"B\");",
+			quote_identifier(&name), quote_identifier(&format!("{name}_AB_pkey")), quote_identifier(&format!("{name}_B_index")), quote_identifier(&name),
+		));
+		for (column, model, id) in [("A", first, first_column), ("B", second, second_column)] {
+			foreign_keys.push(format!(
+				"alter table {} add constraint {} foreign key (\"{column}\") references {} ({}) on delete cascade on update cascade;",
+				quote_identifier(&name), quote_identifier(&format!("{name}_{column}_fkey")), quote_identifier(&model.table()), quote_identifier(&id),
+			));
+		}
+	}
+	sql.extend(foreign_keys);
+	Ok(sql.into_iter().map(|statement| format!("{statement}\n")).collect::<Vec<_>>().join("\n"))
+}
+
+#[test]
+fn test_relations_sql() {
+	let schema = parse_schema("model Fruit {\n\tid Int @id\n\ttreeId Int\n\ttree Tree @relation(fields: [treeId], references: [id], onUpdate: NoAction)\n}\nmodel Tree {\n\tid Int @id\n\tfruits Fruit[]\n}").unwrap();
+	assert_eq!(
+		relations_sql(&schema).unwrap(),
+		"alter table \"Fruit\" add constraint \"Fruit_treeId_fkey\" foreign key (\"treeId\") references \"Tree\" (\"id\") on delete restrict on update no action;\n",
+	);
+	assert_eq!(relations_sql(&parse_schema("model Fruit {\n\tid Int @id\n}").unwrap()).unwrap(), "");
+
+	let relations_error = |source: &str| format!("{:#}", relations_sql(&parse_schema(source).unwrap()).unwrap_err());
+	assert_eq!(
+		relations_error("model Fruit {\n\ttree Tree @relation(fields: [treeId])\n\ttreeId Int\n}\nmodel Tree {\n\tid Int @id\n}"),
+		"Fruit.tree has relation fields but no references",
+	);
+	assert_eq!(
+		relations_error("model Fruit {\n\ttree Tree @relation(fields: [treeId], references: [id])\n}\nmodel Tree {\n\tid Int @id\n}"),
+		"model Fruit has no field treeId",
+	);
+	assert_eq!(
+		relations_error("model Fruit {\n\ttreeId Int\n\ttree Tree @relation(fields: [treeId], references: [id], onDelete: Explode)\n}\nmodel Tree {\n\tid Int @id\n}"),
+		"unknown referential action Explode",
+	);
+	assert_eq!(
+		relations_error("model Fruit {\n\tid Int @id\n\ttrees Tree[]\n}\nmodel Tree {\n\tname String\n\tfruits Fruit[]\n}"),
+		"model Tree needs a single @id to be in an implicit many-to-many relation",
+	);
+
+	// a relation to a model that doesn't exist is taken as a column of an unknown type
+	let missing_model = parse_schema("model Fruit {\n\tid Int @id\n\ttree Tree\n}").unwrap();
+	assert_eq!(relations_sql(&missing_model).unwrap(), "");
+	assert_eq!(
+		schema_files(&missing_model).unwrap_err().to_string(),
+		"tree has type Tree, which isn't a scalar, an enum or a model",
+	);
+	assert_eq!(
+		parse_schema("model Fruit {\n\tid Int @id @primary\n}").unwrap_err().to_string(),
+		"line 2: unknown attribute @primary on field id",
+	);
+}
+
+/// the relative path and sql of every file converted from `schema`
+fn schema_files(schema: &PrismaSchema) -> Result<Vec<(PathBuf, String)>> {
+	let mut files = vec![];
+	if !schema.enums.is_empty() {
+		let enums: Vec<String> = schema.enums.iter().map(|found| {
+			let values: Vec<String> = found.values.iter().map(|(_, value)| quote_literal(value)).collect();
+			format!("create type {} as enum ({});\n", quote_identifier(&found.type_name()), values.join(", "))
+		}).collect();
+		files.push((PathBuf::from("enums.sql"), enums.join("\n")));
+	}
+	for model in &schema.models {
+		files.push((PathBuf::from("models").join(format!("{}.sql", make_slug(&model.table()))), model_sql(schema, model)?));
+	}
+	let relations = relations_sql(schema)?;
+	if !relations.is_empty() {
+		files.push((PathBuf::from("relations.sql"), relations));
+	}
+	Ok(files)
+}
+
+#[test]
+fn test_schema_files() {
+	let schema = parse_schema(r#"
+		datasource db {
+			provider = "postgresql"
+			url      = env("DATABASE_URL") // the url
+		}
+
+		/// who can do what
+		enum Role {
+			USER
+			ADMIN @map("admin")
+			@@map("role")
+		}
+
+		model User {
+			id        Int      @id @default(autoincrement())
+			email     String   @unique @db.VarChar(255)
+			role      Role     @default(ADMIN)
+			tags      String[] @default([])
+			createdAt DateTime @default(now()) @map("created_at")
+			updatedAt DateTime @updatedAt
+			posts     Post[]
+			@@map("users")
+		}
+
+		model Post {
+			id       String  @id @default(cuid())
+			title    String  @default("it's new")
+			score    Decimal? @db.Decimal(10, 2)
+			author   User?   @relation(fields: [authorId], references: [id], onDelete: Cascade)
+			authorId Int?
+			tags     Tag[]
+			@@unique([authorId, title(sort: Desc)])
+			@@index([title], type: Hash)
+		}
+
+		model Tag {
+			name  String @id
+			posts Post[]
+		}
+	"#).unwrap();
+
+	let files = schema_files(&schema).unwrap();
+	let paths: Vec<_> = files.iter().map(|(path, _)| path.to_str().unwrap()).collect();
+	assert_eq!(paths, vec!["enums.sql", "models/users.sql", "models/post.sql", "models/tag.sql", "relations.sql"]);
+	assert_eq!(files[0].1, "create type \"role\" as enum ('USER', 'admin');\n");
+	assert_eq!(files[1].1, r#"create table "users" (
+	"id" serial not null,
+	"email" varchar(255) not null,
+	"role" "role" not null default 'admin',
+	"tags" text[] not null default array[]::text[],
+	"created_at" timestamp(3) not null default current_timestamp,
+	"updatedAt" timestamp(3) not null, -- @updatedAt was set by the prisma client
+	constraint "users_pkey" primary key ("id")
+);
+
+create unique index "users_email_key" on "users" ("email");
+"#);
+	assert_eq!(files[2].1, r#"create table "Post" (
+	"id" text not null, -- @default(cuid()) was filled in by the prisma client
+	"title" text not null default 'it''s new',
+	"score" decimal(10,2),
+	"authorId" integer,
+	constraint "Post_pkey" primary key ("id")
+);
+
+create unique index "Post_authorId_title_key" on "Post" ("authorId", "title" desc);
+
+create index "Post_title_idx" on "Post" using hash ("title");
+"#);
+	assert_eq!(files[4].1, r#"create table "_PostToTag" (
+	"A" text not null,
+	"B" text not null,
+	constraint "_PostToTag_AB_pkey" primary key ("A", "B")
+);
+
+create index "_PostToTag_B_index" on "_PostToTag" ("B");
+
+alter table "Post" add constraint "Post_authorId_fkey" foreign key ("authorId") references "users" ("id") on delete cascade on update cascade;
+
+alter table "_PostToTag" add constraint "_PostToTag_A_fkey" foreign key ("A") references "Post" ("id") on delete cascade on update cascade;
+
+alter table "_PostToTag" add constraint "_PostToTag_B_fkey" foreign key ("B") references "Tag" ("name") on delete cascade on update cascade;
+"#);
+
+	assert!(parse_schema("model Fruit {\n\tid Int @id\n").unwrap_err().to_string().contains("never closed"));
+	let schema = parse_schema("model Fruit {\n\tid Color @id\n}").unwrap();
+	assert!(schema_files(&schema).is_err());
+}
+
+/// converts the prisma schema at `file` into sql files in an empty schema directory
+pub(crate) fn import_prisma(args: &Args, file: &str) -> Result<()> {
+	let source = fs::read_to_string(file).with_context(|| format!("unable to read {file}"))?;
+	let files = schema_files(&parse_schema(&source).with_context(|| format!("unable to parse {file}"))?)?;
+
+	let schema_directory = &args.schema_directory;
+	super::ensure_directory(schema_directory)?;
+	if !list_sql_files(schema_directory)?.is_empty() {
+		return Err(anyhow!("{schema_directory} already contains sql files, imports can only be done into an empty schema directory"));
+	}
+	for (relative_path, sql) in files {
+		let path = Path::new(schema_directory).join(relative_path);
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		fs::write(&path, sql).with_context(|| format!("unable to write {}", path.display()))?;
+		println!("{file} -> {}", path.display());
+	}
+	Ok(())
+}
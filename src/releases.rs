@@ -0,0 +1,105 @@
+use std::{fs, path::PathBuf};
+use anyhow::{anyhow, Result};
+use postgres_migrator::read_sql_file;
+
+use super::{Args, MigrationFile, gather_validated_migrations};
+
+/// lists `<release> <version>` lines, next to the migrations so it's committed along with them
+const RELEASES_FILE_NAME: &str = "releases.txt";
+
+fn releases_path(migrations_directory: &str) -> PathBuf {
+	PathBuf::from(migrations_directory).join(RELEASES_FILE_NAME)
+}
+
+/// the releases in the order they were tagged
+fn parse_releases(contents: &str) -> Result<Vec<(String, String)>> {
+	contents.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.map(|line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+			[release, version] => Ok((release.to_string(), version.to_string())),
+			_ => Err(anyhow!("invalid line in {RELEASES_FILE_NAME}: {line}")),
+		})
+		.collect()
+}
+
+#[test]
+fn test_parse_releases() {
+	assert_eq!(
+		parse_releases("# releases\nv1.2 20220101000000\n\nv1.3   20220102000000\n").unwrap(),
+		vec![("v1.2".to_string(), "20220101000000".to_string()), ("v1.3".to_string(), "20220102000000".to_string())],
+	);
+	assert!(parse_releases("v1.2\n").is_err());
+}
+
+fn read_releases(migrations_directory: &str) -> Result<Vec<(String, String)>> {
+	let path = releases_path(migrations_directory);
+	match path.exists() {
+		true => parse_releases(&fs::read_to_string(path)?),
+		false => Ok(vec![]),
+	}
+}
+
+/// records the latest migration as the last one shipped in `release`
+pub(crate) fn command_tag(args: &Args, release: &str) -> Result<()> {
+	if release.is_empty() || release.contains(char::is_whitespace) || release.starts_with('#') {
+		return Err(anyhow!("invalid release name {release:?}"));
+	}
+	let (_, current_version) = gather_validated_migrations(args)?;
+	let current_version = current_version.ok_or_else(|| anyhow!("there are no migrations to tag"))?;
+
+	let mut releases = read_releases(&args.migrations_directory)?;
+	if let Some((_, version)) = releases.iter().find(|(tagged, _)| tagged == release) {
+		return Err(anyhow!("{release} is already tagged at {version}"));
+	}
+	releases.push((release.to_string(), current_version.clone()));
+
+	let mut contents = "# the latest migration of each release, written by postgres_migrator tag\n".to_string();
+	for (release, version) in &releases {
+		contents.push_str(&format!("{release} {version}\n"));
+	}
+	fs::write(releases_path(&args.migrations_directory), contents)?;
+	println!("tagged {current_version} as {release}");
+	Ok(())
+}
+
+/// the migrations after `from_version` up to and including `to_version`
+fn migrations_between<'a>(migration_files: &'a [MigrationFile], from_version: &str, to_version: &str) -> Vec<&'a MigrationFile> {
+	migration_files.iter()
+		.filter(|migration_file| migration_file.current_version.as_str() > from_version && migration_file.current_version.as_str() <= to_version)
+		.collect()
+}
+
+/// prints the migrations that ship in `to_release` but not in `from_release`, one after another
+pub(crate) fn command_diff_releases(args: &Args, from_release: &str, to_release: &str) -> Result<()> {
+	let releases = read_releases(&args.migrations_directory)?;
+	let version_of = |release: &str| releases.iter().find(|(tagged, _)| tagged == release).map(|(_, version)| version.clone())
+		.ok_or_else(|| anyhow!("{release} hasn't been tagged, see {}", releases_path(&args.migrations_directory).display()));
+	let (from_version, to_version) = (version_of(from_release)?, version_of(to_release)?);
+	if from_version > to_version {
+		return Err(anyhow!("{from_release} ({from_version}) is newer than {to_release} ({to_version})"));
+	}
+
+	let (migration_files, _) = gather_validated_migrations(args)?;
+	let shipped = migrations_between(&migration_files, &from_version, &to_version);
+	if shipped.is_empty() {
+		println!("-- no schema changes between {from_release} and {to_release}");
+	}
+	for migration_file in shipped {
+		println!("-- {}\n{}\n", migration_file.display_file_path, read_sql_file(&migration_file.file_path)?.trim_end());
+	}
+	Ok(())
+}
+
+#[test]
+fn test_migrations_between() {
+	let migration_files = MigrationFile::vec_from_paths(vec![
+		PathBuf::from("migrations/20220101000000.null.one.sql"),
+		PathBuf::from("migrations/20220102000000.20220101000000.two.sql"),
+		PathBuf::from("migrations/20220103000000.20220102000000.three.sql"),
+	]).unwrap();
+	let versions = |from_version, to_version| migrations_between(&migration_files, from_version, to_version).iter()
+		.map(|migration_file| migration_file.current_version.as_str()).collect::<Vec<_>>();
+	assert_eq!(versions("20220101000000", "20220103000000"), vec!["20220102000000", "20220103000000"]);
+	assert_eq!(versions("20220102000000", "20220102000000"), Vec::<&str>::new());
+}
@@ -0,0 +1,124 @@
+//! Downloads the schema or migrations directory from an http(s) url of a `.tar.gz`, `.tgz` or `.tar` archive.
+
+use std::{fs, path::{Path, PathBuf}, process::Command};
+use anyhow::{anyhow, Context, Result};
+
+pub(crate) fn is_remote(directory: &str) -> bool {
+	directory.starts_with("https://") || directory.starts_with("http://")
+}
+
+/// a downloaded and unpacked archive, which is deleted when this is dropped
+pub(crate) struct RemoteDirectory {
+	temp_directory: PathBuf,
+	/// where the sql files are, the archive's only top level directory if it has one
+	pub(crate) directory: PathBuf,
+}
+
+impl RemoteDirectory {
+	/// a new empty temporary directory, with an `unpacked` directory in it for the files
+	pub(crate) fn create() -> Result<RemoteDirectory> {
+		let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+		let temp_directory = std::env::temp_dir().join(format!("postgres_migrator-{}-{nanos}", std::process::id()));
+		let directory = temp_directory.join("unpacked");
+		fs::create_dir_all(&directory)?;
+		Ok(RemoteDirectory{ temp_directory, directory })
+	}
+
+	/// where downloads and other files that aren't unpacked go
+	pub(crate) fn temp_path(&self, name: &str) -> PathBuf {
+		self.temp_directory.join(name)
+	}
+}
+
+impl Drop for RemoteDirectory {
+	fn drop(&mut self) {
+		if let Err(err) = fs::remove_dir_all(&self.temp_directory) {
+			eprintln!("unable to remove {}: {err}", self.temp_directory.display());
+		}
+	}
+}
+
+/// the flags of `tar` that unpack the archive at `url`, by its extension
+fn tar_flags(url: &str) -> Option<&'static str> {
+	let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+	match () {
+		_ if path.ends_with(".tar.gz") || path.ends_with(".tgz") => Some("-xzf"),
+		_ if path.ends_with(".tar") => Some("-xf"),
+		_ => None,
+	}
+}
+
+#[test]
+fn test_tar_flags() {
+	assert_eq!(tar_flags("https://artifacts.example.com/schema-v42.tar.gz"), Some("-xzf"));
+	assert_eq!(tar_flags("https://artifacts.example.com/schema-v42.TGZ?token=abc"), Some("-xzf"));
+	assert_eq!(tar_flags("http://artifacts.example.com/migrations.tar"), Some("-xf"));
+	assert_eq!(tar_flags("https://artifacts.example.com/schema.zip"), None);
+}
+
+pub(crate) fn run(command: &mut Command, description: &str) -> Result<()> {
+	let output = command.output().with_context(|| format!("unable to run {description}"))?;
+	if !output.status.success() {
+		return Err(anyhow!("{description} failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+	}
+	Ok(())
+}
+
+/// the only entry of `directory` if it's a directory, since archives usually wrap their files in one, otherwise `directory`
+fn unwrap_single_directory(directory: &Path) -> Result<PathBuf> {
+	let entries = fs::read_dir(directory)?.collect::<Result<Vec<_>, _>>()?;
+	match &entries[..] {
+		[entry] if entry.file_type()?.is_dir() => Ok(entry.path()),
+		_ => Ok(directory.to_path_buf()),
+	}
+}
+
+/// downloads the archive at `url` with curl, sending `headers` like `Authorization: Bearer ...`, and unpacks it with tar.
+/// the headers are handed to curl in a file, so they don't show up in the list of processes
+pub(crate) fn fetch(url: &str, headers: &[String]) -> Result<RemoteDirectory> {
+	let flags = tar_flags(url).ok_or_else(|| anyhow!("{url} should be a .tar.gz, .tgz or .tar archive"))?;
+	let mut remote = RemoteDirectory::create()?;
+	let unpacked = remote.directory.clone();
+	let archive = remote.temp_path("archive");
+	let headers_file = remote.temp_path("headers");
+	let mut curl = Command::new("curl");
+	curl.args(["--fail", "--silent", "--show-error", "--location", "--output"]).arg(&archive);
+	if !headers.is_empty() {
+		fs::write(&headers_file, headers.iter().map(|header| format!("{header}\n")).collect::<String>())?;
+		curl.arg("--header").arg(format!("@{}", headers_file.display()));
+	}
+	let downloaded = run(curl.arg(url), &format!("curl {url}"));
+	let _ = fs::remove_file(&headers_file);
+	downloaded?;
+
+	run(Command::new("tar").arg(flags).arg(&archive).arg("-C").arg(&unpacked), &format!("tar {flags}"))
+		.with_context(|| format!("unable to unpack {url}"))?;
+	remote.directory = unwrap_single_directory(&unpacked)?;
+	Ok(remote)
+}
+
+#[test]
+fn test_fetch_failures() -> Result<()> {
+	assert_eq!(
+		fetch("https://artifacts.example.com/schema.zip", &[]).err().unwrap().to_string(),
+		"https://artifacts.example.com/schema.zip should be a .tar.gz, .tgz or .tar archive",
+	);
+	let error = fetch("http://localhost:1/schema.tar.gz", &["Authorization: Bearer abc".into()]).err().unwrap();
+	assert!(error.to_string().starts_with("curl http://localhost:1/schema.tar.gz failed: "));
+	assert_eq!(
+		run(&mut Command::new("postgres_migrator_command_that_doesnt_exist"), "nothing").unwrap_err().to_string(),
+		"unable to run nothing",
+	);
+
+	let remote = RemoteDirectory::create()?;
+	let temp_directory = remote.temp_directory.clone();
+	fs::create_dir(remote.directory.join("schema"))?;
+	assert_eq!(unwrap_single_directory(&remote.directory)?, remote.directory.join("schema"));
+	fs::write(remote.directory.join("fruit.sql"), "create table fruit ();")?;
+	assert_eq!(unwrap_single_directory(&remote.directory)?, remote.directory);
+	fs::remove_dir(remote.directory.join("schema"))?;
+	assert_eq!(unwrap_single_directory(&remote.directory)?, remote.directory);
+	drop(remote);
+	assert!(!temp_directory.exists());
+	Ok(())
+}
@@ -0,0 +1,113 @@
+//! Maps the roles the schema and migrations use to the roles of the database, from the `[roles]` table of `migrator.toml`.
+
+use std::collections::BTreeMap;
+use anyhow::Result;
+use regex::Regex;
+
+use super::{quote_identifier, quote_literal};
+use super::objects::normalize_name;
+use super::settings::Settings;
+use super::statements::split_statements;
+
+/// pairs of (role in the schema, role in the database)
+pub(crate) fn read_role_mapping() -> Result<Vec<(String, String)>> {
+	Ok(Settings::read()?.roles.into_iter().collect())
+}
+
+/// sql that makes the roles of the database own what the roles of the schema own in a temp database,
+/// for every pair of roles that both exist on the server temp databases are created on
+pub(crate) fn reassign_sql(role_mapping: &[(String, String)]) -> String {
+	role_mapping.iter()
+		.map(|(schema_role, database_role)| format!(
+			"do $$ begin\n\tif exists (select from pg_roles where rolname = {}) and exists (select from pg_roles where rolname = {}) then\n\t\treassign owned by {} to {};\n\tend if;\nend $$;\n",
+			quote_literal(schema_role), quote_literal(database_role), quote_identifier(schema_role), quote_identifier(database_role),
+		))
+		.collect()
+}
+
+const ROLE: &str = r#"("[^"]+"|[\w$]+)"#;
+
+/// `diff` without the ownership and privilege statements that only differ by a mapped role, and how many were left out.
+/// `owner to` statements naming either role of a pair are left out, and so are a `grant` and a `revoke`
+/// that are the same once their roles are mapped
+pub(crate) fn without_mapped_roles(diff: &str, role_mapping: &[(String, String)]) -> Result<(String, usize)> {
+	if role_mapping.is_empty() {
+		return Ok((diff.to_string(), 0));
+	}
+	let to_schema_role: BTreeMap<String, String> = role_mapping.iter()
+		.flat_map(|(schema_role, database_role)| [(schema_role.to_lowercase(), schema_role.to_lowercase()), (database_role.to_lowercase(), schema_role.to_lowercase())])
+		.collect();
+	let owner = Regex::new(&format!(r"(?is)^alter\s.+\sowner\s+to\s+{ROLE}$")).unwrap();
+	let privilege = Regex::new(&format!(r"(?is)^(grant|revoke)\s+(.+?)\s+(?:to|from)\s+{ROLE}(\s.*)?$")).unwrap();
+
+	let statements = split_statements(diff)?;
+	// a grant or revoke as it would be with the roles of the schema, by whether it's a grant
+	let mapped_privilege = |sql: &str| {
+		let captures = privilege.captures(sql)?;
+		let role = to_schema_role.get(&normalize_name(&captures[3]))?;
+		let canonical = format!("{} {role} {}", captures[2].split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase(), captures.get(4).map_or("", |rest| rest.as_str().trim()));
+		Some((captures[1].eq_ignore_ascii_case("grant"), canonical))
+	};
+	let privileges: Vec<_> = statements.iter().map(|statement| mapped_privilege(&statement.sql)).collect();
+
+	let total = statements.len();
+	let kept: Vec<String> = statements.iter().zip(&privileges)
+		.filter(|(statement, mapped)| {
+			if owner.captures(&statement.sql).is_some_and(|captures| to_schema_role.contains_key(&normalize_name(&captures[1]))) {
+				return false;
+			}
+			let Some((is_grant, canonical)) = mapped else { return true };
+			!privileges.iter().flatten().any(|(other_is_grant, other_canonical)| other_is_grant != is_grant && other_canonical == canonical)
+		})
+		.map(|(statement, _)| format!("{};", statement.sql))
+		.collect();
+	let left_out = total - kept.len();
+	match left_out {
+		0 => Ok((diff.to_string(), 0)),
+		_ => Ok((kept.join("\n\n"), left_out)),
+	}
+}
+
+#[test]
+fn test_without_mapped_roles() {
+	let role_mapping = vec![("app".to_string(), "app_prod".to_string())];
+	let diff = r#"alter table "public"."fruit" owner to "app";
+
+revoke select on table "public"."fruit" from "app_prod";
+
+grant select on table "public"."fruit" to "app";
+
+grant insert on table "public"."fruit" to "app";
+
+grant select on table "public"."fruit" to "reader";
+
+alter table "public"."person" owner to "admin";"#;
+	assert_eq!(
+		without_mapped_roles(diff, &role_mapping).unwrap(),
+		(r#"grant insert on table "public"."fruit" to "app";
+
+grant select on table "public"."fruit" to "reader";
+
+alter table "public"."person" owner to "admin";"#.to_string(), 3),
+	);
+	assert_eq!(without_mapped_roles(diff, &[]).unwrap(), (diff.to_string(), 0));
+}
+
+#[test]
+fn test_without_mapped_roles_failures() {
+	let role_mapping = vec![("app".to_string(), "app_prod".to_string())];
+	assert_eq!(
+		without_mapped_roles("grant select on table \"public\".\"fruit to \"app\";", &role_mapping).unwrap_err().to_string(),
+		"unterminated quoted identifier in statement starting on line 1",
+	);
+	// a grant and revoke of different privileges, or to roles that aren't mapped, are real differences
+	let diff = r#"revoke select on table "public"."fruit" from "app_prod";
+
+grant update on table "public"."fruit" to "app";
+
+revoke select on table "public"."fruit" from "reader";
+
+grant select on table "public"."fruit" to "writer";"#;
+	assert_eq!(without_mapped_roles(diff, &role_mapping).unwrap(), (diff.to_string(), 0));
+	assert_eq!(reassign_sql(&[]), "");
+}
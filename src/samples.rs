@@ -0,0 +1,170 @@
+//! Copies a bounded sample of the rows of every table of the database into a temp database.
+
+use std::io;
+use anyhow::{Context, Result};
+use postgres::{Client, Config};
+
+use super::{connections, quote_identifier};
+
+/// a table and the columns rows can be copied into, leaving out generated columns
+#[derive(Debug)]
+struct Table {
+	schema: String,
+	name: String,
+	columns: Vec<String>,
+}
+
+impl Table {
+	fn qualified_name(&self) -> String {
+		format!("{}.{}", quote_identifier(&self.schema), quote_identifier(&self.name))
+	}
+}
+
+/// the plain tables and partitions outside of system schemas that don't belong to an extension.
+/// partitioned tables are left out since their rows are copied through their partitions
+fn query_tables(client: &mut Client) -> Result<Vec<Table>> {
+	let query = "
+		select n.nspname as schema, c.relname as name,
+			array(
+				select a.attname::text from pg_attribute a
+				where a.attrelid = c.oid and a.attnum > 0 and not a.attisdropped and a.attgenerated = ''
+				order by a.attnum
+			) as columns
+		from pg_class c join pg_namespace n on n.oid = c.relnamespace
+		where c.relkind = 'r'
+			and n.nspname not in ('pg_catalog', 'information_schema') and n.nspname not like 'pg\\_toast%' and n.nspname not like 'pg\\_temp%'
+			and not exists (select from pg_depend d where d.classid = 'pg_class'::regclass and d.objid = c.oid and d.deptype = 'e')
+		order by 1, 2
+	";
+	Ok(client.query(query, &[])?.into_iter()
+		.map(|row| Table{ schema: row.get("schema"), name: row.get("name"), columns: row.get("columns") })
+		.collect())
+}
+
+/// the `copy` that reads up to `rows` rows of `source` and the `copy` that writes them into `target`,
+/// for the columns both have, or `None` if they don't share any
+fn copy_statements(source: &Table, target: &Table, rows: u64) -> Option<(String, String)> {
+	let columns = target.columns.iter()
+		.filter(|column| source.columns.contains(column))
+		.map(|column| quote_identifier(column))
+		.collect::<Vec<_>>()
+		.join(", ");
+	if columns.is_empty() {
+		return None;
+	}
+	Some((
+		format!("copy (select {columns} from {} limit {rows}) to stdout", source.qualified_name()),
+		format!("copy {} ({columns}) from stdin", target.qualified_name()),
+	))
+}
+
+#[test]
+fn test_copy_statements() {
+	let table = |columns: &[&str]| Table{ schema: "public".to_string(), name: "fruit".to_string(), columns: columns.iter().map(|column| column.to_string()).collect() };
+	assert_eq!(
+		copy_statements(&table(&["id", "name", "dropped_later"]), &table(&["id", "added_later", "name"]), 100),
+		Some((
+			r#"copy (select "id", "name" from "public"."fruit" limit 100) to stdout"#.to_string(),
+			r#"copy "public"."fruit" ("id", "name") from stdin"#.to_string(),
+		)),
+	);
+	assert_eq!(copy_statements(&table(&["id"]), &table(&["other"]), 100), None);
+}
+
+/// deletes the sampled rows whose foreign keys point at rows that weren't sampled, until there aren't any,
+/// returning how many were deleted
+fn delete_dangling_rows(client: &mut Client) -> Result<u64> {
+	let query = "
+		select c.conrelid::regclass::text as child, c.confrelid::regclass::text as parent,
+			array(
+				select a.attname::text from unnest(c.conkey) with ordinality k(attnum, position)
+				join pg_attribute a on a.attrelid = c.conrelid and a.attnum = k.attnum order by k.position
+			) as columns,
+			array(
+				select a.attname::text from unnest(c.confkey) with ordinality k(attnum, position)
+				join pg_attribute a on a.attrelid = c.confrelid and a.attnum = k.attnum order by k.position
+			) as referenced_columns
+		from pg_constraint c
+		where c.contype = 'f'
+	";
+	let deletes: Vec<String> = client.query(query, &[])?.into_iter()
+		.map(|row| dangling_rows_sql(row.get("child"), row.get("parent"), &row.get::<_, Vec<String>>("columns"), &row.get::<_, Vec<String>>("referenced_columns")))
+		.collect();
+
+	let mut deleted = 0;
+	loop {
+		let mut deleted_this_pass = 0;
+		for delete in &deletes {
+			deleted_this_pass += client.execute(delete.as_str(), &[])?;
+		}
+		if deleted_this_pass == 0 {
+			return Ok(deleted);
+		}
+		deleted += deleted_this_pass;
+	}
+}
+
+/// `child` and `parent` are already quoted, as `regclass` prints them. rows with a null key column
+/// don't reference anything, like postgres treats them
+fn dangling_rows_sql(child: &str, parent: &str, columns: &[String], referenced_columns: &[String]) -> String {
+	let not_null = columns.iter()
+		.map(|column| format!("child.{} is not null", quote_identifier(column)))
+		.collect::<Vec<_>>()
+		.join(" and ");
+	let matches = columns.iter().zip(referenced_columns)
+		.map(|(column, referenced_column)| format!("parent.{} = child.{}", quote_identifier(referenced_column), quote_identifier(column)))
+		.collect::<Vec<_>>()
+		.join(" and ");
+	format!("delete from {child} child where {not_null} and not exists (select from {parent} parent where {matches})")
+}
+
+#[test]
+fn test_dangling_rows_sql() {
+	assert_eq!(
+		dangling_rows_sql("fruit", "basket", &["basket_id".to_string()], &["id".to_string()]),
+		r#"delete from fruit child where child."basket_id" is not null and not exists (select from basket parent where parent."id" = child."basket_id")"#,
+	);
+}
+
+/// copies up to `rows` rows of every table of `source` into the tables of the temp database `target` with the same name,
+/// and returns how many rows ended up there. tables or columns the temp database doesn't have are skipped.
+/// triggers and foreign keys are turned off while copying if the role is allowed to, and rows that reference rows
+/// that weren't sampled are deleted afterwards, so the migration only sees consistent data
+pub(crate) fn copy_sample(source: &Config, target: &Config, rows: u64) -> Result<u64> {
+	connections::with_client(source, |source_client| {
+		connections::with_client(target, |target_client| {
+			let source_tables = query_tables(source_client)?;
+			let target_tables = query_tables(target_client)?;
+			let replica = target_client.batch_execute("set session_replication_role = replica").is_ok();
+			if !replica {
+				eprintln!("warning: unable to turn off foreign keys while sampling, which needs a superuser, tables that fail them will stay empty");
+			}
+
+			let mut copied = 0;
+			for target_table in &target_tables {
+				let Some(source_table) = source_tables.iter().find(|table| table.schema == target_table.schema && table.name == target_table.name) else { continue };
+				let Some((copy_out, copy_in)) = copy_statements(source_table, target_table, rows) else { continue };
+
+				let mut transaction = target_client.transaction()?;
+				let mut reader = source_client.copy_out(copy_out.as_str())?;
+				let mut writer = transaction.copy_in(copy_in.as_str())?;
+				let outcome = io::copy(&mut reader, &mut writer).map_err(anyhow::Error::from).and_then(|_| Ok(writer.finish()?));
+				match outcome {
+					Ok(count) => {
+						transaction.commit()?;
+						copied += count;
+					},
+					Err(error) if !replica => eprintln!("warning: unable to sample {}: {error}", target_table.qualified_name()),
+					Err(error) => return Err(error).with_context(|| format!("unable to sample {}", target_table.qualified_name())),
+				}
+			}
+
+			let deleted = match replica {
+				true => delete_dangling_rows(target_client)?,
+				false => 0,
+			};
+			target_client.batch_execute("reset session_replication_role")?;
+			Ok(copied - deleted)
+		})
+	})
+}
@@ -0,0 +1,124 @@
+//! Resolves the connection string of `--pg-url-from` from a secrets backend when the command starts.
+
+use std::process::Command;
+use anyhow::{anyhow, Context, Result};
+use postgres::Config;
+
+use super::config_try_from_str;
+
+#[derive(Debug, PartialEq, Eq)]
+enum SecretReference {
+	/// a field of a secret in a key/value engine, `vault:secret/db/prod#url`
+	Vault { path: String, field: String },
+	/// a secret of AWS Secrets Manager, `aws:prod/db#url`, where the key of its json is optional
+	Aws { secret_id: String, key: Option<String> },
+}
+
+fn parse_reference(reference: &str) -> Result<SecretReference> {
+	let (backend, location) = reference.split_once(':')
+		.ok_or_else(|| anyhow!("{reference} should look like vault:<path>#<field> or aws:<secret id>[#<key>]"))?;
+	let (name, key) = match location.rsplit_once('#') {
+		Some((name, key)) => (name.to_string(), Some(key.to_string()).filter(|key| !key.is_empty())),
+		None => (location.to_string(), None),
+	};
+	if name.is_empty() {
+		return Err(anyhow!("{reference} doesn't name a secret"));
+	}
+	match backend {
+		"vault" => Ok(SecretReference::Vault {
+			path: name,
+			field: key.ok_or_else(|| anyhow!("{reference} needs the field of the secret, like vault:{location}#url"))?,
+		}),
+		"aws" => Ok(SecretReference::Aws{ secret_id: name, key }),
+		_ => Err(anyhow!("unknown secrets backend {backend}, use vault or aws")),
+	}
+}
+
+#[test]
+fn test_parse_reference() {
+	assert_eq!(
+		parse_reference("vault:secret/db/prod#url").unwrap(),
+		SecretReference::Vault{ path: "secret/db/prod".into(), field: "url".into() },
+	);
+	assert_eq!(
+		parse_reference("aws:arn:aws:secretsmanager:us-east-1:123:secret:prod/db#url").unwrap(),
+		SecretReference::Aws{ secret_id: "arn:aws:secretsmanager:us-east-1:123:secret:prod/db".into(), key: Some("url".into()) },
+	);
+	assert_eq!(parse_reference("aws:prod/db").unwrap(), SecretReference::Aws{ secret_id: "prod/db".into(), key: None });
+	assert!(parse_reference("vault:secret/db/prod").is_err());
+	assert!(parse_reference("vault:#url").is_err());
+	assert!(parse_reference("gcp:db#url").is_err());
+	assert!(parse_reference("secret/db/prod").is_err());
+}
+
+/// the stdout of `program`, whose errors are the tool's own and don't include the secret
+fn output(program: &str, arguments: &[&str]) -> Result<String> {
+	let output = Command::new(program).args(arguments).output()
+		.with_context(|| format!("unable to run {program}, is it installed?"))?;
+	if !output.status.success() {
+		return Err(anyhow!("{program} {} failed: {}", arguments.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+	}
+	Ok(String::from_utf8(output.stdout).context("the secret isn't valid utf-8")?.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// the connection of a secret's json: the string at `key`, or without a key, the fields of an RDS database secret,
+/// `host`, `port`, `username`, `password` and `dbname`
+fn config_from_json(secret: &str, key: Option<&str>) -> Result<Config> {
+	let json: serde_json::Value = serde_json::from_str(secret).map_err(|_| anyhow!("the secret isn't json"))?;
+	if let Some(key) = key {
+		let url = json.get(key).and_then(|value| value.as_str()).ok_or_else(|| anyhow!("the secret has no string at {key}"))?;
+		return config_try_from_str(url);
+	}
+
+	let field = |name: &str| json.get(name).and_then(|value| match value {
+		serde_json::Value::String(text) => Some(text.clone()),
+		serde_json::Value::Number(number) => Some(number.to_string()),
+		_ => None,
+	});
+	let host = field("host").ok_or_else(|| anyhow!("the secret has no host, give the key of its connection string with #<key>"))?;
+	let mut config = Config::new();
+	config.host(&host);
+	if let Some(port) = field("port") {
+		config.port(port.parse().map_err(|_| anyhow!("the port of the secret isn't a number"))?);
+	}
+	if let Some(user) = field("username") {
+		config.user(&user);
+	}
+	if let Some(password) = field("password") {
+		config.password(password);
+	}
+	if let Some(dbname) = field("dbname") {
+		config.dbname(&dbname);
+	}
+	Ok(config)
+}
+
+#[test]
+fn test_config_from_json() {
+	let config = config_from_json(r#"{"url": "postgres://app@db.internal/app"}"#, Some("url")).unwrap();
+	assert_eq!(config.get_user(), Some("app"));
+	assert!(config_from_json(r#"{"url": 5}"#, Some("url")).is_err());
+
+	let config = config_from_json(r#"{"engine": "postgres", "host": "db.internal", "port": 5433, "username": "app", "password": "hunter2", "dbname": "app"}"#, None).unwrap();
+	assert_eq!(config.get_hosts(), &[postgres::config::Host::Tcp("db.internal".into())]);
+	assert_eq!(config.get_ports(), &[5433]);
+	assert_eq!(config.get_password(), Some(&b"hunter2"[..]));
+	assert_eq!(config.get_dbname(), Some("app"));
+	assert!(config_from_json(r#"{"username": "app"}"#, None).is_err());
+	assert!(config_from_json("postgres://app@db.internal/app", None).is_err());
+}
+
+/// fetches the connection string `reference` points at
+pub(crate) fn resolve_pg_url(reference: &str) -> Result<Config> {
+	let resolved = match parse_reference(reference)? {
+		SecretReference::Vault{path, field} => config_try_from_str(&output("vault", &["kv", "get", &format!("-field={field}"), &path])?),
+		SecretReference::Aws{secret_id, key} => {
+			let secret = output("aws", &["secretsmanager", "get-secret-value", "--secret-id", &secret_id, "--query", "SecretString", "--output", "text"])?;
+			match (key, secret.trim_start().starts_with('{')) {
+				(None, false) => config_try_from_str(&secret),
+				(key, _) => config_from_json(&secret, key.as_deref()),
+			}
+		},
+	};
+	resolved.with_context(|| format!("unable to resolve --pg-url-from {reference}"))
+}
@@ -0,0 +1,139 @@
+use std::{fs, path::{Path, PathBuf}, process::Command};
+use anyhow::{anyhow, Context, Result};
+use sha2::{Digest, Sha256};
+
+use super::signatures::verify_signature;
+
+const RELEASES_URL: &str = "https://github.com/blainehansen/postgres_migrator/releases";
+
+/// name of the prebuilt binary for the platform this binary was built for, for example `postgres_migrator-x86_64-linux`
+fn asset_name() -> String {
+	let extension = if cfg!(windows) { ".exe" } else { "" };
+	format!("postgres_migrator-{}-{}{extension}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+	let mut parts = version.trim_start_matches('v').splitn(3, '.').map(|part| part.parse::<u64>().ok());
+	Some((parts.next()??, parts.next()??, parts.next()??))
+}
+
+/// the hash from a `sha256sum` style checksum file, which may or may not name the file after the hash
+fn parse_checksum_file(contents: &str) -> Option<String> {
+	contents.split_whitespace().next()
+		.filter(|hash| hash.len() == 64 && hash.chars().all(|character| character.is_ascii_hexdigit()))
+		.map(|hash| hash.to_lowercase())
+}
+
+#[test]
+fn test_parse_self_update() {
+	assert_eq!(parse_version("v0.6.7"), Some((0, 6, 7)));
+	assert_eq!(parse_version("1.10.0"), Some((1, 10, 0)));
+	assert!(parse_version("1.10.0") > parse_version("1.9.12"));
+	assert_eq!(parse_version("latest"), None);
+	assert_eq!(parse_version("v1.2"), None);
+
+	let hash = "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08";
+	assert_eq!(parse_checksum_file(&format!("{hash}  postgres_migrator-x86_64-linux\n")), Some(hash.to_lowercase()));
+	assert_eq!(parse_checksum_file(hash), Some(hash.to_lowercase()));
+	assert_eq!(parse_checksum_file("not a checksum"), None);
+	assert_eq!(parse_checksum_file(""), None);
+}
+
+fn curl(arguments: &[&str]) -> Result<String> {
+	let output = Command::new("curl").arg("--fail").arg("--silent").arg("--show-error").arg("--location").args(arguments)
+		.output().context("unable to run curl, which self-update uses to download releases")?;
+	if !output.status.success() {
+		return Err(anyhow!("curl {} failed:\n\n{}", arguments.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+	}
+	Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// github redirects the latest release to its tag, so the tag can be read off the redirect without using the api
+fn query_latest_tag() -> Result<String> {
+	let null_device = if cfg!(windows) { "NUL" } else { "/dev/null" };
+	let latest_url = curl(&["--output", null_device, "--write-out", "%{url_effective}", &format!("{RELEASES_URL}/latest")])?;
+	latest_url.rsplit_once("/tag/")
+		.map(|(_, tag)| tag.trim().to_string())
+		.ok_or_else(|| anyhow!("{RELEASES_URL} has no releases"))
+}
+
+fn download(url: &str, file_path: &Path) -> Result<()> {
+	curl(&["--output", &file_path.to_string_lossy(), url])?;
+	Ok(())
+}
+
+fn with_suffix(file_path: &Path, suffix: &str) -> PathBuf {
+	let mut suffixed = file_path.as_os_str().to_owned();
+	suffixed.push(suffix);
+	PathBuf::from(suffixed)
+}
+
+/// downloads the prebuilt binary of the latest release and verifies it,
+/// against the published checksum and, unless the signature was explicitly skipped, against the published minisign signature
+fn download_verified(tag: &str, download_path: &Path, minisign_public_key_file: Option<&str>) -> Result<()> {
+	let asset_url = format!("{RELEASES_URL}/download/{tag}/{}", asset_name());
+	println!("downloading {asset_url}");
+	download(&asset_url, download_path)
+		.with_context(|| format!("{tag} may not have a prebuilt binary for {}-{}", std::env::consts::ARCH, std::env::consts::OS))?;
+
+	let checksum_file = curl(&[&format!("{asset_url}.sha256")])?;
+	let expected = parse_checksum_file(&checksum_file).ok_or_else(|| anyhow!("{asset_url}.sha256 doesn't contain a sha256 checksum"))?;
+	let actual = format!("{:x}", Sha256::digest(fs::read(download_path)?));
+	if actual != expected {
+		return Err(anyhow!("checksum of {asset_url} is {actual}, but {expected} was published"));
+	}
+
+	match minisign_public_key_file {
+		Some(minisign_public_key_file) => {
+			let signature_path = with_suffix(download_path, ".minisig");
+			let verified = download(&format!("{asset_url}.minisig"), &signature_path)
+				.and_then(|_| verify_signature(download_path, Some(minisign_public_key_file)));
+			let _ = fs::remove_file(&signature_path);
+			verified?;
+		},
+		None => eprintln!("warning: not verifying the signature of {asset_url}, only its checksum, so it isn't known who built it"),
+	}
+	Ok(())
+}
+
+/// moves the verified download over the running binary, which windows only allows after the running binary is moved aside
+fn replace_executable(download_path: &Path, executable_path: &Path) -> Result<()> {
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		fs::set_permissions(download_path, fs::Permissions::from_mode(0o755))?;
+	}
+	if cfg!(windows) {
+		let old_path = with_suffix(executable_path, ".old");
+		let _ = fs::remove_file(&old_path);
+		fs::rename(executable_path, &old_path)?;
+	}
+	fs::rename(download_path, executable_path)
+		.with_context(|| format!("unable to replace {}", executable_path.display()))
+}
+
+pub(crate) fn command_self_update(minisign_public_key_file: Option<&str>, check: bool) -> Result<()> {
+	let current_version = env!("CARGO_PKG_VERSION");
+	let latest_tag = query_latest_tag()?;
+	let latest_version = parse_version(&latest_tag).ok_or_else(|| anyhow!("latest release {latest_tag} isn't a version"))?;
+	if Some(latest_version) <= parse_version(current_version) {
+		println!("{current_version} is the latest version");
+		return Ok(());
+	}
+	if check {
+		println!("{latest_tag} is available, this is {current_version}");
+		return Ok(());
+	}
+
+	let executable_path = std::env::current_exe()?.canonicalize()?;
+	let download_path = executable_path.with_file_name(format!(".{}.download", asset_name()));
+	let updated = download_verified(&latest_tag, &download_path, minisign_public_key_file)
+		.and_then(|_| replace_executable(&download_path, &executable_path));
+	if updated.is_err() {
+		let _ = fs::remove_file(&download_path);
+	}
+	updated?;
+
+	println!("updated {} from {current_version} to {latest_tag}", executable_path.display());
+	Ok(())
+}
@@ -0,0 +1,135 @@
+//! Reads `migrator.toml`, decrypting it with [sops](https://github.com/getsops/sops) when it's encrypted.
+
+use std::{collections::BTreeMap, fs, path::Path, process::Command};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use super::lints::Lints;
+
+pub(crate) const SETTINGS_FILE_NAME: &str = "migrator.toml";
+
+/// the tables of `migrator.toml`. tables without a struct of their own are ignored,
+/// but the keys of the ones that have one have to be known, so a typo doesn't silently turn a setting off
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Settings {
+	pub(crate) lint: Lints,
+	/// pairs of role in the schema and role in the database
+	pub(crate) roles: BTreeMap<String, String>,
+	pub(crate) diff: DiffSettings,
+}
+
+/// the `[diff]` table
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct DiffSettings {
+	pub(crate) ignore_sql: Vec<String>,
+	pub(crate) ignore_sql_files: Vec<String>,
+}
+
+/// whether `contents` is a file sops encrypted as a whole, which is json of the encrypted `data` and sops' own metadata
+fn is_sops_encrypted(contents: &str) -> bool {
+	contents.trim_start().starts_with('{')
+		&& serde_json::from_str::<serde_json::Value>(contents).is_ok_and(|json| json.get("sops").is_some() && json.get("data").is_some())
+}
+
+#[test]
+fn test_is_sops_encrypted() {
+	assert!(is_sops_encrypted(r#"{"data": "ENC[AES256_GCM,data:abc,iv:def,tag:ghi,type:str]", "sops": {"age": [], "version": "3.8.1"}}"#));
+	assert!(!is_sops_encrypted("[roles]\napp = \"app_prod\""));
+	assert!(!is_sops_encrypted(r#"{"data": "plain"}"#));
+}
+
+/// the contents of a settings file at `path`, decrypted with sops if it's encrypted, using whatever key sops finds,
+/// like `SOPS_AGE_KEY_FILE`, a pgp keyring or cloud credentials for a kms
+pub(crate) fn read_file(path: &Path) -> Result<String> {
+	let contents = fs::read_to_string(path).with_context(|| format!("unable to read {}", path.display()))?;
+	if !is_sops_encrypted(&contents) {
+		return Ok(contents);
+	}
+	let output = Command::new("sops").args(["--decrypt", "--input-type", "binary", "--output-type", "binary"]).arg(path).output()
+		.with_context(|| format!("{} is encrypted with sops, which isn't installed", path.display()))?;
+	if !output.status.success() {
+		return Err(anyhow!("unable to decrypt {} with sops: {}", path.display(), String::from_utf8_lossy(&output.stderr).trim()));
+	}
+	String::from_utf8(output.stdout).with_context(|| format!("{} isn't valid utf-8 once decrypted", path.display()))
+}
+
+impl Settings {
+	pub(crate) fn parse(contents: &str) -> Result<Settings> {
+		Ok(toml::from_str(contents)?)
+	}
+
+	/// the settings in `migrator.toml` in the current directory, which are all empty if there isn't one
+	pub(crate) fn read() -> Result<Settings> {
+		let path = Path::new(SETTINGS_FILE_NAME);
+		match path.exists() {
+			true => Settings::parse(&read_file(path)?).with_context(|| format!("invalid {SETTINGS_FILE_NAME}")),
+			false => Ok(Settings::default()),
+		}
+	}
+}
+
+/// every value of a file of flat values rather than settings, like the variables of templates, as text,
+/// with the values of tables by their full dotted key, such as `roles.reader`
+pub(crate) fn parse_texts(contents: &str) -> Result<BTreeMap<String, String>> {
+	fn flatten(prefix: &str, table: toml::Table, texts: &mut BTreeMap<String, String>) -> Result<()> {
+		for (key, value) in table {
+			let key = match prefix.is_empty() {
+				true => key,
+				false => format!("{prefix}.{key}"),
+			};
+			let text = match value {
+				toml::Value::String(text) => text,
+				toml::Value::Integer(integer) => integer.to_string(),
+				toml::Value::Boolean(boolean) => boolean.to_string(),
+				toml::Value::Table(table) => { flatten(&key, table, texts)?; continue },
+				value => return Err(anyhow!("{key} should be a string, integer or boolean, found {value}")),
+			};
+			texts.insert(key, text);
+		}
+		Ok(())
+	}
+
+	let mut texts = BTreeMap::new();
+	flatten("", toml::from_str(contents)?, &mut texts)?;
+	Ok(texts)
+}
+
+#[test]
+fn test_parse_settings() {
+	let settings = Settings::parse(r#"
+		# naming
+		top = 1_000
+		[lint.naming]
+		snake_case = true # comment
+		index_pattern = "ix_{table}_*"
+		table_names = 'plural'
+		exceptions = [
+			"people", # irregular
+			"data",
+		]
+		[roles]
+		app = "app_prod"
+		[diff]
+		ignore_sql = ["drop view pg_stat_statements"]
+	"#).unwrap();
+	assert!(!settings.lint.is_empty());
+	assert_eq!(settings.roles, BTreeMap::from([("app".to_string(), "app_prod".to_string())]));
+	assert_eq!(settings.diff.ignore_sql, vec!["drop view pg_stat_statements".to_string()]);
+	assert!(settings.diff.ignore_sql_files.is_empty());
+
+	assert!(Settings::parse("[lint.naming]\nsnake_cas = true").is_err());
+	assert!(Settings::parse("[lint.naming]\ntable_names = \"many\"").is_err());
+	assert!(Settings::parse("[lint.required]\ncolumns = \"created_at\"").is_err());
+	assert!(Settings::parse("[roles]\napp = 1").is_err());
+	assert!(Settings::parse("a = \"unterminated").is_err());
+	assert!(Settings::parse("a = 1\na = 2").is_err());
+
+	assert_eq!(parse_texts("app_role = 'app_prod'\nfillfactor = 70\n[roles]\nreader = \"analyst\"").unwrap(), BTreeMap::from([
+		("app_role".to_string(), "app_prod".to_string()),
+		("fillfactor".to_string(), "70".to_string()),
+		("roles.reader".to_string(), "analyst".to_string()),
+	]));
+	assert!(parse_texts("replicas = [1, 2]").is_err());
+}
@@ -0,0 +1,81 @@
+use std::{path::{Path, PathBuf}, process::Command};
+use anyhow::{anyhow, Context, Result};
+
+/// a detached signature next to a migration
+#[derive(Debug, PartialEq, Eq)]
+enum Signature {
+	/// `<migration>.minisig`
+	Minisign(PathBuf),
+	/// `<migration>.sig` or `<migration>.asc`
+	Gpg(PathBuf),
+}
+
+fn signature_path(file_path: &Path, extension: &str) -> PathBuf {
+	let mut signature_path = file_path.as_os_str().to_owned();
+	signature_path.push(format!(".{extension}"));
+	PathBuf::from(signature_path)
+}
+
+fn find_signature(file_path: &Path) -> Option<Signature> {
+	let minisign_path = signature_path(file_path, "minisig");
+	if minisign_path.exists() {
+		return Some(Signature::Minisign(minisign_path));
+	}
+	["sig", "asc"].iter()
+		.map(|extension| signature_path(file_path, extension))
+		.find(|gpg_path| gpg_path.exists())
+		.map(Signature::Gpg)
+}
+
+#[test]
+#[serial_test::serial]
+fn test_find_signature() -> Result<()> {
+	use super::{DEFAULT_MIGRATIONS_DIRECTORY, ensure_directory, purge_directory};
+	purge_directory(DEFAULT_MIGRATIONS_DIRECTORY)?;
+	ensure_directory(DEFAULT_MIGRATIONS_DIRECTORY)?;
+
+	let unsigned = Path::new("migrations/20220101000000.null.sql");
+	std::fs::File::create(unsigned)?;
+	assert_eq!(find_signature(unsigned), None);
+
+	let gpg_signed = Path::new("migrations/20220102000000.20220101000000.sql");
+	std::fs::File::create(gpg_signed)?;
+	std::fs::File::create("migrations/20220102000000.20220101000000.sql.asc")?;
+	assert_eq!(find_signature(gpg_signed), Some(Signature::Gpg(PathBuf::from("migrations/20220102000000.20220101000000.sql.asc"))));
+
+	let minisign_signed = Path::new("migrations/20220103000000.20220102000000.sql");
+	std::fs::File::create(minisign_signed)?;
+	std::fs::File::create("migrations/20220103000000.20220102000000.sql.sig")?;
+	std::fs::File::create("migrations/20220103000000.20220102000000.sql.minisig")?;
+	assert_eq!(find_signature(minisign_signed), Some(Signature::Minisign(PathBuf::from("migrations/20220103000000.20220102000000.sql.minisig"))));
+
+	purge_directory(DEFAULT_MIGRATIONS_DIRECTORY)?;
+	Ok(())
+}
+
+/// verifies `file_path` against its detached signature,
+/// using `minisign_public_key_file` for minisign signatures and the default gpg keyring for gpg signatures
+pub(crate) fn verify_signature(file_path: &Path, minisign_public_key_file: Option<&str>) -> Result<()> {
+	let display_file_path = file_path.display();
+	let mut command = match find_signature(file_path) {
+		None => return Err(anyhow!("{display_file_path} isn't signed")),
+		Some(Signature::Minisign(signature_path)) => {
+			let minisign_public_key_file = minisign_public_key_file
+				.ok_or_else(|| anyhow!("{display_file_path} has a minisign signature, but no --minisign-public-key was given"))?;
+			let mut command = Command::new("minisign");
+			command.arg("-V").arg("-q").arg("-p").arg(minisign_public_key_file).arg("-m").arg(file_path).arg("-x").arg(signature_path);
+			command
+		},
+		Some(Signature::Gpg(signature_path)) => {
+			let mut command = Command::new("gpg");
+			command.arg("--batch").arg("--verify").arg(signature_path).arg(file_path);
+			command
+		},
+	};
+
+	let output = command.output().with_context(|| format!("unable to run {:?} to verify {display_file_path}", command.get_program()))?;
+	if !output.status.success() {
+		return Err(anyhow!("signature of {display_file_path} is invalid:\n\n{}", String::from_utf8_lossy(&output.stderr).trim()));
+	}
+	Ok(())
+}
@@ -0,0 +1,161 @@
+use anyhow::{anyhow, Result};
+
+use super::{Args, connections, gather_validated_migrations, list_sql_files, quote_identifier, read_sql_file, templates};
+
+/// one of the two schemas a blue-green deployment alternates between
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ArgEnum)]
+pub(crate) enum Slot {
+	Blue,
+	Green,
+}
+
+impl Slot {
+	fn name(&self) -> &'static str {
+		match self {
+			Slot::Blue => "blue",
+			Slot::Green => "green",
+		}
+	}
+
+	fn schema(&self) -> String {
+		format!("app_{}", self.name())
+	}
+}
+
+#[test]
+fn test_slot_names() {
+	assert_eq!((Slot::Blue.name(), Slot::Blue.schema()), ("blue", "app_blue".to_string()));
+	assert_eq!((Slot::Green.name(), Slot::Green.schema()), ("green", "app_green".to_string()));
+}
+
+pub(crate) const SLOTS_TABLE: &str = "_schema_slots";
+
+// always qualified, since a promoted slot's schema comes first in the search_path
+pub(crate) const SLOTS_TABLE_SQL: &str = "
+	create table if not exists public._schema_slots (
+		slot text primary key,
+		schema_name text not null,
+		-- the last migration version when the slot was built
+		version char(14),
+		built_at timestamptz not null default now(),
+		promoted_at timestamptz,
+		is_active boolean not null default false
+	);
+";
+
+fn query_active_slot(client: &mut impl postgres::GenericClient) -> Result<Option<String>> {
+	client.batch_execute(SLOTS_TABLE_SQL)?;
+	Ok(client.query_opt("select slot from public._schema_slots where is_active", &[])?.map(|row| row.get("slot")))
+}
+
+#[test]
+#[ignore]
+fn test_query_active_slot() -> Result<()> {
+	let mut client = std::env::var("PG_URL")?.parse::<postgres::Config>()?.connect(postgres::NoTls)?;
+	// rolled back when dropped, so an existing _schema_slots is left alone
+	let mut transaction = client.transaction()?;
+	transaction.batch_execute("drop table if exists public._schema_slots")?;
+	assert_eq!(query_active_slot(&mut transaction)?, None);
+
+	transaction.batch_execute("insert into public._schema_slots (slot, schema_name) values ('blue', 'app_blue'), ('green', 'app_green')")?;
+	assert_eq!(query_active_slot(&mut transaction)?, None);
+	transaction.batch_execute("update public._schema_slots set is_active = slot = 'green'")?;
+	assert_eq!(query_active_slot(&mut transaction)?.as_deref(), Some("green"));
+	Ok(())
+}
+
+/// rebuilds the inactive `slot` schema from the schema directory, leaving the active one untouched
+pub(crate) fn build_slot(args: &Args, client: &mut postgres::Client, slot: Slot) -> Result<()> {
+	let (_, version) = gather_validated_migrations(args)?;
+	let schema = slot.schema();
+
+	let mut transaction = client.transaction()?;
+	if query_active_slot(&mut transaction)?.as_deref() == Some(slot.name()) {
+		return Err(anyhow!("{} is the active slot, build the other one and promote it instead", slot.name()));
+	}
+
+	println!("building {schema} from {}", args.schema_directory);
+	transaction.batch_execute(&format!("
+		drop schema if exists {schema} cascade;
+		create schema {schema};
+		set local search_path to {schema}, public;
+	"))?;
+	for sql_file in list_sql_files(&args.schema_directory)? {
+		transaction.batch_execute(&templates::render(&read_sql_file(&sql_file)?)?)
+			.map_err(|err| anyhow!("unable to apply {} to {schema}: {err}", sql_file.display()))?;
+	}
+	transaction.execute(
+		"insert into public._schema_slots (slot, schema_name, version) values ($1, $2, $3)
+		on conflict (slot) do update set schema_name = excluded.schema_name, version = excluded.version, built_at = now()",
+		&[&slot.name(), &schema, &version],
+	)?;
+	transaction.commit()?;
+
+	println!("built {schema}, run `promote {}` to switch to it", slot.name());
+	Ok(())
+}
+
+/// a slot built before the latest migration would roll the application back to an older schema
+fn ensure_current(slot: Slot, built_version: Option<&str>, latest_version: Option<&str>) -> Result<()> {
+	if built_version != latest_version {
+		return Err(anyhow!(
+			"{} was built at {}, but the latest migration is {}, run `migrate --slot {}` again first",
+			slot.name(), built_version.unwrap_or("no migration"), latest_version.unwrap_or("none"), slot.name(),
+		));
+	}
+	Ok(())
+}
+
+fn ensure_application_roles(roles: &[String], current_user: &str) -> Result<()> {
+	match roles.iter().find(|role| *role == current_user) {
+		// the bookkeeping tables postgres_migrator creates would end up in the slot if its own search_path changed
+		Some(role) => Err(anyhow!("{role} is the role postgres_migrator connects as, only promote for the roles your application connects as")),
+		None => Ok(()),
+	}
+}
+
+#[test]
+fn test_promote_checks() {
+	assert!(ensure_current(Slot::Blue, Some("20220102000000"), Some("20220102000000")).is_ok());
+	assert!(ensure_current(Slot::Blue, None, None).is_ok());
+	assert_eq!(
+		ensure_current(Slot::Green, Some("20220101000000"), Some("20220102000000")).unwrap_err().to_string(),
+		"green was built at 20220101000000, but the latest migration is 20220102000000, run `migrate --slot green` again first",
+	);
+	assert!(ensure_current(Slot::Green, None, Some("20220102000000")).is_err());
+
+	let roles = vec!["app".to_string(), "reporting".to_string()];
+	assert!(ensure_application_roles(&roles, "migrator").is_ok());
+	assert!(ensure_application_roles(&roles, "reporting").unwrap_err().to_string().starts_with("reporting is the role postgres_migrator connects as"));
+}
+
+/// points the default `search_path` of `roles` at `slot`, and marks it as the active slot
+pub(crate) fn command_promote(args: &Args, slot: Slot, roles: &[String]) -> Result<()> {
+	let dbname = args.pg_url.get_dbname().ok_or_else(|| anyhow!("need a dbname to promote a slot"))?;
+	let (_, latest_version) = gather_validated_migrations(args)?;
+	let mut client = connections::connect(&args.pg_url)?;
+	let schema = slot.schema();
+
+	let mut transaction = client.transaction()?;
+	query_active_slot(&mut transaction)?;
+	let built_version: Option<String> = transaction.query_opt("select version from public._schema_slots where slot = $1", &[&slot.name()])?
+		.ok_or_else(|| anyhow!("{} hasn't been built, run `migrate --slot {}` first", slot.name(), slot.name()))?
+		.get("version");
+	ensure_current(slot, built_version.as_deref(), latest_version.as_deref())?;
+	let current_user: String = transaction.query_one("select current_user::text as current_user", &[])?.get("current_user");
+	ensure_application_roles(roles, &current_user)?;
+	for role in roles {
+		transaction.batch_execute(&format!(
+			"alter role {} in database {} set search_path to {schema}, public",
+			quote_identifier(role), quote_identifier(dbname),
+		))?;
+	}
+	transaction.execute(
+		"update public._schema_slots set is_active = slot = $1, promoted_at = case when slot = $1 then now() else promoted_at end",
+		&[&slot.name()],
+	)?;
+	transaction.commit()?;
+
+	println!("promoted {schema}, new connections of {} will use it", roles.join(", "));
+	Ok(())
+}
@@ -0,0 +1,287 @@
+//! Applies embedded migrations when a service boots, safely when several instances of it boot at once.
+
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Context, Result};
+use postgres::{Client, Config, NoTls, error::SqlState};
+use regex::Regex;
+
+use super::{Migration, VERSIONS_TABLE_SQL};
+
+/// the default key of the advisory lock, "postmigr" in ascii
+pub const DEFAULT_LOCK_KEY: i64 = 0x706f_7374_6d69_6772;
+
+/// how long to sleep between attempts to connect or to take the lock
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone)]
+pub struct StartupConfig {
+	pub database: Config,
+	/// how long to keep trying to connect while the database is starting up
+	pub database_timeout: Duration,
+	/// how long to wait for another instance that holds the lock to finish migrating
+	pub lock_timeout: Duration,
+	/// the key of the session level advisory lock, which every instance of a service has to share
+	pub lock_key: i64,
+}
+
+impl StartupConfig {
+	/// waits a minute for the database and five for the lock
+	pub fn new(database: Config) -> StartupConfig {
+		StartupConfig {
+			database,
+			database_timeout: Duration::from_secs(60),
+			lock_timeout: Duration::from_secs(300),
+			lock_key: DEFAULT_LOCK_KEY,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AppliedMigration {
+	pub version: String,
+	pub description: String,
+	/// how long the migration took, including recording it
+	pub duration: Duration,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StartupReport {
+	/// the version the database was at once the lock was taken, `None` if it had never been migrated
+	pub initial_version: Option<String>,
+	/// the migrations that were applied, in order
+	pub applied: Vec<AppliedMigration>,
+	/// the version of an onboard migration that was only recorded, since it describes a schema the database already has
+	pub recorded_onboard: Option<String>,
+	/// versions the database has that are newer than every migration of this service,
+	/// applied by a newer release of it, for example while a rolling deploy is underway
+	pub newer_versions: Vec<String>,
+	pub waited_for_database: Duration,
+	pub waited_for_lock: Duration,
+}
+
+impl StartupReport {
+	/// the version the database is at now
+	pub fn current_version(&self) -> Option<&str> {
+		self.newer_versions.last()
+			.or(self.applied.last().map(|applied| &applied.version))
+			.or(self.recorded_onboard.as_ref())
+			.or(self.initial_version.as_ref())
+			.map(String::as_str)
+	}
+}
+
+pub struct Migrator {
+	migrations: Vec<Migration>,
+}
+
+/// what has to happen to bring a database with `applied_versions` up to date with `migrations`
+#[derive(Debug, Eq, PartialEq)]
+struct Plan<'a> {
+	pending: &'a [Migration],
+	newer_versions: Vec<String>,
+}
+
+/// applied versions older than the newest migration that aren't migrations were removed from the chain or come from another one,
+/// which `migrate` refuses too, while newer ones come from a newer release
+fn plan<'a>(migrations: &'a [Migration], applied_versions: &[String]) -> Result<Plan<'a>> {
+	let newest_version = migrations.last().map(|migration| migration.version.as_str()).unwrap_or_default();
+	let (newer_versions, older_versions): (Vec<_>, Vec<_>) = applied_versions.iter().cloned()
+		.partition(|applied_version| applied_version.as_str() > newest_version);
+	let missing_versions = older_versions.iter()
+		.filter(|applied_version| !migrations.iter().any(|migration| &migration.version == *applied_version))
+		.cloned()
+		.collect::<Vec<_>>();
+	if !missing_versions.is_empty() {
+		return Err(anyhow!("these versions have been applied to the database, but aren't migrations: {}", missing_versions.join(", ")));
+	}
+
+	let pending_index = match (newer_versions.is_empty(), older_versions.last()) {
+		(false, _) => migrations.len(),
+		(true, None) => 0,
+		(true, Some(actual_version)) => migrations.iter().position(|migration| &migration.version > actual_version).unwrap_or(migrations.len()),
+	};
+	Ok(Plan{ pending: &migrations[pending_index..], newer_versions })
+}
+
+#[test]
+fn test_plan() {
+	let migrations = super::migrations_from_sources([
+		("20220101000000.null.create_fruit.sql", "create table fruit ();"),
+		("20220102000000.20220101000000.add_color.sql", "alter table fruit add color text;"),
+	]).unwrap();
+	let versions = |versions: &[&str]| versions.iter().map(|version| version.to_string()).collect::<Vec<_>>();
+
+	assert_eq!(plan(&migrations, &[]).unwrap(), Plan{ pending: &migrations[..], newer_versions: vec![] });
+	assert_eq!(plan(&migrations, &versions(&["20220101000000"])).unwrap(), Plan{ pending: &migrations[1..], newer_versions: vec![] });
+	assert_eq!(plan(&migrations, &versions(&["20220101000000", "20220102000000"])).unwrap(), Plan{ pending: &[], newer_versions: vec![] });
+	assert_eq!(
+		plan(&migrations, &versions(&["20220101000000", "20220102000000", "20220103000000"])).unwrap(),
+		Plan{ pending: &[], newer_versions: versions(&["20220103000000"]) },
+	);
+	assert!(plan(&migrations, &versions(&["20211231000000", "20220101000000"])).is_err());
+	assert!(plan(&migrations[..1], &versions(&["20220101000000", "20220101120000"])).unwrap().pending.is_empty());
+}
+
+/// authentication failures won't go away by waiting, anything else might while the database starts up
+fn is_authentication_failure(code: Option<&SqlState>) -> bool {
+	code.is_some_and(|code| code.code().starts_with("28"))
+}
+
+#[test]
+fn test_is_authentication_failure() {
+	assert!(is_authentication_failure(Some(&SqlState::INVALID_PASSWORD)));
+	assert!(is_authentication_failure(Some(&SqlState::INVALID_AUTHORIZATION_SPECIFICATION)));
+	// the database is still starting up, or the connection was refused before postgres answered
+	assert!(!is_authentication_failure(Some(&SqlState::CANNOT_CONNECT_NOW)));
+	assert!(!is_authentication_failure(Some(&SqlState::TOO_MANY_CONNECTIONS)));
+	assert!(!is_authentication_failure(None));
+}
+
+fn wait_for_database(config: &StartupConfig) -> Result<(Client, Duration)> {
+	let started = Instant::now();
+	loop {
+		match config.database.connect(NoTls) {
+			Ok(client) => return Ok((client, started.elapsed())),
+			Err(error) if is_authentication_failure(error.code()) => return Err(error.into()),
+			Err(error) if started.elapsed() > config.database_timeout => {
+				return Err(anyhow::Error::from(error).context(format!("the database didn't accept connections within {:?}", config.database_timeout)));
+			},
+			Err(_) => std::thread::sleep(POLL_INTERVAL),
+		}
+	}
+}
+
+fn wait_for_lock(client: &mut Client, config: &StartupConfig) -> Result<Duration> {
+	let started = Instant::now();
+	loop {
+		let locked: bool = client.query_one("select pg_try_advisory_lock($1) as locked", &[&config.lock_key])?.get("locked");
+		if locked {
+			return Ok(started.elapsed());
+		}
+		if started.elapsed() > config.lock_timeout {
+			return Err(anyhow!("another instance held the migration lock for longer than {:?}", config.lock_timeout));
+		}
+		std::thread::sleep(POLL_INTERVAL);
+	}
+}
+
+#[test]
+fn test_wait_for_database_timeout() {
+	let mut database = Config::new();
+	// nothing listens on port 1, so every attempt is refused right away
+	database.host("localhost").port(1).user("postgres").connect_timeout(Duration::from_secs(1));
+	let config = StartupConfig{ database_timeout: Duration::from_millis(600), ..StartupConfig::new(database) };
+
+	let error = wait_for_database(&config).err().unwrap();
+	assert!(error.to_string().starts_with("the database didn't accept connections within"));
+}
+
+#[test]
+#[ignore]
+fn test_wait_for_lock_timeout() -> Result<()> {
+	let database = std::env::var("PG_URL")?.parse::<Config>()?;
+	let config = StartupConfig{ lock_timeout: Duration::from_millis(600), lock_key: DEFAULT_LOCK_KEY - 1, ..StartupConfig::new(database.clone()) };
+	let (mut holder, mut waiter) = (database.connect(NoTls)?, database.connect(NoTls)?);
+
+	wait_for_lock(&mut holder, &config)?;
+	assert_eq!(
+		wait_for_lock(&mut waiter, &config).unwrap_err().to_string(),
+		"another instance held the migration lock for longer than 600ms",
+	);
+	holder.execute("select pg_advisory_unlock($1)", &[&config.lock_key])?;
+	wait_for_lock(&mut waiter, &config)?;
+	Ok(())
+}
+
+fn query_applied_versions(client: &mut Client) -> Result<Vec<String>> {
+	let exists: bool = client.query_one("select to_regclass('_schema_versions') is not null as exists", &[])?.get("exists");
+	if !exists {
+		return Ok(vec![]);
+	}
+	let started_version = client.query_opt("
+		select current_version from _schema_versions
+		where (to_jsonb(_schema_versions) ->> 'phase') = 'started'
+	", &[])?;
+	if let Some(row) = started_version {
+		return Err(anyhow!("migration {} has been started, run `migrate complete` or `migrate rollback` first", row.get::<_, String>("current_version")));
+	}
+	Ok(client.query("select current_version from _schema_versions order by current_version", &[])?
+		.into_iter()
+		.map(|row| row.get("current_version"))
+		.collect())
+}
+
+/// creates `_schema_versions` if need be, runs `sql` unless it's `None`, and records `migration`, all in one transaction
+fn apply_migration(client: &mut Client, migration: &Migration, sql: Option<&str>) -> Result<()> {
+	let mut transaction = client.transaction()?;
+	let exists: bool = transaction.query_one("select to_regclass('_schema_versions') is not null as exists", &[])?.get("exists");
+	if !exists {
+		transaction.batch_execute(VERSIONS_TABLE_SQL)?;
+	}
+	if let Some(sql) = sql {
+		transaction.batch_execute(sql)?;
+	}
+	transaction.execute(
+		"insert into _schema_versions (current_version, previous_version) values ($1, $2)",
+		&[&migration.version, &migration.previous_version],
+	)?;
+	transaction.commit()?;
+	Ok(())
+}
+
+impl Migrator {
+	/// `migrations` are usually from [`migrations_from_sources`](super::migrations_from_sources), embedded at compile time
+	pub fn new(migrations: Vec<Migration>) -> Migrator {
+		Migrator{ migrations }
+	}
+
+	/// waits for the database, takes the advisory lock, and applies the pending migrations each in its own transaction,
+	/// stopping at the first one that fails. the lock is released once it's done, and instances that waited for it
+	/// find nothing left to apply.
+	///
+	/// an onboard migration is only recorded, like `migrate` does without `--actually-perform-onboard-migrations`.
+	/// phased migrations and migrations that load data with `copy ... from stdin` have to be applied with the binary
+	pub fn migrate_on_startup(&self, config: &StartupConfig) -> Result<StartupReport> {
+		let (mut client, waited_for_database) = wait_for_database(config)?;
+		let waited_for_lock = wait_for_lock(&mut client, config)?;
+		let outcome = self.migrate(&mut client, waited_for_database, waited_for_lock);
+		// the lock would also go away with the connection, but the client could outlive this in a pool someday
+		let unlocked = client.execute("select pg_advisory_unlock($1)", &[&config.lock_key]);
+		let report = outcome?;
+		unlocked?;
+		Ok(report)
+	}
+
+	fn migrate(&self, client: &mut Client, waited_for_database: Duration, waited_for_lock: Duration) -> Result<StartupReport> {
+		let applied_versions = query_applied_versions(client)?;
+		let Plan{pending, newer_versions} = plan(&self.migrations, &applied_versions)?;
+		let mut report = StartupReport {
+			initial_version: applied_versions.last().cloned(),
+			applied: vec![], recorded_onboard: None, newer_versions,
+			waited_for_database, waited_for_lock,
+		};
+
+		let phase_marker = Regex::new(r"(?im)^--\s*phase:").unwrap();
+		for migration in pending {
+			let name = format!("{}.{}", migration.version, migration.description);
+			if migration.is_onboard {
+				apply_migration(client, migration, None).with_context(|| format!("unable to record {name}"))?;
+				report.recorded_onboard = Some(migration.version.clone());
+				continue;
+			}
+			if phase_marker.is_match(&migration.sql) {
+				return Err(anyhow!("{name} is a phased migration, which has to be applied with `postgres_migrator migrate start`"));
+			}
+
+			let started = Instant::now();
+			apply_migration(client, migration, Some(&migration.sql))
+				.with_context(|| format!("unable to apply {name}, {} migrations were applied before it", report.applied.len()))?;
+			report.applied.push(AppliedMigration {
+				version: migration.version.clone(),
+				description: migration.description.clone(),
+				duration: started.elapsed(),
+			});
+		}
+		Ok(report)
+	}
+}
@@ -0,0 +1,302 @@
+use std::{fs, io::{self, Write}, path::Path};
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+
+use super::{connections, structure};
+
+/// a single sql statement of a migration, without its terminating semicolon
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Statement {
+	pub(crate) sql: String,
+	/// 1-based line the statement starts on
+	pub(crate) line: usize,
+	/// the rows of a `copy ... from stdin`, which are streamed to the server after it
+	pub(crate) copy: Option<CopyData>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum CopyData {
+	/// the lines after the statement, up to a `\.` line, like `pg_dump` writes them
+	Inline(String),
+	/// the file of a `\copy ... from 'file'`, relative to the sql file
+	File(String),
+}
+
+/// a `copy ... from stdin` statement, whose rows follow it
+fn is_copy_from_stdin(sql: &str) -> bool {
+	Regex::new(r"(?is)^copy\s.+\sfrom\s+stdin\b").unwrap().is_match(sql)
+}
+
+/// the `copy ... from stdin` statement and the file of a psql `\copy ... from 'file'` line
+fn parse_backslash_copy(line: &str) -> Option<(String, String)> {
+	let backslash_copy = Regex::new(r"(?is)^\\copy\s+(.+?)\s+from\s+'((?:[^']|'')*)'\s*(.*?)\s*;?\s*$").unwrap();
+	let captures = backslash_copy.captures(line.trim())?;
+	let options = match &captures[3] {
+		"" => String::new(),
+		options => format!(" {options}"),
+	};
+	Some((format!("copy {} from stdin{options}", &captures[1]), captures[2].replace("''", "'")))
+}
+
+/// where the splitter is inside the sql, quotes and comments can contain semicolons that don't end statements
+enum State {
+	Code,
+	LineComment,
+	BlockComment(usize),
+	SingleQuoted { backslash_escapes: bool },
+	DoubleQuoted,
+	DollarQuoted(String),
+}
+
+/// the `$tag$` starting at the beginning of `rest`, if there is one
+fn dollar_quote_tag(rest: &str) -> Option<&str> {
+	let end = rest[1..].find('$')? + 2;
+	let tag = &rest[..end];
+	let is_valid = tag[1..end - 1].chars().enumerate()
+		.all(|(index, character)| character == '_' || character.is_alphabetic() || (index > 0 && character.is_ascii_digit()));
+	is_valid.then_some(tag)
+}
+
+/// splits sql into its statements, understanding quotes, quoted identifiers, comments and dollar quoted bodies,
+/// and leaving out statements that consist only of whitespace and comments.
+/// the rows after a `copy ... from stdin`, and `\copy ... from 'file'` lines, become the `copy` of their statement
+pub(crate) fn split_statements(sql: &str) -> Result<Vec<Statement>> {
+	let mut statements = vec![];
+	let mut state = State::Code;
+	let mut line = 1;
+	let mut start: Option<(usize, usize)> = None;
+	let mut index = 0;
+
+	while index < sql.len() {
+		let rest = &sql[index..];
+		let character = rest.chars().next().unwrap();
+		let mut advance = character.len_utf8();
+
+		match state {
+			State::Code => match character {
+				';' => {
+					if let Some((start_index, start_line)) = start.take() {
+						let statement_sql = sql[start_index..index].trim_end().to_string();
+						let copy = match is_copy_from_stdin(&statement_sql) {
+							false => None,
+							true => {
+								// the rows start on the line after the statement, and end at a line that's only `\.`
+								let rows_start = rest.find('\n').map_or(sql.len(), |newline| index + newline + 1);
+								let rows_length = sql[rows_start..].split_inclusive('\n')
+									.take_while(|row| row.trim_end() != "\\.")
+									.map(str::len)
+									.sum::<usize>();
+								let end_marker = &sql[rows_start + rows_length..];
+								if end_marker.is_empty() {
+									return Err(anyhow!("the rows of the copy starting on line {start_line} have no \\. line after them"));
+								}
+								advance = rows_start + rows_length + end_marker.find('\n').map_or(end_marker.len(), |newline| newline + 1) - index;
+								Some(CopyData::Inline(sql[rows_start..rows_start + rows_length].to_string()))
+							},
+						};
+						statements.push(Statement{ sql: statement_sql, line: start_line, copy });
+					}
+				},
+				'\\' if start.is_none() && rest.get(..5).is_some_and(|command| command.eq_ignore_ascii_case("\\copy")) => {
+					let copy_line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+					let (copy_sql, file) = parse_backslash_copy(copy_line)
+						.ok_or_else(|| anyhow!("line {line}: only `\\copy <table> from '<file>'` is supported, found {copy_line}"))?;
+					statements.push(Statement{ sql: copy_sql, line, copy: Some(CopyData::File(file)) });
+					advance = copy_line.len();
+				},
+				_ if rest.starts_with("--") => { state = State::LineComment; advance = 2; },
+				_ if rest.starts_with("/*") => { state = State::BlockComment(1); advance = 2; },
+				_ if character.is_whitespace() => {},
+				_ => {
+					if start.is_none() {
+						start = Some((index, line));
+					}
+					let previous = sql[..index].chars().next_back();
+					let is_escape_string = matches!(previous, Some('e' | 'E'))
+						&& !sql[..index - 1].chars().next_back().is_some_and(|before| before.is_alphanumeric() || before == '_');
+					match character {
+						'\'' => state = State::SingleQuoted{ backslash_escapes: is_escape_string },
+						'"' => state = State::DoubleQuoted,
+						// a `$` right after an identifier character is part of the identifier, not a dollar quote
+						'$' if !previous.is_some_and(|previous| previous.is_alphanumeric() || previous == '_') => {
+							if let Some(tag) = dollar_quote_tag(rest) {
+								advance = tag.len();
+								state = State::DollarQuoted(tag.to_string());
+							}
+						},
+						_ => {},
+					}
+				},
+			},
+			State::LineComment => {
+				if character == '\n' {
+					state = State::Code;
+				}
+			},
+			State::BlockComment(depth) => {
+				if rest.starts_with("*/") {
+					advance = 2;
+					state = if depth == 1 { State::Code } else { State::BlockComment(depth - 1) };
+				}
+				else if rest.starts_with("/*") {
+					advance = 2;
+					state = State::BlockComment(depth + 1);
+				}
+			},
+			State::SingleQuoted{ backslash_escapes } => {
+				if backslash_escapes && character == '\\' {
+					advance += rest[1..].chars().next().map_or(0, char::len_utf8);
+				}
+				else if rest.starts_with("''") {
+					advance = 2;
+				}
+				else if character == '\'' {
+					state = State::Code;
+				}
+			},
+			State::DoubleQuoted => {
+				if rest.starts_with("\"\"") {
+					advance = 2;
+				}
+				else if character == '"' {
+					state = State::Code;
+				}
+			},
+			State::DollarQuoted(ref tag) => {
+				if rest.starts_with(tag.as_str()) {
+					advance = tag.len();
+					state = State::Code;
+				}
+			},
+		}
+
+		line += rest[..advance].matches('\n').count();
+		index += advance;
+	}
+
+	let unterminated = match state {
+		State::Code | State::LineComment => None,
+		State::BlockComment(_) => Some("block comment".to_string()),
+		State::SingleQuoted{..} => Some("string".to_string()),
+		State::DoubleQuoted => Some("quoted identifier".to_string()),
+		State::DollarQuoted(tag) => Some(format!("dollar quote {tag}")),
+	};
+	if let Some(unterminated) = unterminated {
+		let start_line = start.map_or(line, |(_, start_line)| start_line);
+		return Err(anyhow!("unterminated {unterminated} in statement starting on line {start_line}"));
+	}
+
+	if let Some((start_index, start_line)) = start {
+		statements.push(Statement{ sql: sql[start_index..].trim_end().to_string(), line: start_line, copy: None });
+	}
+	Ok(statements)
+}
+
+#[test]
+fn test_split_statements() {
+	let split = |sql: &str| split_statements(sql).unwrap().into_iter().map(|statement| (statement.line, statement.sql)).collect::<Vec<_>>();
+
+	assert_eq!(split(""), vec![]);
+	assert_eq!(split("  -- nothing here\n/* or here */;\n"), vec![]);
+	assert_eq!(
+		split("create table fruit ();\n\n-- a comment; with a semicolon\ncreate table vegetable ()"),
+		vec![(1, "create table fruit ()".to_string()), (4, "create table vegetable ()".to_string())],
+	);
+	assert_eq!(
+		split("insert into fruit values ('a;b', 'it''s', E'\\';', \"weird;name\");"),
+		vec![(1, "insert into fruit values ('a;b', 'it''s', E'\\';', \"weird;name\")".to_string())],
+	);
+	assert_eq!(
+		split("create function f() returns int as $body$\n\tselect 1; $$ not the end $$;\n$body$ language sql;\nselect $1, a$b;"),
+		vec![
+			(1, "create function f() returns int as $body$\n\tselect 1; $$ not the end $$;\n$body$ language sql".to_string()),
+			(4, "select $1, a$b".to_string()),
+		],
+	);
+	assert_eq!(split("/* outer /* inner; */ still; */ select 1;"), vec![(1, "select 1".to_string())]);
+
+	let copies = split_statements("copy fruit (id, name) from stdin;\n1\tapple\n2\tit's; fine\n\\.\nselect 1;\n\\copy vegetable from 'vegetables.csv' with (format csv)\nselect 2;").unwrap();
+	assert_eq!(copies, vec![
+		Statement{ sql: "copy fruit (id, name) from stdin".to_string(), line: 1, copy: Some(CopyData::Inline("1\tapple\n2\tit's; fine\n".to_string())) },
+		Statement{ sql: "select 1".to_string(), line: 5, copy: None },
+		Statement{ sql: "copy vegetable from stdin with (format csv)".to_string(), line: 6, copy: Some(CopyData::File("vegetables.csv".to_string())) },
+		Statement{ sql: "select 2".to_string(), line: 7, copy: None },
+	]);
+	assert!(split_statements("copy fruit from stdin;\n1\tapple\n").is_err());
+	assert!(split_statements("\\copy fruit to 'fruit.csv'").is_err());
+
+	assert_eq!(split_statements("select 'oops;").unwrap_err().to_string(), "unterminated string in statement starting on line 1");
+	assert_eq!(
+		split_statements("select 1;\ndo $$ begin\nend;").unwrap_err().to_string(),
+		"unterminated dollar quote $$ in statement starting on line 2",
+	);
+}
+
+/// the 1-based line of the file a database error points at, given the statement it happened in
+fn error_line(statement: &Statement, err: &postgres::Error) -> usize {
+	let position = match err.as_db_error().and_then(|db_error| db_error.position()) {
+		Some(postgres::error::ErrorPosition::Original(position)) => *position as usize,
+		_ => return statement.line,
+	};
+	statement.line + statement.sql.chars().take(position.saturating_sub(1)).filter(|character| *character == '\n').count()
+}
+
+/// runs `statement`, streaming its rows if it's a copy
+fn execute_statement(client: &mut impl postgres::GenericClient, statement: &Statement, file: Option<fs::File>) -> Result<(), postgres::Error> {
+	let Some(copy) = &statement.copy else {
+		return client.batch_execute(&statement.sql);
+	};
+	let mut writer = client.copy_in(statement.sql.as_str())?;
+	// writing only fails once the server has failed the copy, which finishing then reports
+	let _ = match (copy, file) {
+		(_, Some(mut file)) => io::copy(&mut file, &mut writer).map(drop),
+		(CopyData::Inline(rows), None) => writer.write_all(rows.as_bytes()),
+		(CopyData::File(_), None) => Ok(()),
+	};
+	writer.finish().map(drop)
+}
+
+/// executes the statements of `sql` one at a time, calling `on_statement` with the index and count of statements before each one,
+/// so failures can name the statement and line they happened on. the files of `\\copy` lines are relative to `directory`
+pub(crate) fn execute_statements(
+	client: &mut impl postgres::GenericClient,
+	sql: &str,
+	directory: &Path,
+	mut on_statement: impl FnMut(usize, usize, &Statement),
+) -> Result<()> {
+	let statements = split_statements(sql)?;
+	for (index, statement) in statements.iter().enumerate() {
+		on_statement(index, statements.len(), statement);
+		let file = match &statement.copy {
+			Some(CopyData::File(file)) => {
+				let path = directory.join(file);
+				Some(fs::File::open(&path).with_context(|| format!("statement {} of {}, on line {}, copies from {}, which can't be read", index + 1, statements.len(), statement.line, path.display()))?)
+			},
+			_ => None,
+		};
+		execute_statement(client, statement, file).map_err(|err| {
+			let message = format!(
+				"statement {} of {}, on line {}, failed: {}\n\n{}",
+				index + 1, statements.len(), error_line(statement, &err), err.as_db_error().map_or(err.to_string(), ToString::to_string), statement.sql,
+			);
+			// kept as the cause when the connection was lost, so `migrate` knows it can reconnect and try again
+			match connections::is_connection_lost(&err) {
+				true => anyhow::Error::new(err).context(message),
+				false => anyhow!(message),
+			}
+		})?;
+	}
+	Ok(())
+}
+
+/// runs the sql of `sql_file` with `batch_execute`, unless it copies rows, which are then streamed statement by statement,
+/// or has the no-transaction directive, since `batch_execute` runs its statements in one implicit transaction.
+/// the files of `\\copy` lines are relative to the directory of `sql_file`
+pub(crate) fn batch_execute(client: &mut impl postgres::GenericClient, sql: &str, sql_file: &Path) -> Result<()> {
+	let one_at_a_time = structure::runs_without_transaction(sql)
+		|| split_statements(sql).is_ok_and(|statements| statements.iter().any(|statement| statement.copy.is_some()));
+	match one_at_a_time {
+		true => execute_statements(client, sql, sql_file.parent().unwrap_or(Path::new("")), |_, _, _| {}),
+		false => Ok(client.batch_execute(sql)?),
+	}
+}
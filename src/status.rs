@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use anyhow::Result;
+use postgres_migrator::{migration_description, read_sql_file};
+use regex::Regex;
+use serde_json::json;
+
+use super::{Args, MigrationFile, connections, create_versions_table, gather_validated_migrations, is_pending, query_actual_version, query_applied_versions, table_exists};
+
+/// the `-- key: value` lines of the comment block a migration starts with, such as
+/// `-- author: jane`, `-- ticket: PROJ-123` or `-- risk: high`, with keys lowercased.
+/// `-- phase:` markers aren't metadata
+fn parse_metadata(sql: &str) -> BTreeMap<String, String> {
+	let field = Regex::new(r"^--\s*([A-Za-z][\w-]*)\s*:\s*(.*?)\s*$").unwrap();
+	sql.lines()
+		.map(str::trim)
+		.take_while(|line| line.is_empty() || line.starts_with("--"))
+		.filter_map(|line| field.captures(line))
+		.map(|captures| (captures[1].to_lowercase(), captures[2].to_string()))
+		.filter(|(key, value)| key != "phase" && !value.is_empty())
+		.collect()
+}
+
+#[test]
+fn test_parse_metadata() {
+	assert_eq!(
+		parse_metadata("-- Author: Jane Doe\n-- ticket: PROJ-123 \n--risk:high\n-- renames fruit.name\n\ncreate table fruit ();\n-- owner: nobody\n"),
+		BTreeMap::from([
+			("author".to_string(), "Jane Doe".to_string()),
+			("ticket".to_string(), "PROJ-123".to_string()),
+			("risk".to_string(), "high".to_string()),
+		]),
+	);
+	assert_eq!(parse_metadata("-- phase: start\nalter table fruit add title text;"), BTreeMap::new());
+	assert_eq!(parse_metadata("create table fruit ();"), BTreeMap::new());
+}
+
+fn format_metadata(metadata: &BTreeMap<String, String>) -> String {
+	metadata.iter().map(|(key, value)| format!("{key}={value}")).collect::<Vec<_>>().join(" ")
+}
+
+fn migration_metadata(migration_file: &MigrationFile) -> Result<BTreeMap<String, String>> {
+	Ok(parse_metadata(&read_sql_file(&migration_file.file_path)?))
+}
+
+#[test]
+fn test_status_failures() -> Result<()> {
+	use std::fs;
+	let root = std::env::temp_dir().join("postgres_migrator_test_status_failures");
+	let _ = fs::remove_dir_all(&root);
+	fs::create_dir_all(&root)?;
+	let args = super::test_args(&["--pg-url", "postgres://localhost:1/app", "--migrations-directory", root.to_str().unwrap(), "status"]);
+
+	// the database isn't reachable
+	fs::write(root.join("20220101000000.null.create_fruit.sql"), "-- author: jane\ncreate table fruit ();")?;
+	assert!(command_status(&args, false).is_err());
+	assert!(command_history(&args, true).is_err());
+	let (migration_files, _) = gather_validated_migrations(&args)?;
+
+	// the chain is checked before connecting
+	fs::write(root.join("20220103000000.20220102000000.add_color.sql"), "alter table fruit add color text;")?;
+	let misaligned = format!("misaligned versions in {}: expected 20220101000000, got 20220102000000", root.join("20220103000000.20220102000000.add_color.sql").display());
+	assert_eq!(command_status(&args, true).unwrap_err().to_string(), misaligned);
+	assert_eq!(command_history(&args, false).unwrap_err().to_string(), misaligned);
+
+	fs::remove_dir_all(&root)?;
+	assert!(migration_metadata(&migration_files[0]).is_err());
+	Ok(())
+}
+
+/// lists every migration with whether it has been applied to the database, and the metadata in its header
+pub(crate) fn command_status(args: &Args, as_json: bool) -> Result<()> {
+	let (migration_files, _) = gather_validated_migrations(args)?;
+	let actual_version = connections::with_client(args.read_config(), query_actual_version)?;
+
+	let mut migrations = vec![];
+	for migration_file in &migration_files {
+		let state = match is_pending(&migration_file.current_version, &actual_version) {
+			true => "pending",
+			false => "applied",
+		};
+		migrations.push((migration_file, state, migration_metadata(migration_file)?));
+	}
+
+	if as_json {
+		let migrations: Vec<_> = migrations.iter().map(|(migration_file, state, metadata)| json!({
+			"version": migration_file.current_version,
+			"description": migration_description(&migration_file.file_path),
+			"file": migration_file.display_file_path,
+			"state": state,
+			"metadata": metadata,
+		})).collect();
+		let pending = migrations.iter().filter(|migration| migration["state"] == "pending").count();
+		println!("{}", serde_json::to_string_pretty(&json!({ "current_version": actual_version, "pending": pending, "migrations": migrations }))?);
+		return Ok(());
+	}
+
+	println!("database is at {}", actual_version.as_deref().unwrap_or("no version"));
+	for (migration_file, state, metadata) in &migrations {
+		println!("{}", format!("{state:<8} {} {}", migration_file.display_file_path, format_metadata(metadata)).trim_end());
+	}
+	Ok(())
+}
+
+/// lists the migrations applied to the database in order, with the metadata of the ones that still have a migration file,
+/// and when they were applied if `migrate --store-sql` or `migrate --status-view` recorded it
+pub(crate) fn command_history(args: &Args, as_json: bool) -> Result<()> {
+	let (migration_files, _) = gather_validated_migrations(args)?;
+	let (applied_versions, applied_at) = connections::with_client(args.read_config(), |client| {
+		let applied_versions = query_applied_versions(client)?;
+		let mut applied_at = BTreeMap::new();
+		if table_exists(client, "_schema_versions_sql")? {
+			let query = "select current_version, to_char(applied_at at time zone 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"') as applied_at from _schema_versions_sql";
+			for row in client.query(query, &[])? {
+				applied_at.insert(row.get::<_, String>("current_version"), row.get::<_, String>("applied_at"));
+			}
+		}
+		if status_view_exists(client)? {
+			let query = "select current_version, to_char(applied_at at time zone 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"') as applied_at from _schema_versions where applied_at is not null";
+			for row in client.query(query, &[])? {
+				applied_at.entry(row.get::<_, String>("current_version")).or_insert(row.get::<_, String>("applied_at"));
+			}
+		}
+		Ok((applied_versions, applied_at))
+	})?;
+
+	let mut history = vec![];
+	for version in &applied_versions {
+		let migration_file = migration_files.iter().find(|migration_file| &migration_file.current_version == version);
+		let metadata = match migration_file {
+			Some(migration_file) => migration_metadata(migration_file)?,
+			None => BTreeMap::new(),
+		};
+		history.push((version, migration_file, applied_at.get(version), metadata));
+	}
+
+	if as_json {
+		let history: Vec<_> = history.iter().map(|(version, migration_file, applied_at, metadata)| json!({
+			"version": version,
+			"description": migration_file.map(|migration_file| migration_description(&migration_file.file_path)),
+			"file": migration_file.map(|migration_file| &migration_file.display_file_path),
+			"applied_at": applied_at,
+			"metadata": metadata,
+		})).collect();
+		println!("{}", serde_json::to_string_pretty(&history)?);
+		return Ok(());
+	}
+
+	if history.is_empty() {
+		println!("no migrations have been applied");
+	}
+	for (version, migration_file, applied_at, metadata) in &history {
+		let file = migration_file.map_or("(no migration file)", |migration_file| migration_file.display_file_path.as_str());
+		println!("{}", format!("{version} {:<20} {file} {}", applied_at.map_or("", String::as_str), format_metadata(metadata)).trim_end());
+	}
+	Ok(())
+}
+
+/// the `_schema_status` view, along with when each version was applied and the versions of the migrations directory
+/// as of the latest `migrate`, which is what it reads from. the view's definition doesn't depend on the migrations,
+/// so temp databases can mirror it without it showing up in diffs
+pub(crate) const STATUS_VIEW_SQL: &str = "
+	alter table _schema_versions add column if not exists applied_at timestamptz;
+	alter table _schema_versions alter column applied_at set default now();
+	create table if not exists _schema_known_versions (version char(14) primary key);
+	create or replace view _schema_status as
+	select
+		(select max(current_version) from _schema_versions) as current_version,
+		(select count(*) from _schema_known_versions where version > (select coalesce(max(current_version), '') from _schema_versions)) as pending,
+		array(select current_version from _schema_versions where current_version not in (select version from _schema_known_versions) order by 1) as unknown_versions,
+		(select max(applied_at) from _schema_versions) as last_applied_at;
+";
+
+fn status_view_exists(client: &mut impl postgres::GenericClient) -> Result<bool> {
+	Ok(client.query_one("select to_regclass('_schema_status') is not null as status_view_exists", &[])?.get("status_view_exists"))
+}
+
+/// the bookkeeping sql mirroring the status view, if the database has it
+pub(crate) fn query_status_bookkeeping_sql(client: &mut impl postgres::GenericClient) -> Result<&'static str> {
+	Ok(if status_view_exists(client)? { STATUS_VIEW_SQL } else { "" })
+}
+
+/// creates the status view if `create` is set, and, if the database has it, records the versions of `migration_files`
+/// as the ones the view counts pending migrations from and tells unknown versions apart with
+pub(crate) fn refresh_status_view(client: &mut postgres::Client, migration_files: &[MigrationFile], create: bool) -> Result<()> {
+	let mut transaction = client.transaction()?;
+	if !create && !status_view_exists(&mut transaction)? {
+		return Ok(());
+	}
+	create_versions_table(&mut transaction)?;
+	transaction.batch_execute(STATUS_VIEW_SQL)?;
+	let versions: Vec<&str> = migration_files.iter().map(|migration_file| migration_file.current_version.as_str()).collect();
+	transaction.execute("delete from _schema_known_versions", &[])?;
+	transaction.execute("insert into _schema_known_versions select unnest($1::text[])", &[&versions])?;
+	transaction.commit()?;
+	Ok(())
+}
@@ -0,0 +1,89 @@
+//! Checks that each migration can be applied the way `migrate` applies it.
+
+use regex::Regex;
+
+use super::statements::split_statements;
+
+/// the line that makes `migrate` apply a migration statement by statement outside of a transaction,
+/// recording its version once they've all succeeded
+pub(crate) const NO_TRANSACTION_DIRECTIVE: &str = "-- no-transaction";
+
+/// whether the comment block `sql` starts with has the no-transaction directive
+pub(crate) fn runs_without_transaction(sql: &str) -> bool {
+	let directive = Regex::new(r"(?i)^--\s*no-transaction\s*$").unwrap();
+	sql.lines()
+		.map(str::trim)
+		.take_while(|line| line.is_empty() || line.starts_with("--"))
+		.any(|line| directive.is_match(line))
+}
+
+/// what a statement that postgres refuses to run inside a transaction block does
+fn transaction_incompatibility(statement_sql: &str) -> Option<&'static str> {
+	let incompatibilities = [
+		(r"^create\s+(unique\s+)?index\s+concurrently\b", "create index concurrently"),
+		(r"^drop\s+index\s+concurrently\b", "drop index concurrently"),
+		(r"^reindex\b.*\bconcurrently\b", "reindex concurrently"),
+		(r"^reindex\s+(\(.*?\)\s*)?(database|system)\b", "reindex database"),
+		(r"^alter\s+table\b.*\bdetach\s+partition\b.*\bconcurrently\b", "detach partition concurrently"),
+		(r"^vacuum\b", "vacuum"),
+		(r"^(create|drop)\s+database\b", "create or drop database"),
+		(r"^(create|drop)\s+tablespace\b", "create or drop tablespace"),
+		(r"^alter\s+system\b", "alter system"),
+		(r"^create\s+subscription\b", "create subscription"),
+	];
+	incompatibilities.into_iter()
+		.find(|(pattern, _)| Regex::new(&format!("(?is){pattern}")).unwrap().is_match(statement_sql))
+		.map(|(_, description)| description)
+}
+
+/// whether any statement of `sql` is one postgres refuses to run inside a transaction block
+pub(crate) fn needs_no_transaction(sql: &str) -> bool {
+	split_statements(sql).is_ok_and(|statements| statements.iter().any(|statement| transaction_incompatibility(&statement.sql).is_some()))
+}
+
+/// the structural problems of the sql of a single migration, each naming the line it's on, which the lints check
+pub(crate) fn structure_problems(sql: &str) -> Vec<String> {
+	let statements = match split_statements(sql) {
+		Ok(statements) => statements,
+		Err(err) => return vec![err.to_string()],
+	};
+	let meta_command = Regex::new(r"^\\[A-Za-z!?]\S*").unwrap();
+	let without_transaction = runs_without_transaction(sql);
+
+	let mut problems = vec![];
+	for statement in &statements {
+		for (offset, line) in statement.sql.lines().enumerate() {
+			if let Some(found) = meta_command.find(line.trim_start()) {
+				problems.push(format!("line {}: {} is a psql meta-command, which only psql understands", statement.line + offset, found.as_str()));
+			}
+		}
+		if let (Some(description), false) = (transaction_incompatibility(&statement.sql), without_transaction) {
+			problems.push(format!(
+				"line {}: {description} can't run inside a transaction, add a `{NO_TRANSACTION_DIRECTIVE}` line to the top of the migration",
+				statement.line,
+			));
+		}
+	}
+	problems
+}
+
+#[test]
+fn test_structure_problems() {
+	assert_eq!(structure_problems("create table fruit ();\ncreate index fruit_name on fruit (name);"), Vec::<String>::new());
+	assert_eq!(structure_problems("-- no-transaction\ncreate index concurrently fruit_name on fruit (name);"), Vec::<String>::new());
+	assert_eq!(
+		structure_problems("create table fruit ();\n\nCREATE UNIQUE INDEX CONCURRENTLY fruit_name on fruit (name);"),
+		vec!["line 3: create index concurrently can't run inside a transaction, add a `-- no-transaction` line to the top of the migration"],
+	);
+	assert_eq!(
+		structure_problems("\\set ON_ERROR_STOP on\ncreate table fruit ();\nselect 1\n\\gset"),
+		vec!["line 1: \\set is a psql meta-command, which only psql understands", "line 4: \\gset is a psql meta-command, which only psql understands"],
+	);
+	assert_eq!(
+		structure_problems("create function f() returns int as $$ select 1;"),
+		vec!["unterminated dollar quote $$ in statement starting on line 1"],
+	);
+	// only the comment block at the top counts, and copies are fine
+	assert_eq!(structure_problems("create table fruit ();\n-- no-transaction\nvacuum fruit;").len(), 1);
+	assert_eq!(structure_problems("copy fruit (name) from stdin;\napple\n\\.\n"), Vec::<String>::new());
+}
@@ -0,0 +1,87 @@
+//! Variables of each environment, from `vars.<env>.toml`, substituted for `${name}` in the schema and migrations.
+
+use std::{collections::BTreeMap, path::{Path, PathBuf}, sync::OnceLock};
+use anyhow::{anyhow, Context, Result};
+use regex::{Captures, Regex};
+
+use super::{read_sql_file, settings};
+
+/// the variables of the environment given with `--env`, set once before any command runs
+static VARIABLES: OnceLock<(String, BTreeMap<String, String>)> = OnceLock::new();
+
+fn variables_file_name(env: &str) -> String {
+	format!("vars.{env}.toml")
+}
+
+fn variable_pattern() -> Regex {
+	Regex::new(r"\$\{([A-Za-z_][\w.]*)\}").unwrap()
+}
+
+/// reads the variables of `env`, which every file applied afterwards is rendered with
+pub(crate) fn load(env: &str) -> Result<()> {
+	if env.is_empty() || !env.chars().all(|character| character.is_alphanumeric() || character == '_' || character == '-') {
+		return Err(anyhow!("invalid environment {env:?}, use letters, digits, _ and -"));
+	}
+	let file_name = variables_file_name(env);
+	if !Path::new(&file_name).exists() {
+		return Err(anyhow!("there's no {file_name} in the current directory for --env {env}"));
+	}
+	let variables = settings::parse_texts(&settings::read_file(Path::new(&file_name))?)
+		.with_context(|| format!("invalid {file_name}"))?;
+	VARIABLES.set((env.to_string(), variables)).map_err(|_| anyhow!("the variables of an environment were already loaded"))
+}
+
+/// the variables `sql` uses that `variables` doesn't set, in the order they first appear
+fn unset_variables(sql: &str, variables: &BTreeMap<String, String>) -> Vec<String> {
+	let mut unset: Vec<String> = vec![];
+	for captures in variable_pattern().captures_iter(sql) {
+		let name = &captures[1];
+		if !variables.contains_key(name) && !unset.iter().any(|unset_name| unset_name == name) {
+			unset.push(name.to_string());
+		}
+	}
+	unset
+}
+
+fn substitute(sql: &str, variables: &BTreeMap<String, String>) -> String {
+	variable_pattern().replace_all(sql, |captures: &Captures| variables[&captures[1]].clone()).into_owned()
+}
+
+#[test]
+fn test_substitute() {
+	let variables = BTreeMap::from([
+		("app_role".to_string(), "app_prod".to_string()),
+		("roles.reader".to_string(), "analyst".to_string()),
+	]);
+	let sql = "grant select on fruit to ${app_role}, ${roles.reader};\nselect '{{1,2}}'::int[], $$ ${app_role} $$;";
+	assert_eq!(unset_variables(sql, &variables), Vec::<String>::new());
+	assert_eq!(substitute(sql, &variables), "grant select on fruit to app_prod, analyst;\nselect '{{1,2}}'::int[], $$ app_prod $$;");
+	assert_eq!(unset_variables("alter role ${owner} set work_mem = '${work_mem}'; grant ${owner} to ${app_role};", &variables), vec!["owner", "work_mem"]);
+}
+
+/// `sql` with the variables of `--env` substituted, or as it is without `--env`
+pub(crate) fn render(sql: &str) -> Result<String> {
+	let Some((env, variables)) = VARIABLES.get() else { return Ok(sql.to_string()) };
+	let unset = unset_variables(sql, variables);
+	if !unset.is_empty() {
+		return Err(anyhow!("{} isn't set in {}", unset.join(", "), variables_file_name(env)));
+	}
+	Ok(substitute(sql, variables))
+}
+
+/// fails listing every variable that `sql_files` use and `--env` doesn't set, so none of them is applied
+/// when one of the later ones can't be
+pub(crate) fn ensure_variables_set(sql_files: &[PathBuf]) -> Result<()> {
+	let Some((env, variables)) = VARIABLES.get() else { return Ok(()) };
+	let mut problems = vec![];
+	for sql_file in sql_files {
+		let unset = unset_variables(&read_sql_file(sql_file)?, variables);
+		if !unset.is_empty() {
+			problems.push(format!("{}: {}", sql_file.display(), unset.join(", ")));
+		}
+	}
+	match problems.is_empty() {
+		true => Ok(()),
+		false => Err(anyhow!("these variables aren't set in {}:\n{}", variables_file_name(env), problems.join("\n"))),
+	}
+}
@@ -0,0 +1,144 @@
+//! Builds the schema directory and applies the migrations on throwaway docker servers of several postgres versions.
+
+use std::{path::PathBuf, process::Command, time::{Duration, Instant}};
+use anyhow::{anyhow, Context, Result};
+use postgres::Config;
+
+use super::{Args, connections, gather_validated_migrations, list_sql_files, read_sql_to_apply, statements};
+
+/// how long a server gets to accept connections after its container started
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct VerifyOptions {
+	/// the major versions of postgres to verify against, separated by commas
+	#[clap(long, required = true, use_value_delimiter = true)]
+	pg_versions: Vec<u32>,
+	/// the docker image the servers are started from, tagged with each version
+	#[clap(long, default_value = "postgres")]
+	image: String,
+}
+
+/// a postgres server in a docker container, which is removed when this is dropped
+struct DockerServer {
+	container_id: String,
+	config: Config,
+}
+
+fn docker(arguments: &[&str]) -> Result<String> {
+	let output = Command::new("docker").args(arguments).output()
+		.context("unable to run docker, is it installed?")?;
+	if !output.status.success() {
+		return Err(anyhow!("docker {} failed: {}", arguments.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+	}
+	Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// the port of `docker port` output like `127.0.0.1:49153`, which has a line for each address the port is published on
+fn parse_published_port(output: &str) -> Option<u16> {
+	output.lines().next()?.rsplit_once(':')?.1.trim().parse().ok()
+}
+
+#[test]
+fn test_parse_published_port() {
+	assert_eq!(parse_published_port("127.0.0.1:49153"), Some(49153));
+	assert_eq!(parse_published_port("127.0.0.1:49153\n[::1]:49153\n"), Some(49153));
+	assert_eq!(parse_published_port(""), None);
+}
+
+impl DockerServer {
+	fn start(image: &str, version: u32) -> Result<DockerServer> {
+		let image = format!("{image}:{version}");
+		// only published on localhost, so trusting every connection is fine
+		let container_id = docker(&["run", "--detach", "--rm", "--publish", "127.0.0.1::5432", "--env", "POSTGRES_HOST_AUTH_METHOD=trust", &image])?;
+		let mut server = DockerServer{ container_id, config: Config::new() };
+		let port = parse_published_port(&docker(&["port", &server.container_id, "5432/tcp"])?)
+			.ok_or_else(|| anyhow!("unable to find the port {image} was published on"))?;
+
+		server.config.host("127.0.0.1").port(port).user("postgres").dbname("postgres");
+		let started = Instant::now();
+		while let Err(error) = server.config.connect(postgres::NoTls) {
+			if started.elapsed() > STARTUP_TIMEOUT {
+				return Err(anyhow!("{image} didn't accept connections within {} seconds: {error}", STARTUP_TIMEOUT.as_secs()));
+			}
+			std::thread::sleep(Duration::from_millis(500));
+		}
+		Ok(server)
+	}
+
+	/// creates the database `dbname` and applies `sql_files` to it in order
+	fn apply(&self, dbname: &str, sql_files: Vec<PathBuf>) -> Result<()> {
+		let is_migrations = dbname == "migrations";
+		connections::with_client(&self.config, |client| Ok(client.batch_execute(&format!("create database {dbname}"))?))?;
+		let mut config = self.config.clone();
+		config.dbname(dbname);
+		let outcome = connections::with_client(&config, |client| {
+			for sql_file in sql_files {
+				statements::batch_execute(client, &read_sql_to_apply(&sql_file, is_migrations)?, &sql_file)
+					.with_context(|| format!("{} doesn't apply cleanly", sql_file.display()))?;
+			}
+			Ok(())
+		});
+		connections::close(&config);
+		outcome
+	}
+}
+
+impl Drop for DockerServer {
+	fn drop(&mut self) {
+		connections::close(&self.config);
+		if let Err(error) = docker(&["rm", "--force", &self.container_id]) {
+			eprintln!("warning: unable to remove container {}: {error}", self.container_id);
+		}
+	}
+}
+
+/// builds the schema directory and applies every migration, which includes the pending ones, on each version
+fn verify_version(args: &Args, image: &str, version: u32, migration_files: &[PathBuf]) -> Result<()> {
+	let server = DockerServer::start(image, version)?;
+	server.apply("schema", list_sql_files(&args.schema_directory)?).context("schema directory")?;
+	server.apply("migrations", migration_files.to_vec()).context("migrations")?;
+	Ok(())
+}
+
+pub(crate) fn command_verify(args: &Args, options: &VerifyOptions) -> Result<()> {
+	let migration_files: Vec<PathBuf> = gather_validated_migrations(args)?.0.into_iter()
+		.map(|migration_file| migration_file.file_path)
+		.collect();
+
+	let mut failed_versions = vec![];
+	for &version in &options.pg_versions {
+		match verify_version(args, &options.image, version, &migration_files) {
+			Ok(()) => println!("ok      postgres {version}"),
+			Err(error) => {
+				println!("failed  postgres {version}: {error:#}");
+				failed_versions.push(version.to_string());
+			},
+		}
+	}
+	match failed_versions.is_empty() {
+		true => Ok(()),
+		false => Err(anyhow!("verification failed on postgres {}", failed_versions.join(", "))),
+	}
+}
+
+#[test]
+fn test_verify_failures() -> Result<()> {
+	assert_eq!(parse_published_port("127.0.0.1:port"), None);
+	assert_eq!(parse_published_port("49153"), None);
+
+	let root = std::env::temp_dir().join("postgres_migrator_test_verify_failures");
+	let _ = std::fs::remove_dir_all(&root);
+	std::fs::create_dir_all(&root)?;
+	let args = super::test_args(&["--schema-directory", root.to_str().unwrap(), "--migrations-directory", root.to_str().unwrap(), "verify", "--pg-versions", "13"]);
+	// an image reference docker refuses without pulling anything, so every version fails
+	let options = VerifyOptions{ pg_versions: vec![13, 16], image: "Postgres_Migrator_Invalid".into() };
+	assert_eq!(command_verify(&args, &options).unwrap_err().to_string(), "verification failed on postgres 13, 16");
+
+	// the chain is checked before any server is started
+	std::fs::write(root.join("20220102000000.20220101000000.add_color.sql"), "alter table fruit add color text;")?;
+	assert!(command_verify(&args, &VerifyOptions{ pg_versions: vec![], ..options }).is_err());
+
+	std::fs::remove_dir_all(&root)?;
+	Ok(())
+}